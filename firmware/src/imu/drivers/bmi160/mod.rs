@@ -8,8 +8,10 @@ use crate::imu::{FusedData, Imu, UnfusedData};
 use crate::utils;
 
 use ::bmi160::{AccelerometerPowerMode, GyroscopePowerMode, SensorSelector};
+use core::time::Duration;
 use defmt::{debug, trace};
 use embassy_futures::yield_now;
+use embassy_time::Instant;
 use embedded_hal::blocking::delay::DelayMs;
 use firmware_protocol::ImuType;
 use nalgebra::vector;
@@ -18,6 +20,27 @@ type BmiDriver<I2c> = ::bmi160::Bmi160<bmi160::interface::I2cInterface<I2c>>;
 // Second generic is `()` because we don't have chip select errors in I2C.
 type BmiError<I> = ::bmi160::Error<<I as I2c>::Error, ()>;
 
+/// The SENSORTIME register is a 24 bit counter, which wraps every 655.36 s.
+const SENSORTIME_MASK: u32 = 0x00FF_FFFF;
+/// The duration of one tick of the SENSORTIME register is 39.0625 µs, which is 10 times
+/// this many nanoseconds.
+const SENSORTIME_TICK_NANOS_X10: u64 = 390_625;
+
+/// The registers that configure the output data rate and filters of the accelerometer
+/// and the gyroscope. The `bmi160` crate doesn't support them, so they are written
+/// directly.
+const ACC_CONF: u8 = 0x40;
+const GYR_CONF: u8 = 0x42;
+/// The output data rate of both the accelerometer and the gyroscope, in Hz. Without
+/// configuring it, the BMI160 samples at 100 Hz.
+const ODR_HZ: u32 = 400;
+/// The value of `ACC_CONF` and `GYR_CONF` for [`ODR_HZ`], with the normal filter mode.
+const ODR_CONF: u8 = 0b0010_1010;
+/// The SENSORTIME ticks between two samples, at 25.6 kHz. The data registers are
+/// updated whenever SENSORTIME passes a multiple of this.
+const SAMPLE_TICKS: u32 = 25_600 / ODR_HZ;
+const _: () = assert!(SAMPLE_TICKS.is_power_of_two());
+
 pub struct InitError<I: I2c> {
 	pub i2c: I,
 	pub error: BmiError<I>,
@@ -33,6 +56,11 @@ where
 
 pub struct Bmi160<I: I2c> {
 	driver: BmiDriver<I>,
+	/// The SENSORTIME of the previous sample, rounded down to [`SAMPLE_TICKS`].
+	last_sensortime: Option<u32>,
+	/// The time of the first sample, and the SENSORTIME ticks since then.
+	start: Duration,
+	ticks: u64,
 }
 impl<I: I2c> Bmi160<I> {
 	pub fn new(i2c: I, delay: &mut impl DelayMs<u32>) -> Result<Self, InitError<I>> {
@@ -57,6 +85,15 @@ impl<I: I2c> Bmi160<I> {
 				trace!("Flushing I2C with bogus data");
 				let _ = i2c.write(addr.addr(), &[0]);
 				delay.delay_ms(100);
+				trace!("Setting the output data rate to {} Hz", ODR_HZ);
+				for reg in [ACC_CONF, GYR_CONF] {
+					if let Err(err) = i2c.write(addr.addr(), &[reg, ODR_CONF]) {
+						return Err((i2c, ::bmi160::Error::Comm(err)));
+					}
+					// The sensors are still suspended, which needs a pause between
+					// writes.
+					delay.delay_ms(1);
+				}
 				trace!("Constructing IMU");
 				let mut driver = BmiDriver::new_with_i2c(i2c, addr);
 				let id = unwrap_or_err!(driver, driver.chip_id());
@@ -71,13 +108,36 @@ impl<I: I2c> Bmi160<I> {
 				);
 				debug!("BMI power mode set to Normal");
 				delay.delay_ms(100);
-				Ok(Self { driver })
+				Ok(Self {
+					driver,
+					last_sensortime: None,
+					start: Duration::ZERO,
+					ticks: 0,
+				})
 			},
 			|i| debug!("Retrying IMU connection (attempts so far: {})", i + 1),
 		)
 		// Map converts from tuple -> struct
 		.map_err(|(i2c, error)| InitError { i2c, error })
 	}
+
+	/// Turns the SENSORTIME of a sample into a timestamp, in the same clock as
+	/// `Instant` at the first sample. Returns `None` if it is the same sample as the
+	/// previous one.
+	fn timestamp(&mut self, sensortime: u32) -> Option<Duration> {
+		if self.last_sensortime == Some(sensortime) {
+			return None;
+		}
+		match self.last_sensortime.replace(sensortime) {
+			Some(last) => {
+				let ticks = sensortime.wrapping_sub(last) & SENSORTIME_MASK;
+				self.ticks += u64::from(ticks);
+			}
+			None => self.start = Duration::from_micros(Instant::now().as_micros()),
+		}
+		let ticks = Duration::from_nanos(self.ticks * SENSORTIME_TICK_NANOS_X10 / 10);
+		Some(self.start + ticks)
+	}
 }
 
 impl<I: I2c> Imu for Bmi160<I> {
@@ -87,10 +147,28 @@ impl<I: I2c> Imu for Bmi160<I> {
 	const IMU_TYPE: ImuType = ImuType::Bmi160;
 
 	async fn next_data(&mut self) -> Result<Self::Data, Self::Error> {
-		// Avoids permablocking async tasks, since we don't do any actual waiting.
-		yield_now().await;
-
-		let data = self.driver.data(SensorSelector::new().gyro().accel())?;
+		let (data, timestamp) = loop {
+			// The data ready flags are cleared when the data is read, so this waits for
+			// a new sample.
+			let status = self.driver.status()?;
+			if !(status.accel_data_ready && status.gyro_data_ready) {
+				// Avoids permablocking async tasks, since we don't do any actual waiting.
+				yield_now().await;
+				continue;
+			}
+			// SENSORTIME is a free-running counter, which is latched when the data is
+			// read. So it is the time of the read, and includes the delay since the
+			// sample was taken. But the samples are taken in sync with it, so rounding
+			// it down to the last sample gives the time of the sample itself.
+			let data = self
+				.driver
+				.data(SensorSelector::new().gyro().accel().time())?;
+			let sensortime = data.time.unwrap() & !(SAMPLE_TICKS - 1);
+			if let Some(timestamp) = self.timestamp(sensortime) {
+				break (data, timestamp);
+			}
+			trace!("Skipping a sample that was already read");
+		};
 		let gyro = data.gyro.unwrap();
 		let accel = data.accel.unwrap();
 
@@ -110,7 +188,11 @@ impl<I: I2c> Imu for Bmi160<I> {
 		let gyro = vector![g(gyro.x), g(gyro.y), g(gyro.z)];
 		let accel = vector![a(accel.x), a(accel.y), a(accel.z)];
 
-		Ok(UnfusedData { accel, gyro })
+		Ok(UnfusedData {
+			accel,
+			gyro,
+			timestamp,
+		})
 	}
 }
 
@@ -121,7 +203,7 @@ pub fn new_imu(
 ) -> impl Imu<Data = FusedData> {
 	let bmi = Bmi160::new(i2c, delay).expect("Failed to initialize BMI160");
 	FusedImu {
-		fuser: new_fuser(),
+		fuser: new_fuser(1. / ODR_HZ as f32),
 		imu: bmi,
 	}
 }
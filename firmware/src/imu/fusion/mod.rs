@@ -1,21 +1,12 @@
-mod dcm;
-mod stubbed;
+//! The fusion algorithms themselves live in the [`imu_fusion`] crate, so that they can
+//! also be benchmarked on the host.
 
-pub use self::dcm::Dcm;
-pub use self::stubbed::Stubbed;
+pub use imu_fusion::Fuser;
 
 use crate::imu::{FusedData, Imu, UnfusedData};
 
 use firmware_protocol::ImuType;
 
-/// Represents a sensor fusion algorithm that will take an imu's `UnfusedData` and
-/// do math to turn it into `FusedData`, suitable for use as orientation.
-pub trait Fuser {
-	// Note: Intentionally not async, this should only be doing math, not io or
-	// any internal awaiting.
-	fn process(&mut self, unfused: &UnfusedData) -> FusedData;
-}
-
 /// Combines an unfused `Imu` with a `Fuser`.
 pub struct FusedImu<I: Imu, F: Fuser> {
 	pub imu: I,
@@ -33,12 +24,16 @@ impl<I: Imu<Data = UnfusedData>, F: Fuser> Imu for FusedImu<I, F> {
 	}
 }
 
-/// Builds a new fuser. The concrete impl is determined by a feature flag.
-pub fn new_fuser() -> impl Fuser {
+/// Builds a new fuser, for an imu whose samples are `ts` seconds apart. The concrete
+/// impl is determined by a feature flag.
+#[allow(unused_variables)]
+pub fn new_fuser(ts: f32) -> impl Fuser {
 	#[cfg(feature = "fusion-stubbed")]
-	let f = Stubbed::new();
+	let f = imu_fusion::Stubbed::new();
 	#[cfg(feature = "fusion-dcm")]
-	let f = Dcm::new();
+	let f = imu_fusion::Dcm::new();
+	#[cfg(feature = "fusion-vqf")]
+	let f = imu_fusion::Vqf::with_sample_time(ts);
 
 	f
 }
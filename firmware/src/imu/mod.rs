@@ -10,18 +10,7 @@ use crate::{
 	utils::Unreliable,
 };
 
-pub type Quat = nalgebra::UnitQuaternion<f32>;
-pub type Accel = nalgebra::Vector3<f32>;
-pub type Gyro = nalgebra::Vector3<f32>;
-
-pub struct UnfusedData {
-	pub accel: Accel,
-	pub gyro: Gyro,
-}
-
-pub struct FusedData {
-	pub q: Quat,
-}
+pub use imu_fusion::{FusedData, Quat, UnfusedData};
 
 pub trait Imu {
 	type Error: core::fmt::Debug; // TODO: Maybe use defmt instead?
@@ -0,0 +1,347 @@
+//! Runs the [`imu_fusion`] algorithms over recorded imu datasets, and measures how well
+//! they do against the ground truth orientation.
+//!
+//! # Dataset format
+//! A dataset is a CSV file with a header row, and one row per sample. Lines starting
+//! with `#` are comments. These columns are needed, in any order:
+//! - `time`: The timestamp of the sample, in seconds. It has to increase from one
+//!   sample to the next.
+//! - `gyr_x`, `gyr_y`, `gyr_z`: The angular velocity, in rad/s.
+//! - `acc_x`, `acc_y`, `acc_z`: The acceleration, in m/s².
+//! - `quat_w`, `quat_x`, `quat_y`, `quat_z`: The ground truth orientation of the
+//!   sensor, in a frame where +Z is up.
+//!
+//! Every value has to be a finite number.
+//!
+//! Public benchmark sets such as [BROAD] can be converted to this format.
+//!
+//! [BROAD]: https://github.com/dlaidig/broad
+
+use imu_fusion::{Fuser, Quat, UnfusedData};
+use nalgebra::{Quaternion, Vector3};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use std::f32::consts::PI;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// A single sample of a dataset.
+#[derive(Debug, Clone)]
+pub struct Sample {
+	/// In seconds.
+	pub time: f32,
+	/// In rad/s.
+	pub gyro: Vector3<f32>,
+	/// In m/s².
+	pub accel: Vector3<f32>,
+	/// The ground truth orientation.
+	pub truth: Quat,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dataset {
+	pub name: String,
+	pub samples: Vec<Sample>,
+}
+impl Dataset {
+	/// Loads a dataset from a CSV file, in the format described in the [crate] docs.
+	pub fn load(path: &Path) -> Result<Self> {
+		let text = std::fs::read_to_string(path)
+			.wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+		let name = path.file_stem().map_or_else(
+			|| path.display().to_string(),
+			|s| s.to_string_lossy().into(),
+		);
+		Self::parse(name, &text)
+			.wrap_err_with(|| format!("Invalid dataset {}", path.display()))
+	}
+
+	/// Parses a dataset from the contents of a CSV file.
+	pub fn parse(name: String, text: &str) -> Result<Self> {
+		let mut lines = text
+			.lines()
+			.enumerate()
+			.filter(|(_, l)| !l.starts_with('#') && !l.trim().is_empty());
+		let (_, header) = lines.next().ok_or_else(|| eyre!("Missing header"))?;
+		let header: Vec<_> = header.split(',').map(str::trim).collect();
+		let column = |name: &str| {
+			header
+				.iter()
+				.position(|c| *c == name)
+				.ok_or_else(|| eyre!("Missing column {name}"))
+		};
+		let vec3 = |prefix: &str| -> Result<[usize; 3]> {
+			Ok([
+				column(&format!("{prefix}_x"))?,
+				column(&format!("{prefix}_y"))?,
+				column(&format!("{prefix}_z"))?,
+			])
+		};
+		let time = column("time")?;
+		let gyr = vec3("gyr")?;
+		let acc = vec3("acc")?;
+		let quat_w = column("quat_w")?;
+		let quat = vec3("quat")?;
+
+		let mut samples = Vec::new();
+		for (line_idx, line) in lines {
+			let row = line
+				.split(',')
+				.map(|v| v.trim().parse::<f32>())
+				.collect::<Result<Vec<_>, _>>()
+				.wrap_err_with(|| format!("Invalid number on line {}", line_idx + 1))?;
+			if row.len() != header.len() {
+				bail!("Wrong number of columns on line {}", line_idx + 1);
+			}
+			let v =
+				|idx: [usize; 3]| Vector3::new(row[idx[0]], row[idx[1]], row[idx[2]]);
+			if row.iter().any(|v| !v.is_finite()) {
+				bail!("Non-finite number on line {}", line_idx + 1);
+			}
+			if row[time] < 0. {
+				bail!("Negative time on line {}", line_idx + 1);
+			}
+			if samples
+				.last()
+				.map_or(false, |s: &Sample| row[time] <= s.time)
+			{
+				bail!("Time does not increase on line {}", line_idx + 1);
+			}
+			samples.push(Sample {
+				time: row[time],
+				gyro: v(gyr),
+				accel: v(acc),
+				truth: Quat::from_quaternion(Quaternion::from_parts(
+					row[quat_w],
+					v(quat),
+				)),
+			});
+		}
+		Ok(Self { name, samples })
+	}
+
+	/// The mean time between samples, in seconds, or 10 ms with fewer than two samples.
+	pub fn sample_time(&self) -> f32 {
+		match (self.samples.first(), self.samples.last()) {
+			(Some(first), Some(last)) if self.samples.len() > 1 => {
+				(last.time - first.time) / (self.samples.len() - 1) as f32
+			}
+			_ => 0.01,
+		}
+	}
+
+	/// A deterministic dataset of a sensor that is turned around, then lies still, for
+	/// `duration` seconds at 100 Hz. The gyroscope has a bias and noise.
+	pub fn synthetic(duration: f32) -> Self {
+		const TS: f32 = 0.01;
+		const GRAVITY: f32 = 9.81;
+		let bias = Vector3::new(0.01, -0.02, 0.015);
+		// xorshift, so that the noise is the same every time.
+		let mut seed = 0x2545_f491_u32;
+		let mut noise = move |scale: f32| {
+			seed ^= seed << 13;
+			seed ^= seed >> 17;
+			seed ^= seed << 5;
+			(seed as f32 / u32::MAX as f32 - 0.5) * 2. * scale
+		};
+		let mut noise3 = move |scale| Vector3::from_fn(|_, _| noise(scale));
+
+		let mut truth = Quat::from_scaled_axis(Vector3::new(0.3, -0.2, 1.0));
+		let mut samples = Vec::new();
+		for i in 0..(duration / TS) as usize {
+			let time = i as f32 * TS;
+			let angular_vel = if time % 20. < 15. {
+				Vector3::new(
+					0.8 * (0.7 * time).sin(),
+					0.6 * (1.1 * time + 1.).sin(),
+					1.2 * (0.5 * time).cos(),
+				)
+			} else {
+				Vector3::zeros()
+			};
+			let gravity = truth.inverse() * Vector3::new(0., 0., GRAVITY);
+			samples.push(Sample {
+				time,
+				gyro: angular_vel + bias + noise3(0.005),
+				accel: gravity + noise3(0.05),
+				truth,
+			});
+			truth *= Quat::from_scaled_axis(angular_vel * TS);
+		}
+		Self {
+			name: String::from("synthetic"),
+			samples,
+		}
+	}
+}
+
+/// How well a fuser did on a dataset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+	/// The root mean square of the orientation error, in degrees. A constant heading
+	/// offset is not counted as an error, since the fusers can't know the heading of
+	/// the ground truth frame.
+	pub rmse: f32,
+	/// The root mean square of the inclination error, in degrees.
+	pub inclination_rmse: f32,
+	/// How fast the heading error grows, in degrees per minute.
+	pub heading_drift: f32,
+	/// The CPU time that the fuser took for each sample.
+	pub time_per_sample: Duration,
+}
+
+/// Runs `fuser` over every sample of `dataset`.
+pub fn run(fuser: &mut dyn Fuser, dataset: &Dataset) -> Report {
+	let mut estimates = Vec::with_capacity(dataset.samples.len());
+	let mut elapsed = Duration::ZERO;
+	for sample in &dataset.samples {
+		let unfused = UnfusedData {
+			accel: sample.accel,
+			gyro: sample.gyro,
+			timestamp: Duration::from_secs_f32(sample.time),
+		};
+		let start = Instant::now();
+		let fused = fuser.process(&unfused);
+		elapsed += start.elapsed();
+		estimates.push(fused.q);
+	}
+	let samples = dataset.samples.len().max(1) as u32;
+	Report {
+		time_per_sample: elapsed / samples,
+		..evaluate(&dataset.samples, &estimates)
+	}
+}
+
+/// Compares the `estimates` against the ground truth of the `samples`.
+pub fn evaluate(samples: &[Sample], estimates: &[Quat]) -> Report {
+	assert_eq!(samples.len(), estimates.len());
+	let n = samples.len().max(1) as f32;
+
+	// The error in the earth frame, and its heading part around the vertical axis.
+	let errors: Vec<Quat> = samples
+		.iter()
+		.zip(estimates)
+		.map(|(s, q)| q * s.truth.inverse())
+		.collect();
+	let mut headings = Vec::with_capacity(errors.len());
+	for error in &errors {
+		let heading = 2. * error.k.atan2(error.w);
+		let heading = match headings.last() {
+			// Unwrap, so that the heading can drift by more than a turn.
+			Some(last) => last + wrap_angle(heading - last),
+			None => wrap_angle(heading),
+		};
+		headings.push(heading);
+	}
+	let mean_heading = headings.iter().sum::<f32>() / n;
+	let offset = Quat::from_axis_angle(&Vector3::z_axis(), -mean_heading);
+
+	let rmse = errors
+		.iter()
+		.map(|e| (offset * e).angle().powi(2))
+		.sum::<f32>()
+		/ n;
+	let up = Vector3::z();
+	let inclination_rmse = samples
+		.iter()
+		.zip(estimates)
+		.map(|(s, q)| (q.inverse() * up).angle(&(s.truth.inverse() * up)).powi(2))
+		.sum::<f32>()
+		/ n;
+
+	// Least squares fit of the heading error over time.
+	let mean_time = samples.iter().map(|s| s.time).sum::<f32>() / n;
+	let (mut cov, mut var) = (0., 0.);
+	for (s, heading) in samples.iter().zip(&headings) {
+		cov += (s.time - mean_time) * (heading - mean_heading);
+		var += (s.time - mean_time).powi(2);
+	}
+	let heading_drift = if var > 0. { cov / var } else { 0. };
+
+	Report {
+		rmse: rmse.sqrt().to_degrees(),
+		inclination_rmse: inclination_rmse.sqrt().to_degrees(),
+		heading_drift: heading_drift.to_degrees() * 60.,
+		time_per_sample: Duration::ZERO,
+	}
+}
+
+/// Wraps an angle to `[-PI, PI]`.
+fn wrap_angle(angle: f32) -> f32 {
+	(angle + PI).rem_euclid(2. * PI) - PI
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use imu_fusion::{Stubbed, Vqf};
+
+	use approx::assert_relative_eq;
+
+	#[test]
+	fn test_parse() {
+		let text = "# A comment\n\
+			quat_w,quat_x,quat_y,quat_z,time,gyr_x,gyr_y,gyr_z,acc_x,acc_y,acc_z\n\
+			1,0,0,0,0,0.1,0.2,0.3,0,0,9.81\n\
+			0,0,0,1,0.01,0,0,0,0,0,9.81\n";
+		let dataset = Dataset::parse(String::from("test"), text).unwrap();
+		assert_eq!(dataset.samples.len(), 2);
+		assert_eq!(dataset.samples[0].gyro, Vector3::new(0.1, 0.2, 0.3));
+		assert_eq!(dataset.samples[1].time, 0.01);
+		assert_relative_eq!(
+			dataset.samples[1].truth,
+			Quat::from_axis_angle(&Vector3::z_axis(), PI)
+		);
+
+		assert!(Dataset::parse(String::from("test"), "time,gyr_x\n0,0\n").is_err());
+
+		let header =
+			"time,gyr_x,gyr_y,gyr_z,acc_x,acc_y,acc_z,quat_w,quat_x,quat_y,quat_z\n";
+		let parse = |rows: &str| {
+			Dataset::parse(String::from("test"), &(header.to_owned() + rows))
+		};
+		assert!(parse("0,0,0,0,0,0,9.81,1,0,0,0\n").is_ok());
+		assert!(parse("0,NaN,0,0,0,0,9.81,1,0,0,0\n").is_err());
+		assert!(parse("0,0,0,0,0,0,inf,1,0,0,0\n").is_err());
+		assert!(parse("inf,0,0,0,0,0,9.81,1,0,0,0\n").is_err());
+		// The time has to increase from one sample to the next.
+		let row = |time: &str| format!("{time},0,0,0,0,0,9.81,1,0,0,0\n");
+		let dataset = parse(&(row("0.01") + &row("0.02") + &row("0.04"))).unwrap();
+		assert_relative_eq!(dataset.sample_time(), 0.015);
+		assert!(parse(&(row("0.01") + &row("0.01"))).is_err());
+		assert!(parse(&(row("0.02") + &row("0.01"))).is_err());
+	}
+
+	#[test]
+	fn test_evaluate() {
+		let dataset = Dataset::synthetic(60.);
+		let truth: Vec<_> = dataset.samples.iter().map(|s| s.truth).collect();
+		let report = evaluate(&dataset.samples, &truth);
+		assert_relative_eq!(report.rmse, 0., epsilon = 1e-2);
+		assert_relative_eq!(report.heading_drift, 0., epsilon = 1e-2);
+
+		// A constant heading offset is not an error, but a growing one is drift.
+		let drifting: Vec<_> = dataset
+			.samples
+			.iter()
+			.map(|s| {
+				let heading = 1. + (s.time / 60.).to_radians();
+				Quat::from_axis_angle(&Vector3::z_axis(), heading) * s.truth
+			})
+			.collect();
+		let report = evaluate(&dataset.samples, &drifting);
+		assert_relative_eq!(report.heading_drift, 1., epsilon = 1e-2);
+		assert_relative_eq!(report.inclination_rmse, 0., epsilon = 1e-2);
+		assert!(report.rmse < 0.3);
+	}
+
+	#[test]
+	fn test_run() {
+		let dataset = Dataset::synthetic(60.);
+		let stubbed = run(&mut Stubbed::new(), &dataset);
+		let vqf = run(&mut Vqf::with_sample_time(dataset.sample_time()), &dataset);
+		assert!(vqf.inclination_rmse < 2.);
+		assert!(vqf.rmse < stubbed.rmse);
+		assert!(vqf.heading_drift.abs() < stubbed.heading_drift.abs());
+	}
+}
@@ -0,0 +1,58 @@
+use fusion_bench::{run, Dataset};
+use imu_fusion::{Dcm, Fuser, Stubbed, Vqf};
+
+use clap::Parser;
+use std::path::PathBuf;
+
+/// Makes a new instance of a fuser, for samples that are this many seconds apart.
+type NewFuser = fn(f32) -> Box<dyn Fuser>;
+
+/// Compares the sensor fusion algorithms on imu datasets with a ground truth.
+#[derive(Parser, Debug)]
+struct Args {
+	/// CSV files of the datasets. See the `fusion_bench` docs for the format.
+	datasets: Vec<PathBuf>,
+	/// Also runs on a generated dataset of this many seconds.
+	#[clap(long)]
+	synthetic: Option<f32>,
+}
+
+fn main() -> eyre::Result<()> {
+	let args = Args::parse();
+	let mut datasets = args
+		.datasets
+		.iter()
+		.map(|p| Dataset::load(p))
+		.collect::<eyre::Result<Vec<_>>>()?;
+	if let Some(duration) = args.synthetic {
+		datasets.push(Dataset::synthetic(duration));
+	}
+	if datasets.is_empty() {
+		eyre::bail!("No datasets given. Pass CSV files, or --synthetic <SECONDS>");
+	}
+
+	let fusers: [(&str, NewFuser); 3] = [
+		("stubbed", |_| Box::new(Stubbed::new())),
+		("dcm", |_| Box::new(Dcm::new())),
+		("vqf", |ts| Box::new(Vqf::with_sample_time(ts))),
+	];
+	println!(
+		"{:<20} {:<8} {:>10} {:>16} {:>16} {:>12}",
+		"dataset", "fuser", "rmse (°)", "incl rmse (°)", "drift (°/min)", "ns/sample"
+	);
+	for dataset in &datasets {
+		for (name, new_fuser) in fusers {
+			let report = run(new_fuser(dataset.sample_time()).as_mut(), dataset);
+			println!(
+				"{:<20} {:<8} {:>10.2} {:>16.2} {:>16.2} {:>12}",
+				dataset.name,
+				name,
+				report.rmse,
+				report.inclination_rmse,
+				report.heading_drift,
+				report.time_per_sample.as_nanos()
+			);
+		}
+	}
+	Ok(())
+}
@@ -1,33 +1,39 @@
+use core::time::Duration;
+
 use dcmimu::DCMIMU;
-use embassy_time::Instant;
 
-use crate::imu::fusion::Fuser;
-use crate::imu::{FusedData, Quat, UnfusedData};
+use crate::{seconds_between, FusedData, Fuser, Quat, UnfusedData};
 
 /// Extended Kalman filtering in direction cosine matrix formation
 pub struct Dcm {
 	dcm: DCMIMU,
-	last: Instant,
+	last: Option<Duration>,
 }
 
 impl Dcm {
-	#[allow(dead_code)]
 	pub fn new() -> Self {
 		Self {
 			dcm: DCMIMU::new(),
-			last: Instant::now(),
+			last: None,
 		}
 	}
 }
+impl Default for Dcm {
+	fn default() -> Self {
+		Self::new()
+	}
+}
 
 impl Fuser for Dcm {
 	fn process(&mut self, unfused: &UnfusedData) -> FusedData {
-		let last = self.last;
-		self.last = Instant::now();
-		let elapsed = self.last - last;
-		let elapsed = elapsed.as_micros() as f32 / 1_000_000.0;
+		let UnfusedData {
+			accel,
+			gyro,
+			timestamp,
+		} = unfused;
 
-		let UnfusedData { accel, gyro } = unfused;
+		let last = self.last.replace(*timestamp).unwrap_or(*timestamp);
+		let elapsed = seconds_between(last, *timestamp);
 
 		// TODO: Check that these euler angle convention matches
 		let (euler, _) = self.dcm.update(
@@ -0,0 +1,52 @@
+//! Sensor fusion algorithms, which turn the unfused readings of an imu into an
+//! orientation.
+//!
+//! Every algorithm implements [`Fuser`]. The crate is `no_std` so that the firmware can
+//! use it, but it also builds for the host, which is how the algorithms get
+//! benchmarked against each other.
+
+#![no_std]
+
+#[cfg(feature = "dcm")]
+mod dcm;
+mod stubbed;
+#[cfg(feature = "vqf")]
+mod vqf;
+
+#[cfg(feature = "dcm")]
+pub use self::dcm::Dcm;
+pub use self::stubbed::Stubbed;
+#[cfg(feature = "vqf")]
+pub use self::vqf::Vqf;
+
+use core::time::Duration;
+
+pub type Quat = nalgebra::UnitQuaternion<f32>;
+pub type Accel = nalgebra::Vector3<f32>;
+pub type Gyro = nalgebra::Vector3<f32>;
+
+pub struct UnfusedData {
+	/// In m/s².
+	pub accel: Accel,
+	/// In rad/s.
+	pub gyro: Gyro,
+	/// When the sample was read from the imu, relative to an arbitrary point in time.
+	pub timestamp: Duration,
+}
+
+pub struct FusedData {
+	pub q: Quat,
+}
+
+/// Represents a sensor fusion algorithm that will take an imu's `UnfusedData` and
+/// do math to turn it into `FusedData`, suitable for use as orientation.
+pub trait Fuser {
+	// Note: Intentionally not async, this should only be doing math, not io or
+	// any internal awaiting.
+	fn process(&mut self, unfused: &UnfusedData) -> FusedData;
+}
+
+/// The seconds between two timestamps, or zero if `now` is not after `last`.
+fn seconds_between(last: Duration, now: Duration) -> f32 {
+	now.checked_sub(last).unwrap_or_default().as_secs_f32()
+}
@@ -0,0 +1,26 @@
+use core::f32::consts::PI;
+use core::time::Duration;
+
+use crate::{seconds_between, FusedData, Fuser, Quat, UnfusedData};
+
+/// A fake fuser that just rotates around the x axis.
+#[derive(Default)]
+pub struct Stubbed {
+	start: Option<Duration>,
+}
+impl Stubbed {
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+impl Fuser for Stubbed {
+	fn process(&mut self, unfused: &UnfusedData) -> FusedData {
+		let start = *self.start.get_or_insert(unfused.timestamp);
+		let dt = seconds_between(start, unfused.timestamp);
+
+		const ROT_RATE: f32 = PI / 2.; // 90 degrees per second
+		FusedData {
+			q: Quat::from_axis_angle(&nalgebra::Vector3::x_axis(), dt * ROT_RATE),
+		}
+	}
+}
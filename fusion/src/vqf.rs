@@ -0,0 +1,53 @@
+use core::time::Duration;
+
+use ::vqf::nalgebra as na;
+
+use crate::{seconds_between, FusedData, Fuser, Quat, UnfusedData};
+
+/// Versatile Quaternion-based Filter, which also estimates the gyroscope bias. It only
+/// estimates the bias while moving with the `vqf-motion-bias-est` feature.
+pub struct Vqf {
+	vqf: ::vqf::Vqf,
+	ts: f32,
+	last: Option<Duration>,
+}
+
+impl Vqf {
+	/// Tunes the filter for samples that are `ts` seconds apart. The actual time
+	/// between samples comes from their timestamps, but should stay close to `ts`. Use
+	/// the output data rate that the imu is configured for.
+	pub fn with_sample_time(ts: f32) -> Self {
+		let params = ::vqf::VqfParameters::default();
+		Self {
+			vqf: ::vqf::Vqf::new(ts, ts, ts, params),
+			ts,
+			last: None,
+		}
+	}
+}
+impl Fuser for Vqf {
+	fn process(&mut self, unfused: &UnfusedData) -> FusedData {
+		let UnfusedData {
+			accel,
+			gyro,
+			timestamp,
+		} = unfused;
+
+		let dt = match self.last.replace(*timestamp) {
+			Some(last) => seconds_between(last, *timestamp),
+			None => self.ts,
+		};
+		let gyro = na::Vector3::new(gyro.x, gyro.y, gyro.z);
+		let accel = na::Vector3::new(accel.x, accel.y, accel.z);
+		// A sample without any time passed since the last one has nothing new, and is
+		// rejected.
+		let _ = self.vqf.update_dt(gyro, accel, None, dt);
+
+		// `vqf` uses a different version of nalgebra.
+		let q = self.vqf.quat_6d();
+		let q = nalgebra::Quaternion::new(q.w, q.i, q.j, q.k);
+		FusedData {
+			q: Quat::new_unchecked(q),
+		}
+	}
+}
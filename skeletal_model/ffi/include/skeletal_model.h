@@ -0,0 +1,228 @@
+#ifndef SKELETAL_MODEL_H
+#define SKELETAL_MODEL_H
+
+/* Generated by cbindgen from src/lib.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The result of calling a function of the C API.
+typedef enum SmStatus {
+  SM_STATUS_OK = 0,
+  // A required pointer argument was null.
+  SM_STATUS_NULL_POINTER,
+  // An argument was out of range, such as the number of bone lengths or a bone.
+  SM_STATUS_INVALID_ARGUMENT,
+  // The tracker id does not refer to a tracker of the right kind.
+  SM_STATUS_INVALID_TRACKER,
+  // The skeleton has no positional input, so it could not be solved.
+  SM_STATUS_NO_ROOT_NODE,
+  // The skeletal model panicked. This is a bug, and the skeleton may be left in an
+  // inconsistent state.
+  SM_STATUS_PANIC,
+} SmStatus;
+
+// The different kinds of bones in the skeleton. Unlike [`BoneKind`], the values of
+// these are stable.
+typedef enum SmBone {
+  SM_BONE_HEAD,
+  SM_BONE_NECK,
+  SM_BONE_UPPER_CHEST,
+  SM_BONE_CHEST,
+  SM_BONE_WAIST,
+  SM_BONE_HIP,
+  SM_BONE_THIGH_L,
+  SM_BONE_THIGH_R,
+  SM_BONE_ANKLE_L,
+  SM_BONE_ANKLE_R,
+  SM_BONE_FOOT_L,
+  SM_BONE_FOOT_R,
+  SM_BONE_TOES_L,
+  SM_BONE_TOES_R,
+  SM_BONE_SHOULDER_L,
+  SM_BONE_SHOULDER_R,
+  SM_BONE_UPPER_ARM_L,
+  SM_BONE_UPPER_ARM_R,
+  SM_BONE_FOREARM_L,
+  SM_BONE_FOREARM_R,
+  SM_BONE_HAND_L,
+  SM_BONE_HAND_R,
+  SM_BONE_THUMB_PROXIMAL_L,
+  SM_BONE_THUMB_INTERMEDIATE_L,
+  SM_BONE_THUMB_DISTAL_L,
+  SM_BONE_INDEX_PROXIMAL_L,
+  SM_BONE_INDEX_INTERMEDIATE_L,
+  SM_BONE_INDEX_DISTAL_L,
+  SM_BONE_MIDDLE_PROXIMAL_L,
+  SM_BONE_MIDDLE_INTERMEDIATE_L,
+  SM_BONE_MIDDLE_DISTAL_L,
+  SM_BONE_RING_PROXIMAL_L,
+  SM_BONE_RING_INTERMEDIATE_L,
+  SM_BONE_RING_DISTAL_L,
+  SM_BONE_LITTLE_PROXIMAL_L,
+  SM_BONE_LITTLE_INTERMEDIATE_L,
+  SM_BONE_LITTLE_DISTAL_L,
+  SM_BONE_THUMB_PROXIMAL_R,
+  SM_BONE_THUMB_INTERMEDIATE_R,
+  SM_BONE_THUMB_DISTAL_R,
+  SM_BONE_INDEX_PROXIMAL_R,
+  SM_BONE_INDEX_INTERMEDIATE_R,
+  SM_BONE_INDEX_DISTAL_R,
+  SM_BONE_MIDDLE_PROXIMAL_R,
+  SM_BONE_MIDDLE_INTERMEDIATE_R,
+  SM_BONE_MIDDLE_DISTAL_R,
+  SM_BONE_RING_PROXIMAL_R,
+  SM_BONE_RING_INTERMEDIATE_R,
+  SM_BONE_RING_DISTAL_R,
+  SM_BONE_LITTLE_PROXIMAL_R,
+  SM_BONE_LITTLE_INTERMEDIATE_R,
+  SM_BONE_LITTLE_DISTAL_R,
+} SmBone;
+
+// The different kinds of calibration. See [`ResetKind`].
+typedef enum SmResetKind {
+  SM_RESET_KIND_FULL,
+  SM_RESET_KIND_YAW,
+  SM_RESET_KIND_MOUNTING,
+} SmResetKind;
+
+// The opaque skeleton handle.
+typedef struct SmSkeleton SmSkeleton;
+
+typedef struct SmVec3 {
+  float x;
+  float y;
+  float z;
+} SmVec3;
+
+typedef struct SmQuat {
+  float x;
+  float y;
+  float z;
+  float w;
+} SmQuat;
+
+// A position and rotation.
+typedef struct SmPose {
+  struct SmVec3 position;
+  struct SmQuat rotation;
+} SmPose;
+
+// The data of one input tracker, captured while the user is in the calibration pose.
+typedef struct SmCalibrationSample {
+  uint32_t tracker;
+  // Null for trackers without positional data.
+  const struct SmVec3 *position;
+  struct SmQuat rotation;
+} SmCalibrationSample;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The number of bones in the skeleton, which is also the number of values in
+// [`SmBone`].
+size_t sm_num_bones(void);
+
+// Creates a new skeleton. `bone_lengths` must point to [`sm_num_bones()`] lengths,
+// indexed by [`SmBone`]. Returns null if `bone_lengths` is invalid.
+//
+// # Safety
+// `bone_lengths` must be valid for reads of `len` floats.
+struct SmSkeleton *sm_skeleton_new(const float *bone_lengths, size_t len);
+
+// Destroys a skeleton created by [`sm_skeleton_new()`]. Null is ignored.
+//
+// # Safety
+// `skeleton` must be null or come from [`sm_skeleton_new()`], and must not be used
+// afterwards.
+void sm_skeleton_free(struct SmSkeleton *skeleton);
+
+// Attaches an input tracker to `bone`, which is one of [`SmBone`], and writes its id
+// to `out_id`. Either of `pos` or `rot` may be null, if the tracker doesn't provide
+// that data.
+//
+// # Safety
+// All pointers must be null or valid.
+enum SmStatus sm_skeleton_attach_input_tracker(struct SmSkeleton *skeleton,
+                                               uint32_t bone,
+                                               const struct SmVec3 *pos,
+                                               const struct SmQuat *rot,
+                                               uint32_t *out_id);
+
+// Updates the latest data of an input tracker. Either of `pos` or `rot` may be null,
+// if the tracker doesn't provide that data.
+//
+// # Safety
+// All pointers must be null or valid.
+enum SmStatus sm_skeleton_update_input_tracker(struct SmSkeleton *skeleton,
+                                               uint32_t id,
+                                               const struct SmVec3 *pos,
+                                               const struct SmQuat *rot);
+
+// Detaches an input tracker from the skeleton.
+//
+// # Safety
+// `skeleton` must be null or valid.
+enum SmStatus sm_skeleton_detach_input_tracker(struct SmSkeleton *skeleton, uint32_t id);
+
+// Adds an output tracker to `bone`, which is one of [`SmBone`], and writes its id to
+// `out_id`. `offset` is relative to the head of the bone, in the local frame of the
+// bone.
+//
+// # Safety
+// All pointers must be null or valid.
+enum SmStatus sm_skeleton_add_output_tracker(struct SmSkeleton *skeleton,
+                                             uint32_t bone,
+                                             const struct SmPose *offset,
+                                             uint32_t *out_id);
+
+// Removes an output tracker from the skeleton.
+//
+// # Safety
+// `skeleton` must be null or valid.
+enum SmStatus sm_skeleton_remove_output_tracker(struct SmSkeleton *skeleton, uint32_t id);
+
+// Calibrates the input trackers, using `len` samples taken while the user was in the
+// calibration pose. `kind` is one of [`SmResetKind`].
+//
+// # Safety
+// `samples` must be valid for reads of `len` samples, and their `position`s must be
+// null or valid.
+enum SmStatus sm_skeleton_calibrate(struct SmSkeleton *skeleton,
+                                    uint32_t kind,
+                                    const struct SmCalibrationSample *samples,
+                                    size_t len);
+
+// Solves for the pose of the skeleton.
+//
+// # Safety
+// `skeleton` must be null or valid.
+enum SmStatus sm_skeleton_solve(struct SmSkeleton *skeleton);
+
+// Writes the pose of a bone, as of the last [`sm_skeleton_solve()`], to `out`.
+// `bone` is one of [`SmBone`]. The position is that of the head of the bone.
+//
+// # Safety
+// All pointers must be null or valid.
+enum SmStatus sm_skeleton_bone_pose(const struct SmSkeleton *skeleton,
+                                    uint32_t bone,
+                                    struct SmPose *out);
+
+// Writes the pose of an output tracker, as of the last [`sm_skeleton_solve()`], to
+// `out`.
+//
+// # Safety
+// All pointers must be null or valid.
+enum SmStatus sm_skeleton_output_tracker_pose(const struct SmSkeleton *skeleton,
+                                              uint32_t id,
+                                              struct SmPose *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SKELETAL_MODEL_H */
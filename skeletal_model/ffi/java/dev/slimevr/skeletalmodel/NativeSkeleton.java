@@ -0,0 +1,126 @@
+package dev.slimevr.skeletalmodel;
+
+/**
+ * A skeleton backed by the rust skeletal model, through the JNI layer of
+ * `skeletal_model_ffi`. The native library must be built with the `jni` feature.
+ *
+ * <p>Bones are identified by the values of `SmBone` in `skeletal_model.h`. Positions
+ * are `float[3]` of `{x, y, z}`, rotations are quaternions as `float[4]` of
+ * `{x, y, z, w}`, and poses are `float[7]` of a position followed by a rotation.
+ * Errors, including panics of the native library, are thrown as exceptions.
+ */
+public final class NativeSkeleton implements AutoCloseable {
+	static {
+		System.loadLibrary("skeletal_model_ffi");
+	}
+
+	/** The number of bones in the skeleton. */
+	public static final int NUM_BONES = numBones();
+
+	/** The kinds of calibration, see `SmResetKind` in `skeletal_model.h`. */
+	public static final int RESET_FULL = 0;
+	public static final int RESET_YAW = 1;
+	public static final int RESET_MOUNTING = 2;
+
+	private long handle;
+
+	/** @param boneLengths The length of each bone, indexed by bone. */
+	public NativeSkeleton(float[] boneLengths) {
+		handle = create(boneLengths);
+	}
+
+	/**
+	 * Attaches an input tracker to a bone, and returns its id. Either of the position
+	 * or rotation may be null, if the tracker doesn't provide that data.
+	 */
+	public int attachInputTracker(int bone, float[] position, float[] rotation) {
+		return attachInputTracker(handle, bone, position, rotation);
+	}
+
+	/** Updates the latest data of an input tracker. */
+	public void updateInputTracker(int tracker, float[] position, float[] rotation) {
+		updateInputTracker(handle, tracker, position, rotation);
+	}
+
+	public void detachInputTracker(int tracker) {
+		detachInputTracker(handle, tracker);
+	}
+
+	/**
+	 * Adds an output tracker to a bone, and returns its id. The offset is relative to
+	 * the head of the bone, in the local frame of the bone.
+	 */
+	public int addOutputTracker(int bone, float[] offset) {
+		return addOutputTracker(handle, bone, offset);
+	}
+
+	public void removeOutputTracker(int tracker) {
+		removeOutputTracker(handle, tracker);
+	}
+
+	/**
+	 * Calibrates the input trackers, using samples taken while the user was in the
+	 * calibration pose. The sample of {@code trackers[i]} is made of
+	 * {@code positions[i]} and {@code rotations[i]}. Positions may be null, for trackers
+	 * without positional data.
+	 *
+	 * @param kind One of {@link #RESET_FULL}, {@link #RESET_YAW} or
+	 *     {@link #RESET_MOUNTING}.
+	 */
+	public void calibrate(
+		int kind, int[] trackers, float[][] positions, float[][] rotations) {
+		calibrate(handle, kind, trackers, positions, rotations);
+	}
+
+	/** Solves for the pose of the skeleton. */
+	public void solve() {
+		solve(handle);
+	}
+
+	/** The pose of the head of a bone, as of the last {@link #solve()}. */
+	public float[] bonePose(int bone) {
+		float[] pose = new float[7];
+		bonePose(handle, bone, pose);
+		return pose;
+	}
+
+	/** The pose of an output tracker, as of the last {@link #solve()}. */
+	public float[] outputTrackerPose(int tracker) {
+		float[] pose = new float[7];
+		outputTrackerPose(handle, tracker, pose);
+		return pose;
+	}
+
+	@Override
+	public void close() {
+		destroy(handle);
+		handle = 0;
+	}
+
+	private static native int numBones();
+
+	private static native long create(float[] boneLengths);
+
+	private static native void destroy(long handle);
+
+	private static native int attachInputTracker(
+		long handle, int bone, float[] position, float[] rotation);
+
+	private static native void updateInputTracker(
+		long handle, int tracker, float[] position, float[] rotation);
+
+	private static native void detachInputTracker(long handle, int tracker);
+
+	private static native int addOutputTracker(long handle, int bone, float[] offset);
+
+	private static native void removeOutputTracker(long handle, int tracker);
+
+	private static native void calibrate(
+		long handle, int kind, int[] trackers, float[][] positions, float[][] rotations);
+
+	private static native void solve(long handle);
+
+	private static native void bonePose(long handle, int bone, float[] out);
+
+	private static native void outputTrackerPose(long handle, int tracker, float[] out);
+}
@@ -0,0 +1,359 @@
+//! A thin JNI layer over the C API, used by `dev.slimevr.skeletalmodel.NativeSkeleton`
+//! in the `java` folder.
+//!
+//! Skeletons are passed to Java as a `long` handle. Bones are `int`s with the values
+//! of [`SmBone`](crate::SmBone), reset kinds are `int`s with the values of
+//! [`SmResetKind`](crate::SmResetKind), and poses are `float[7]` of
+//! `[px, py, pz, qx, qy, qz, qw]`. Errors and panics are thrown as Java exceptions.
+
+use crate::{
+	sm_num_bones, sm_skeleton_add_output_tracker, sm_skeleton_attach_input_tracker,
+	sm_skeleton_bone_pose, sm_skeleton_calibrate, sm_skeleton_detach_input_tracker,
+	sm_skeleton_free, sm_skeleton_new, sm_skeleton_output_tracker_pose,
+	sm_skeleton_remove_output_tracker, sm_skeleton_solve,
+	sm_skeleton_update_input_tracker, SmCalibrationSample, SmPose, SmQuat, SmSkeleton,
+	SmStatus, SmVec3,
+};
+use skeletal_model::Isometry;
+
+use ::jni::objects::{JClass, JFloatArray, JIntArray, JObjectArray};
+use ::jni::sys::{jint, jlong};
+use ::jni::JNIEnv;
+use std::panic::AssertUnwindSafe;
+
+/// An error that gets thrown as a Java exception.
+struct Throw(&'static str, String);
+impl From<::jni::errors::Error> for Throw {
+	fn from(other: ::jni::errors::Error) -> Self {
+		Self("java/lang/RuntimeException", other.to_string())
+	}
+}
+impl From<SmStatus> for Throw {
+	fn from(other: SmStatus) -> Self {
+		let class = match other {
+			SmStatus::NullPointer => "java/lang/NullPointerException",
+			SmStatus::NoRootNode => "java/lang/IllegalStateException",
+			SmStatus::Panic => "java/lang/RuntimeException",
+			_ => "java/lang/IllegalArgumentException",
+		};
+		Self(class, format!("{other:?}"))
+	}
+}
+
+fn status(s: SmStatus) -> Result<(), Throw> {
+	match s {
+		SmStatus::Ok => Ok(()),
+		s => Err(s.into()),
+	}
+}
+
+/// Runs `f`, and throws its error or panic as an exception. Returns `default` on
+/// errors, which Java ignores since an exception is pending.
+fn throwing<T>(
+	env: &mut JNIEnv,
+	default: T,
+	f: impl FnOnce(&mut JNIEnv) -> Result<T, Throw>,
+) -> T {
+	let result = std::panic::catch_unwind(AssertUnwindSafe(|| f(env)))
+		.unwrap_or_else(|_| Err(SmStatus::Panic.into()));
+	match result {
+		Ok(v) => v,
+		Err(Throw(class, msg)) => {
+			// If this fails, there is already a pending exception.
+			let _ = env.throw_new(class, msg);
+			default
+		}
+	}
+}
+
+fn skeleton_ptr(handle: jlong) -> Result<*mut SmSkeleton, Throw> {
+	match handle as *mut SmSkeleton {
+		p if p.is_null() => Err(SmStatus::NullPointer.into()),
+		p => Ok(p),
+	}
+}
+
+/// Reads a whole `float[]`, or `None` if it is null.
+fn read_floats(env: &mut JNIEnv, arr: &JFloatArray) -> Result<Option<Vec<f32>>, Throw> {
+	if arr.is_null() {
+		return Ok(None);
+	}
+	let mut buf = vec![0.; env.get_array_length(arr)? as usize];
+	env.get_float_array_region(arr, 0, &mut buf)?;
+	Ok(Some(buf))
+}
+
+fn read_vec3(env: &mut JNIEnv, arr: &JFloatArray) -> Result<Option<SmVec3>, Throw> {
+	Ok(match read_floats(env, arr)?.as_deref() {
+		None => None,
+		Some(&[x, y, z]) => Some(SmVec3 { x, y, z }),
+		Some(_) => return Err(SmStatus::InvalidArgument.into()),
+	})
+}
+
+fn read_quat(env: &mut JNIEnv, arr: &JFloatArray) -> Result<Option<SmQuat>, Throw> {
+	Ok(match read_floats(env, arr)?.as_deref() {
+		None => None,
+		Some(&[x, y, z, w]) => Some(SmQuat { x, y, z, w }),
+		Some(_) => return Err(SmStatus::InvalidArgument.into()),
+	})
+}
+
+fn write_pose(env: &mut JNIEnv, arr: &JFloatArray, pose: SmPose) -> Result<(), Throw> {
+	if arr.is_null() {
+		return Err(SmStatus::NullPointer.into());
+	}
+	let SmPose {
+		position: p,
+		rotation: q,
+	} = pose;
+	env.set_float_array_region(arr, 0, &[p.x, p.y, p.z, q.x, q.y, q.z, q.w])?;
+	Ok(())
+}
+
+fn opt_ptr<T>(opt: &Option<T>) -> *const T {
+	opt.as_ref().map_or(std::ptr::null(), |v| v as *const T)
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_numBones(
+	mut env: JNIEnv,
+	_class: JClass,
+) -> jint {
+	throwing(&mut env, 0, |_env| Ok(sm_num_bones() as _))
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_create(
+	mut env: JNIEnv,
+	_class: JClass,
+	bone_lengths: JFloatArray,
+) -> jlong {
+	throwing(&mut env, 0, |env| {
+		let lengths = read_floats(env, &bone_lengths)?
+			.ok_or(Throw::from(SmStatus::NullPointer))?;
+		let skeleton = unsafe { sm_skeleton_new(lengths.as_ptr(), lengths.len()) };
+		if skeleton.is_null() {
+			return Err(SmStatus::InvalidArgument.into());
+		}
+		Ok(skeleton as jlong)
+	})
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_destroy(
+	mut env: JNIEnv,
+	_class: JClass,
+	handle: jlong,
+) {
+	throwing(&mut env, (), |_env| {
+		unsafe { sm_skeleton_free(handle as *mut SmSkeleton) };
+		Ok(())
+	})
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_attachInputTracker(
+	mut env: JNIEnv,
+	_class: JClass,
+	handle: jlong,
+	bone_kind: jint,
+	position: JFloatArray,
+	rotation: JFloatArray,
+) -> jint {
+	throwing(&mut env, -1, |env| {
+		let skeleton = skeleton_ptr(handle)?;
+		let pos = read_vec3(env, &position)?;
+		let rot = read_quat(env, &rotation)?;
+		let mut id = 0;
+		status(unsafe {
+			sm_skeleton_attach_input_tracker(
+				skeleton,
+				bone_kind as _,
+				opt_ptr(&pos),
+				opt_ptr(&rot),
+				&mut id,
+			)
+		})?;
+		Ok(id as _)
+	})
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_updateInputTracker(
+	mut env: JNIEnv,
+	_class: JClass,
+	handle: jlong,
+	id: jint,
+	position: JFloatArray,
+	rotation: JFloatArray,
+) {
+	throwing(&mut env, (), |env| {
+		let skeleton = skeleton_ptr(handle)?;
+		let pos = read_vec3(env, &position)?;
+		let rot = read_quat(env, &rotation)?;
+		status(unsafe {
+			sm_skeleton_update_input_tracker(
+				skeleton,
+				id as _,
+				opt_ptr(&pos),
+				opt_ptr(&rot),
+			)
+		})
+	})
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_detachInputTracker(
+	mut env: JNIEnv,
+	_class: JClass,
+	handle: jlong,
+	id: jint,
+) {
+	throwing(&mut env, (), |_env| {
+		let skeleton = skeleton_ptr(handle)?;
+		status(unsafe { sm_skeleton_detach_input_tracker(skeleton, id as _) })
+	})
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_addOutputTracker(
+	mut env: JNIEnv,
+	_class: JClass,
+	handle: jlong,
+	bone_kind: jint,
+	offset: JFloatArray,
+) -> jint {
+	throwing(&mut env, -1, |env| {
+		let skeleton = skeleton_ptr(handle)?;
+		let offset = match read_floats(env, &offset)?.as_deref() {
+			Some(&[px, py, pz, x, y, z, w]) => SmPose {
+				position: SmVec3 {
+					x: px,
+					y: py,
+					z: pz,
+				},
+				rotation: SmQuat { x, y, z, w },
+			},
+			None => return Err(SmStatus::NullPointer.into()),
+			Some(_) => return Err(SmStatus::InvalidArgument.into()),
+		};
+		let mut id = 0;
+		status(unsafe {
+			sm_skeleton_add_output_tracker(skeleton, bone_kind as _, &offset, &mut id)
+		})?;
+		Ok(id as _)
+	})
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_removeOutputTracker(
+	mut env: JNIEnv,
+	_class: JClass,
+	handle: jlong,
+	id: jint,
+) {
+	throwing(&mut env, (), |_env| {
+		let skeleton = skeleton_ptr(handle)?;
+		status(unsafe { sm_skeleton_remove_output_tracker(skeleton, id as _) })
+	})
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_calibrate(
+	mut env: JNIEnv,
+	_class: JClass,
+	handle: jlong,
+	kind: jint,
+	trackers: JIntArray,
+	positions: JObjectArray,
+	rotations: JObjectArray,
+) {
+	throwing(&mut env, (), |env| {
+		let skeleton = skeleton_ptr(handle)?;
+		if trackers.is_null() || positions.is_null() || rotations.is_null() {
+			return Err(SmStatus::NullPointer.into());
+		}
+		let len = env.get_array_length(&trackers)?;
+		if env.get_array_length(&positions)? != len
+			|| env.get_array_length(&rotations)? != len
+		{
+			return Err(SmStatus::InvalidArgument.into());
+		}
+		let mut ids = vec![0; len as usize];
+		env.get_int_array_region(&trackers, 0, &mut ids)?;
+
+		// The samples point into `sample_positions`, so it has to outlive them.
+		let mut sample_positions = Vec::with_capacity(len as usize);
+		let mut sample_rotations = Vec::with_capacity(len as usize);
+		for i in 0..len {
+			let pos = JFloatArray::from(env.get_object_array_element(&positions, i)?);
+			sample_positions.push(read_vec3(env, &pos)?);
+			env.delete_local_ref(pos)?;
+			let rot = JFloatArray::from(env.get_object_array_element(&rotations, i)?);
+			let rotation =
+				read_quat(env, &rot)?.ok_or(Throw::from(SmStatus::NullPointer))?;
+			sample_rotations.push(rotation);
+			env.delete_local_ref(rot)?;
+		}
+		let samples: Vec<_> = ids
+			.iter()
+			.zip(&sample_positions)
+			.zip(sample_rotations)
+			.map(|((&id, pos), rotation)| SmCalibrationSample {
+				tracker: id as _,
+				position: opt_ptr(pos),
+				rotation,
+			})
+			.collect();
+		status(unsafe {
+			sm_skeleton_calibrate(skeleton, kind as _, samples.as_ptr(), samples.len())
+		})
+	})
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_solve(
+	mut env: JNIEnv,
+	_class: JClass,
+	handle: jlong,
+) {
+	throwing(&mut env, (), |_env| {
+		let skeleton = skeleton_ptr(handle)?;
+		status(unsafe { sm_skeleton_solve(skeleton) })
+	})
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_bonePose(
+	mut env: JNIEnv,
+	_class: JClass,
+	handle: jlong,
+	bone_kind: jint,
+	out: JFloatArray,
+) {
+	throwing(&mut env, (), |env| {
+		let skeleton = skeleton_ptr(handle)?;
+		let mut pose = SmPose::from(Isometry::identity());
+		status(unsafe { sm_skeleton_bone_pose(skeleton, bone_kind as _, &mut pose) })?;
+		write_pose(env, &out, pose)
+	})
+}
+
+#[no_mangle]
+pub extern "system" fn Java_dev_slimevr_skeletalmodel_NativeSkeleton_outputTrackerPose(
+	mut env: JNIEnv,
+	_class: JClass,
+	handle: jlong,
+	id: jint,
+	out: JFloatArray,
+) {
+	throwing(&mut env, (), |env| {
+		let skeleton = skeleton_ptr(handle)?;
+		let mut pose = SmPose::from(Isometry::identity());
+		status(unsafe {
+			sm_skeleton_output_tracker_pose(skeleton, id as _, &mut pose)
+		})?;
+		write_pose(env, &out, pose)
+	})
+}
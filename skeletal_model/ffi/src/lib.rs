@@ -0,0 +1,684 @@
+//! A C ABI for the [`skeletal_model`] crate, so that it can be embedded in other
+//! languages. The header for this API is generated by [cbindgen] into
+//! `include/skeletal_model.h`.
+//!
+//! The [`Skeleton`] is exposed as an opaque pointer, created by [`sm_skeleton_new()`]
+//! and destroyed with [`sm_skeleton_free()`]. All functions that can fail return a
+//! [`SmStatus`], and write their results through out-pointers. Enums are passed as
+//! `uint32_t`, so that out of range values are rejected instead of being undefined
+//! behavior. Panics never unwind into the caller, they are reported as
+//! [`SmStatus::Panic`] instead.
+//!
+//! With the `jni` feature, there is also a thin JNI layer on top of this API, for use
+//! from Java. See the [`jni`] module.
+//!
+//! [cbindgen]: https://github.com/eqrion/cbindgen
+
+#[cfg(feature = "jni")]
+pub mod jni;
+
+use skeletal_model::skeleton::{CalibrationData, ResetKind, SkeletonConfig};
+use skeletal_model::{
+	BoneKind, BoneMap, Global, Isometry, Local, Point, Skeleton, TrackerId,
+	Translation, UnitQuat,
+};
+
+use nalgebra::Quaternion;
+use std::panic::AssertUnwindSafe;
+
+/// The different kinds of bones in the skeleton. Unlike [`BoneKind`], the values of
+/// these are stable.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SmBone {
+	Head,
+	Neck,
+	UpperChest,
+	Chest,
+	Waist,
+	Hip,
+	ThighL,
+	ThighR,
+	AnkleL,
+	AnkleR,
+	FootL,
+	FootR,
+	ToesL,
+	ToesR,
+	ShoulderL,
+	ShoulderR,
+	UpperArmL,
+	UpperArmR,
+	ForearmL,
+	ForearmR,
+	HandL,
+	HandR,
+	ThumbProximalL,
+	ThumbIntermediateL,
+	ThumbDistalL,
+	IndexProximalL,
+	IndexIntermediateL,
+	IndexDistalL,
+	MiddleProximalL,
+	MiddleIntermediateL,
+	MiddleDistalL,
+	RingProximalL,
+	RingIntermediateL,
+	RingDistalL,
+	LittleProximalL,
+	LittleIntermediateL,
+	LittleDistalL,
+	ThumbProximalR,
+	ThumbIntermediateR,
+	ThumbDistalR,
+	IndexProximalR,
+	IndexIntermediateR,
+	IndexDistalR,
+	MiddleProximalR,
+	MiddleIntermediateR,
+	MiddleDistalR,
+	RingProximalR,
+	RingIntermediateR,
+	RingDistalR,
+	LittleProximalR,
+	LittleIntermediateR,
+	LittleDistalR,
+}
+
+/// Implements the conversions of `SmBone`. cbindgen can't see through macros, so the
+/// enum itself has to be written out above.
+macro_rules! sm_bone_conversions {
+	($($bone:ident),* $(,)?) => {
+		impl From<SmBone> for BoneKind {
+			fn from(other: SmBone) -> Self {
+				match other {
+					$(SmBone::$bone => Self::$bone),*
+				}
+			}
+		}
+		impl TryFrom<u32> for SmBone {
+			type Error = ();
+
+			fn try_from(value: u32) -> Result<Self, Self::Error> {
+				[$(SmBone::$bone),*].get(value as usize).copied().ok_or(())
+			}
+		}
+	};
+}
+sm_bone_conversions!(
+	Head,
+	Neck,
+	UpperChest,
+	Chest,
+	Waist,
+	Hip,
+	ThighL,
+	ThighR,
+	AnkleL,
+	AnkleR,
+	FootL,
+	FootR,
+	ToesL,
+	ToesR,
+	ShoulderL,
+	ShoulderR,
+	UpperArmL,
+	UpperArmR,
+	ForearmL,
+	ForearmR,
+	HandL,
+	HandR,
+	ThumbProximalL,
+	ThumbIntermediateL,
+	ThumbDistalL,
+	IndexProximalL,
+	IndexIntermediateL,
+	IndexDistalL,
+	MiddleProximalL,
+	MiddleIntermediateL,
+	MiddleDistalL,
+	RingProximalL,
+	RingIntermediateL,
+	RingDistalL,
+	LittleProximalL,
+	LittleIntermediateL,
+	LittleDistalL,
+	ThumbProximalR,
+	ThumbIntermediateR,
+	ThumbDistalR,
+	IndexProximalR,
+	IndexIntermediateR,
+	IndexDistalR,
+	MiddleProximalR,
+	MiddleIntermediateR,
+	MiddleDistalR,
+	RingProximalR,
+	RingIntermediateR,
+	RingDistalR,
+	LittleProximalR,
+	LittleIntermediateR,
+	LittleDistalR,
+);
+
+/// The result of calling a function of the C API.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SmStatus {
+	Ok = 0,
+	/// A required pointer argument was null.
+	NullPointer,
+	/// An argument was out of range, such as the number of bone lengths or a bone.
+	InvalidArgument,
+	/// The tracker id does not refer to a tracker of the right kind.
+	InvalidTracker,
+	/// The skeleton has no positional input, so it could not be solved.
+	NoRootNode,
+	/// The skeletal model panicked. This is a bug, and the skeleton may be left in an
+	/// inconsistent state.
+	Panic,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmVec3 {
+	pub x: f32,
+	pub y: f32,
+	pub z: f32,
+}
+impl From<SmVec3> for Point {
+	fn from(other: SmVec3) -> Self {
+		Point::new(other.x, other.y, other.z)
+	}
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmQuat {
+	pub x: f32,
+	pub y: f32,
+	pub z: f32,
+	pub w: f32,
+}
+impl From<SmQuat> for UnitQuat {
+	fn from(other: SmQuat) -> Self {
+		let SmQuat { x, y, z, w } = other;
+		UnitQuat::from_quaternion(Quaternion::new(w, x, y, z))
+	}
+}
+
+/// A position and rotation.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmPose {
+	pub position: SmVec3,
+	pub rotation: SmQuat,
+}
+impl From<SmPose> for Isometry {
+	fn from(other: SmPose) -> Self {
+		let trans = Translation::from(Point::from(other.position).coords);
+		Isometry::from_parts(trans, other.rotation.into())
+	}
+}
+impl From<Isometry> for SmPose {
+	fn from(other: Isometry) -> Self {
+		let t = other.translation.vector;
+		let q = other.rotation.coords;
+		Self {
+			position: SmVec3 {
+				x: t.x,
+				y: t.y,
+				z: t.z,
+			},
+			rotation: SmQuat {
+				x: q.x,
+				y: q.y,
+				z: q.z,
+				w: q.w,
+			},
+		}
+	}
+}
+
+/// The different kinds of calibration. See [`ResetKind`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SmResetKind {
+	Full,
+	Yaw,
+	Mounting,
+}
+impl From<SmResetKind> for ResetKind {
+	fn from(other: SmResetKind) -> Self {
+		match other {
+			SmResetKind::Full => Self::Full,
+			SmResetKind::Yaw => Self::Yaw,
+			SmResetKind::Mounting => Self::Mounting,
+		}
+	}
+}
+impl TryFrom<u32> for SmResetKind {
+	type Error = ();
+
+	fn try_from(value: u32) -> Result<Self, Self::Error> {
+		[Self::Full, Self::Yaw, Self::Mounting]
+			.get(value as usize)
+			.copied()
+			.ok_or(())
+	}
+}
+
+/// The data of one input tracker, captured while the user is in the calibration pose.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SmCalibrationSample {
+	pub tracker: u32,
+	/// Null for trackers without positional data.
+	pub position: *const SmVec3,
+	pub rotation: SmQuat,
+}
+
+/// The opaque skeleton handle.
+pub struct SmSkeleton(Skeleton);
+
+/// Turns `expr` into a reference, returning [`SmStatus::NullPointer`] if it is null.
+macro_rules! deref {
+	($ptr:expr) => {
+		match $ptr.as_ref() {
+			Some(r) => r,
+			None => return SmStatus::NullPointer,
+		}
+	};
+	(mut $ptr:expr) => {
+		match $ptr.as_mut() {
+			Some(r) => r,
+			None => return SmStatus::NullPointer,
+		}
+	};
+}
+
+/// Converts a `Result` to a [`SmStatus`], returning early on errors.
+macro_rules! check {
+	($result:expr) => {
+		match $result {
+			Ok(v) => v,
+			Err(err) => return SmStatus::from(err),
+		}
+	};
+}
+
+/// Converts a `u32` to an enum like [`SmBone`], returning
+/// [`SmStatus::InvalidArgument`] if it is out of range.
+macro_rules! convert {
+	($value:expr) => {
+		match $value.try_into() {
+			Ok(v) => v,
+			Err(()) => return SmStatus::InvalidArgument,
+		}
+	};
+}
+
+/// Runs `f`, and turns a panic into [`SmStatus::Panic`]. Unwinding into the caller
+/// would be undefined behavior, so every function of the API goes through this.
+fn catch_panic(f: impl FnOnce() -> SmStatus) -> SmStatus {
+	std::panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(SmStatus::Panic)
+}
+
+impl From<skeletal_model::skeleton::TrackerError> for SmStatus {
+	fn from(_: skeletal_model::skeleton::TrackerError) -> Self {
+		Self::InvalidTracker
+	}
+}
+impl From<skeletal_model::skeleton::SolveError> for SmStatus {
+	fn from(_: skeletal_model::skeleton::SolveError) -> Self {
+		Self::NoRootNode
+	}
+}
+
+/// The number of bones in the skeleton, which is also the number of values in
+/// [`SmBone`].
+#[no_mangle]
+pub extern "C" fn sm_num_bones() -> usize {
+	BoneKind::NUM_TYPES
+}
+
+/// Creates a new skeleton. `bone_lengths` must point to [`sm_num_bones()`] lengths,
+/// indexed by [`SmBone`]. Returns null if `bone_lengths` is invalid.
+///
+/// # Safety
+/// `bone_lengths` must be valid for reads of `len` floats.
+#[no_mangle]
+pub unsafe extern "C" fn sm_skeleton_new(
+	bone_lengths: *const f32,
+	len: usize,
+) -> *mut SmSkeleton {
+	if bone_lengths.is_null() || len != BoneKind::NUM_TYPES {
+		return std::ptr::null_mut();
+	}
+	let lengths = std::slice::from_raw_parts(bone_lengths, len);
+	let skeleton = std::panic::catch_unwind(|| {
+		let mut bone_map = BoneMap::new([0.; BoneKind::NUM_TYPES]);
+		for (i, length) in lengths.iter().enumerate() {
+			let bone: SmBone = (i as u32).try_into().unwrap();
+			bone_map[bone.into()] = *length;
+		}
+		Skeleton::new(&SkeletonConfig::new(bone_map))
+	});
+	match skeleton {
+		Ok(skeleton) => Box::into_raw(Box::new(SmSkeleton(skeleton))),
+		Err(_) => std::ptr::null_mut(),
+	}
+}
+
+/// Destroys a skeleton created by [`sm_skeleton_new()`]. Null is ignored.
+///
+/// # Safety
+/// `skeleton` must be null or come from [`sm_skeleton_new()`], and must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn sm_skeleton_free(skeleton: *mut SmSkeleton) {
+	if !skeleton.is_null() {
+		// Dropping a skeleton doesn't panic, but there would be nothing to report it
+		// to anyway.
+		let _ = std::panic::catch_unwind(AssertUnwindSafe(|| {
+			drop(Box::from_raw(skeleton));
+		}));
+	}
+}
+
+/// Attaches an input tracker to `bone`, which is one of [`SmBone`], and writes its id
+/// to `out_id`. Either of `pos` or `rot` may be null, if the tracker doesn't provide
+/// that data.
+///
+/// # Safety
+/// All pointers must be null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn sm_skeleton_attach_input_tracker(
+	skeleton: *mut SmSkeleton,
+	bone: u32,
+	pos: *const SmVec3,
+	rot: *const SmQuat,
+	out_id: *mut u32,
+) -> SmStatus {
+	catch_panic(|| {
+		let skeleton = deref!(mut skeleton);
+		let out_id = deref!(mut out_id);
+		let bone: SmBone = convert!(bone);
+		let pos = pos.as_ref().map(|p| Global(Point::from(*p)));
+		let rot = rot.as_ref().map(|r| Global(UnitQuat::from(*r)));
+		*out_id = skeleton
+			.0
+			.attach_input_tracker(bone.into(), pos, rot)
+			.into();
+		SmStatus::Ok
+	})
+}
+
+/// Updates the latest data of an input tracker. Either of `pos` or `rot` may be null,
+/// if the tracker doesn't provide that data.
+///
+/// # Safety
+/// All pointers must be null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn sm_skeleton_update_input_tracker(
+	skeleton: *mut SmSkeleton,
+	id: u32,
+	pos: *const SmVec3,
+	rot: *const SmQuat,
+) -> SmStatus {
+	catch_panic(|| {
+		let skeleton = deref!(mut skeleton);
+		let pos = pos.as_ref().map(|p| Global(Point::from(*p)));
+		let rot = rot.as_ref().map(|r| Global(UnitQuat::from(*r)));
+		check!(skeleton.0.update_input_tracker(id.into(), pos, rot));
+		SmStatus::Ok
+	})
+}
+
+/// Detaches an input tracker from the skeleton.
+///
+/// # Safety
+/// `skeleton` must be null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn sm_skeleton_detach_input_tracker(
+	skeleton: *mut SmSkeleton,
+	id: u32,
+) -> SmStatus {
+	catch_panic(|| {
+		let skeleton = deref!(mut skeleton);
+		check!(skeleton.0.detach_input_tracker(id.into()));
+		SmStatus::Ok
+	})
+}
+
+/// Adds an output tracker to `bone`, which is one of [`SmBone`], and writes its id to
+/// `out_id`. `offset` is relative to the head of the bone, in the local frame of the
+/// bone.
+///
+/// # Safety
+/// All pointers must be null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn sm_skeleton_add_output_tracker(
+	skeleton: *mut SmSkeleton,
+	bone: u32,
+	offset: *const SmPose,
+	out_id: *mut u32,
+) -> SmStatus {
+	catch_panic(|| {
+		let skeleton = deref!(mut skeleton);
+		let offset = deref!(offset);
+		let out_id = deref!(mut out_id);
+		let bone: SmBone = convert!(bone);
+		let offset = Local(Isometry::from(*offset));
+		*out_id = skeleton.0.add_output_tracker(bone.into(), offset).into();
+		SmStatus::Ok
+	})
+}
+
+/// Removes an output tracker from the skeleton.
+///
+/// # Safety
+/// `skeleton` must be null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn sm_skeleton_remove_output_tracker(
+	skeleton: *mut SmSkeleton,
+	id: u32,
+) -> SmStatus {
+	catch_panic(|| {
+		let skeleton = deref!(mut skeleton);
+		check!(skeleton.0.remove_output_tracker(id.into()));
+		SmStatus::Ok
+	})
+}
+
+/// Calibrates the input trackers, using `len` samples taken while the user was in the
+/// calibration pose. `kind` is one of [`SmResetKind`].
+///
+/// # Safety
+/// `samples` must be valid for reads of `len` samples, and their `position`s must be
+/// null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn sm_skeleton_calibrate(
+	skeleton: *mut SmSkeleton,
+	kind: u32,
+	samples: *const SmCalibrationSample,
+	len: usize,
+) -> SmStatus {
+	catch_panic(|| {
+		let skeleton = deref!(mut skeleton);
+		let kind: SmResetKind = convert!(kind);
+		if samples.is_null() && len > 0 {
+			return SmStatus::NullPointer;
+		}
+		let samples = if len > 0 {
+			std::slice::from_raw_parts(samples, len)
+		} else {
+			&[]
+		};
+		let samples = samples.iter().map(|s| {
+			let rot = Global(UnitQuat::from(s.rotation));
+			let data = match s.position.as_ref() {
+				Some(pos) => CalibrationData::SixDof {
+					pos: Global(Point::from(*pos)),
+					rot,
+				},
+				None => CalibrationData::ThreeDof { rot },
+			};
+			(TrackerId::from(s.tracker), data)
+		});
+		check!(skeleton.0.calibrate(kind.into(), samples));
+		SmStatus::Ok
+	})
+}
+
+/// Solves for the pose of the skeleton.
+///
+/// # Safety
+/// `skeleton` must be null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn sm_skeleton_solve(skeleton: *mut SmSkeleton) -> SmStatus {
+	catch_panic(|| {
+		let skeleton = deref!(mut skeleton);
+		check!(skeleton.0.solve());
+		SmStatus::Ok
+	})
+}
+
+/// Writes the pose of a bone, as of the last [`sm_skeleton_solve()`], to `out`.
+/// `bone` is one of [`SmBone`]. The position is that of the head of the bone.
+///
+/// # Safety
+/// All pointers must be null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn sm_skeleton_bone_pose(
+	skeleton: *const SmSkeleton,
+	bone: u32,
+	out: *mut SmPose,
+) -> SmStatus {
+	catch_panic(|| {
+		let skeleton = deref!(skeleton);
+		let out = deref!(mut out);
+		let bone: SmBone = convert!(bone);
+		*out = skeleton.0.bone_pose(bone.into()).0.into();
+		SmStatus::Ok
+	})
+}
+
+/// Writes the pose of an output tracker, as of the last [`sm_skeleton_solve()`], to
+/// `out`.
+///
+/// # Safety
+/// All pointers must be null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn sm_skeleton_output_tracker_pose(
+	skeleton: *const SmSkeleton,
+	id: u32,
+	out: *mut SmPose,
+) -> SmStatus {
+	catch_panic(|| {
+		let skeleton = deref!(skeleton);
+		let out = deref!(mut out);
+		*out = check!(skeleton.0.output_tracker_pose(id.into())).0.into();
+		SmStatus::Ok
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::ptr::{null, null_mut};
+
+	const IDENTITY: SmQuat = SmQuat {
+		x: 0.,
+		y: 0.,
+		z: 0.,
+		w: 1.,
+	};
+
+	/// The bones are listed by hand, so make sure they match the rust ones.
+	#[test]
+	fn test_bone_order() {
+		for (i, expected) in BoneKind::iter().enumerate() {
+			let bone = SmBone::try_from(i as u32).unwrap();
+			assert_eq!(bone as usize, i);
+			assert_eq!(BoneKind::from(bone), expected);
+			assert_eq!(format!("{bone:?}"), format!("{expected:?}"));
+		}
+		assert!(SmBone::try_from(sm_num_bones() as u32).is_err());
+	}
+
+	#[test]
+	fn test_catch_panic() {
+		assert_eq!(catch_panic(|| SmStatus::Ok), SmStatus::Ok);
+		assert_eq!(catch_panic(|| panic!("oops")), SmStatus::Panic);
+	}
+
+	#[test]
+	fn test_lifecycle() {
+		unsafe {
+			let lengths = vec![0.5; sm_num_bones()];
+			assert!(sm_skeleton_new(lengths.as_ptr(), lengths.len() - 1).is_null());
+			let skeleton = sm_skeleton_new(lengths.as_ptr(), lengths.len());
+			assert!(!skeleton.is_null());
+
+			assert_eq!(sm_skeleton_solve(skeleton), SmStatus::NoRootNode);
+			let mut hmd = 0;
+			let pos = SmVec3 {
+				x: 0.,
+				y: 2.,
+				z: 0.,
+			};
+			let status = sm_skeleton_attach_input_tracker(
+				skeleton,
+				SmBone::Neck as u32,
+				&pos,
+				&IDENTITY,
+				&mut hmd,
+			);
+			assert_eq!(status, SmStatus::Ok);
+			let status = sm_skeleton_attach_input_tracker(
+				skeleton,
+				SmBone::Neck as u32,
+				null(),
+				null(),
+				null_mut(),
+			);
+			assert_eq!(status, SmStatus::NullPointer);
+			assert_eq!(sm_skeleton_solve(skeleton), SmStatus::Ok);
+
+			let mut pose = SmPose::from(Isometry::identity());
+			let status =
+				sm_skeleton_bone_pose(skeleton, SmBone::Chest as u32, &mut pose);
+			assert_eq!(status, SmStatus::Ok);
+			assert_eq!(
+				pose.position,
+				SmVec3 {
+					x: 0.,
+					y: 1.,
+					z: 0.
+				}
+			);
+			let status = sm_skeleton_output_tracker_pose(skeleton, hmd, &mut pose);
+			assert_eq!(status, SmStatus::InvalidTracker);
+
+			// Out of range enums are rejected.
+			let status =
+				sm_skeleton_bone_pose(skeleton, sm_num_bones() as u32, &mut pose);
+			assert_eq!(status, SmStatus::InvalidArgument);
+			let status = sm_skeleton_calibrate(skeleton, 3, null(), 0);
+			assert_eq!(status, SmStatus::InvalidArgument);
+			let status = sm_skeleton_calibrate(skeleton, 0, null(), 0);
+			assert_eq!(status, SmStatus::Ok);
+
+			assert_eq!(
+				sm_skeleton_detach_input_tracker(skeleton, hmd),
+				SmStatus::Ok
+			);
+			assert_eq!(
+				sm_skeleton_detach_input_tracker(skeleton, hmd),
+				SmStatus::InvalidTracker
+			);
+			sm_skeleton_free(skeleton);
+		}
+	}
+}
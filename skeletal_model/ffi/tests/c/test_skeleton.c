@@ -0,0 +1,108 @@
+// A test harness for the C API. Run it with `make -C skeletal_model/ffi/tests/c`.
+
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "skeletal_model.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                                \
+	do {                                                                         \
+		if (!(cond)) {                                                           \
+			fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+			failures++;                                                          \
+		}                                                                        \
+	} while (0)
+
+static int close_to(float a, float b) { return fabsf(a - b) < 1e-5f; }
+
+static int vec3_eq(SmVec3 v, float x, float y, float z) {
+	return close_to(v.x, x) && close_to(v.y, y) && close_to(v.z, z);
+}
+
+static const SmQuat IDENTITY = {0.f, 0.f, 0.f, 1.f};
+
+static SmSkeleton *make_skeleton(void) {
+	size_t n = sm_num_bones();
+	float *lengths = malloc(n * sizeof(float));
+	for (size_t i = 0; i < n; i++) {
+		lengths[i] = 0.5f;
+	}
+	CHECK(sm_skeleton_new(lengths, n - 1) == NULL);
+	SmSkeleton *skeleton = sm_skeleton_new(lengths, n);
+	free(lengths);
+	return skeleton;
+}
+
+static void test_bone_poses(void) {
+	SmSkeleton *skeleton = make_skeleton();
+	CHECK(skeleton != NULL);
+	CHECK(sm_skeleton_solve(skeleton) == SM_STATUS_NO_ROOT_NODE);
+
+	uint32_t hmd;
+	SmVec3 pos = {0.f, 2.f, 0.f};
+	CHECK(sm_skeleton_attach_input_tracker(skeleton, SM_BONE_NECK, &pos, &IDENTITY, &hmd) ==
+	      SM_STATUS_OK);
+	CHECK(sm_skeleton_solve(skeleton) == SM_STATUS_OK);
+
+	SmPose pose;
+	CHECK(sm_skeleton_bone_pose(skeleton, SM_BONE_NECK, &pose) == SM_STATUS_OK);
+	CHECK(vec3_eq(pose.position, 0.f, 2.f, 0.f));
+	CHECK(close_to(pose.rotation.w, 1.f));
+	// The neck and upper chest are between the head of the neck and the chest.
+	CHECK(sm_skeleton_bone_pose(skeleton, SM_BONE_CHEST, &pose) == SM_STATUS_OK);
+	CHECK(vec3_eq(pose.position, 0.f, 1.f, 0.f));
+
+	CHECK(sm_skeleton_bone_pose(skeleton, SM_BONE_CHEST, NULL) == SM_STATUS_NULL_POINTER);
+	CHECK(sm_skeleton_bone_pose(skeleton, sm_num_bones(), &pose) == SM_STATUS_INVALID_ARGUMENT);
+	CHECK(sm_skeleton_solve(NULL) == SM_STATUS_NULL_POINTER);
+	sm_skeleton_free(skeleton);
+}
+
+static void test_trackers(void) {
+	SmSkeleton *skeleton = make_skeleton();
+
+	uint32_t hmd, hip;
+	SmVec3 pos = {0.f, 2.f, 0.f};
+	CHECK(sm_skeleton_attach_input_tracker(skeleton, SM_BONE_NECK, &pos, NULL, &hmd) ==
+	      SM_STATUS_OK);
+	SmPose offset = {{0.f, 0.f, 0.1f}, IDENTITY};
+	CHECK(sm_skeleton_add_output_tracker(skeleton, SM_BONE_HIP, &offset, &hip) ==
+	      SM_STATUS_OK);
+
+	SmCalibrationSample sample = {hmd, &pos, IDENTITY};
+	CHECK(sm_skeleton_calibrate(skeleton, SM_RESET_KIND_FULL, &sample, 1) == SM_STATUS_OK);
+	CHECK(sm_skeleton_solve(skeleton) == SM_STATUS_OK);
+
+	SmPose pose;
+	CHECK(sm_skeleton_output_tracker_pose(skeleton, hip, &pose) == SM_STATUS_OK);
+	CHECK(vec3_eq(pose.position, 0.f, 0.f, 0.1f));
+
+	pos.y = 3.f;
+	CHECK(sm_skeleton_update_input_tracker(skeleton, hmd, &pos, NULL) == SM_STATUS_OK);
+	CHECK(sm_skeleton_solve(skeleton) == SM_STATUS_OK);
+	CHECK(sm_skeleton_output_tracker_pose(skeleton, hip, &pose) == SM_STATUS_OK);
+	CHECK(vec3_eq(pose.position, 0.f, 1.f, 0.1f));
+
+	// Input and output trackers can't be mixed up.
+	CHECK(sm_skeleton_output_tracker_pose(skeleton, hmd, &pose) == SM_STATUS_INVALID_TRACKER);
+	CHECK(sm_skeleton_detach_input_tracker(skeleton, hip) == SM_STATUS_INVALID_TRACKER);
+
+	CHECK(sm_skeleton_remove_output_tracker(skeleton, hip) == SM_STATUS_OK);
+	CHECK(sm_skeleton_detach_input_tracker(skeleton, hmd) == SM_STATUS_OK);
+	CHECK(sm_skeleton_solve(skeleton) == SM_STATUS_NO_ROOT_NODE);
+	sm_skeleton_free(skeleton);
+}
+
+int main(void) {
+	test_bone_poses();
+	test_trackers();
+	if (failures > 0) {
+		fprintf(stderr, "%d checks failed\n", failures);
+		return EXIT_FAILURE;
+	}
+	printf("all checks passed\n");
+	return EXIT_SUCCESS;
+}
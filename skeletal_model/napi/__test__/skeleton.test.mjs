@@ -0,0 +1,66 @@
+import assert from 'node:assert/strict'
+import { test } from 'node:test'
+import { createRequire } from 'node:module'
+
+const require = createRequire(import.meta.url)
+const { BoneKind, BoneMap, ResetKind, Skeleton, SkeletonConfig } = require('../index.js')
+
+const IDENTITY = new Float32Array([0, 0, 0, 1])
+
+function makeSkeleton() {
+  const lengths = new BoneMap(0.5)
+  return new Skeleton(new SkeletonConfig(lengths))
+}
+
+function assertClose(actual, expected) {
+  assert.equal(actual.length, expected.length)
+  for (let i = 0; i < expected.length; i++) {
+    assert.ok(Math.abs(actual[i] - expected[i]) < 1e-5, `${actual} != ${expected}`)
+  }
+}
+
+test('bone map', () => {
+  const map = new BoneMap()
+  map.set(BoneKind.FootL, 0.25)
+  assert.equal(map.get(BoneKind.FootL), 0.25)
+  assert.equal(map.get(BoneKind.FootR), 0)
+  assert.equal(map.toArray().length, BoneKind.LittleDistalR + 1)
+})
+
+test('solve and read bone poses', () => {
+  const skeleton = makeSkeleton()
+  assert.equal(skeleton.boneLength(BoneKind.Chest), 0.5)
+  skeleton.attachInputTracker(BoneKind.Neck, new Float32Array([0, 2, 0]), IDENTITY)
+  skeleton.solve()
+
+  const neck = skeleton.bonePose(BoneKind.Neck)
+  assertClose(neck.position, [0, 2, 0])
+  assertClose(neck.rotation, IDENTITY)
+  // The neck and upper chest are between the head of the neck and the chest.
+  assertClose(skeleton.bonePose(BoneKind.Chest).position, [0, 1, 0])
+})
+
+test('trackers', () => {
+  const skeleton = makeSkeleton()
+  const hmd = skeleton.attachInputTracker(BoneKind.Neck, new Float32Array([0, 2, 0]))
+  const hip = skeleton.addOutputTracker(
+    BoneKind.Hip,
+    new Float32Array([0, 0, 0.1]),
+    IDENTITY,
+  )
+  skeleton.calibrate(ResetKind.Full, [
+    { tracker: hmd, position: new Float32Array([0, 2, 0]), rotation: IDENTITY },
+  ])
+  skeleton.solve()
+  assertClose(skeleton.outputTrackerPose(hip).position, [0, 0, 0.1])
+
+  skeleton.updateInputTracker(hmd, new Float32Array([0, 3, 0]))
+  skeleton.solve()
+  assertClose(skeleton.outputTrackerPose(hip).position, [0, 1, 0.1])
+
+  // Output trackers are not input trackers.
+  assert.throws(() => skeleton.detachInputTracker(hip))
+  assert.throws(() => skeleton.updateInputTracker(hmd, new Float32Array([0, 1])))
+  skeleton.detachInputTracker(hmd)
+  assert.throws(() => skeleton.solve())
+})
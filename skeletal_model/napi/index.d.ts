@@ -0,0 +1,130 @@
+/* tslint:disable */
+/* eslint-disable */
+
+/* auto-generated by NAPI-RS */
+
+/**
+ * The different kinds of bones in the skeleton. See the rust docs for
+ * `skeletal_model::BoneKind`.
+ */
+export const enum BoneKind {
+  Head = 0,
+  Neck = 1,
+  UpperChest = 2,
+  Chest = 3,
+  Waist = 4,
+  Hip = 5,
+  ThighL = 6,
+  ThighR = 7,
+  AnkleL = 8,
+  AnkleR = 9,
+  FootL = 10,
+  FootR = 11,
+  ToesL = 12,
+  ToesR = 13,
+  ShoulderL = 14,
+  ShoulderR = 15,
+  UpperArmL = 16,
+  UpperArmR = 17,
+  ForearmL = 18,
+  ForearmR = 19,
+  HandL = 20,
+  HandR = 21,
+  ThumbProximalL = 22,
+  ThumbIntermediateL = 23,
+  ThumbDistalL = 24,
+  IndexProximalL = 25,
+  IndexIntermediateL = 26,
+  IndexDistalL = 27,
+  MiddleProximalL = 28,
+  MiddleIntermediateL = 29,
+  MiddleDistalL = 30,
+  RingProximalL = 31,
+  RingIntermediateL = 32,
+  RingDistalL = 33,
+  LittleProximalL = 34,
+  LittleIntermediateL = 35,
+  LittleDistalL = 36,
+  ThumbProximalR = 37,
+  ThumbIntermediateR = 38,
+  ThumbDistalR = 39,
+  IndexProximalR = 40,
+  IndexIntermediateR = 41,
+  IndexDistalR = 42,
+  MiddleProximalR = 43,
+  MiddleIntermediateR = 44,
+  MiddleDistalR = 45,
+  RingProximalR = 46,
+  RingIntermediateR = 47,
+  RingDistalR = 48,
+  LittleProximalR = 49,
+  LittleIntermediateR = 50,
+  LittleDistalR = 51
+}
+/** The data of one tracker, captured while the user stands in the calibration pose. */
+export interface CalibrationSample {
+  tracker: number
+  /** `[x, y, z]`, only for trackers with positional data. */
+  position?: Float32Array
+  /** `[x, y, z, w]` */
+  rotation: Float32Array
+}
+/** The position and rotation of a bone or tracker, in global space. */
+export interface Pose {
+  /** `[x, y, z]` */
+  position: Float32Array
+  /** `[x, y, z, w]` */
+  rotation: Float32Array
+}
+/**
+ * The different kinds of calibration. See the rust docs for
+ * `skeletal_model::skeleton::ResetKind`.
+ */
+export const enum ResetKind {
+  Full = 0,
+  Yaw = 1,
+  Mounting = 2
+}
+/** Maps every `BoneKind` to a number. Used for bone lengths. */
+export class BoneMap {
+  /** Creates a map where every bone has the value `fill`. */
+  constructor(fill?: number | undefined | null)
+  get(bone: BoneKind): number
+  set(bone: BoneKind, value: number): void
+  /** The values of the map, indexed by `BoneKind`. */
+  toArray(): Float32Array
+}
+/**
+ * Models the pose of a human wearing FBT. See the rust docs for
+ * `skeletal_model::Skeleton`.
+ */
+export class Skeleton {
+  constructor(config: SkeletonConfig)
+  /** Solves for the pose of the skeleton. */
+  solve(): void
+  /**
+   * The pose of a bone, as of the last `solve()`. The position is that of the
+   * head of the bone.
+   */
+  bonePose(bone: BoneKind): Pose
+  boneLength(bone: BoneKind): number
+  /** Attaches an input tracker to `bone`, and returns its id. */
+  attachInputTracker(bone: BoneKind, position?: Float32Array | undefined | null, rotation?: Float32Array | undefined | null): number
+  /** Updates the latest data of an input tracker. */
+  updateInputTracker(tracker: number, position?: Float32Array | undefined | null, rotation?: Float32Array | undefined | null): void
+  detachInputTracker(tracker: number): void
+  /**
+   * Adds an output tracker to `bone`, and returns its id. The offset is relative to
+   * the head of the bone, in the local frame of the bone.
+   */
+  addOutputTracker(bone: BoneKind, position: Float32Array, rotation: Float32Array): number
+  removeOutputTracker(tracker: number): void
+  /** The pose of an output tracker, as of the last `solve()`. */
+  outputTrackerPose(tracker: number): Pose
+  /** Calibrates the input trackers while the user stands in the calibration pose. */
+  calibrate(kind: ResetKind, samples: Array<CalibrationSample>): void
+}
+/** Used to initialize the `Skeleton` with its initial parameters. */
+export class SkeletonConfig {
+  constructor(boneLengths: BoneMap)
+}
@@ -0,0 +1,2 @@
+// Loads the native addon built by `napi build`.
+module.exports = require('./skeletal_model.node')
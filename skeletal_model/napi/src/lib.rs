@@ -1,14 +1,359 @@
-pub fn add(left: usize, right: usize) -> usize {
-	left + right
+//! Node.js bindings for the [`skeletal_model`] crate.
+//!
+//! The API mirrors the rust one, with a few differences to make it feel at home in
+//! JavaScript:
+//! * Positions are `Float32Array`s of `[x, y, z]`, and rotations are quaternions as
+//!   `Float32Array`s of `[x, y, z, w]`.
+//! * Tracker ids are plain numbers.
+//! * Errors are thrown as exceptions.
+//!
+//! The typescript definitions in `index.d.ts` are generated from this file by
+//! `napi build`, and committed so that they can be read without building.
+
+#[macro_use]
+extern crate napi_derive;
+
+use napi::bindgen_prelude::{Error, Float32Array, Result};
+use skeletal_model::skeleton::{self as sk, CalibrationData, ResetKind as SkResetKind};
+use skeletal_model::{Global, Isometry, Local, Point, Translation, UnitQuat};
+
+use nalgebra::Quaternion;
+
+/// Declares `BoneKind`, and its conversion to [`skeletal_model::BoneKind`].
+macro_rules! bone_kind {
+	($($bone:ident),* $(,)?) => {
+		/// The different kinds of bones in the skeleton. See the rust docs for
+		/// `skeletal_model::BoneKind`.
+		#[napi]
+		pub enum BoneKind {
+			$($bone),*
+		}
+		impl From<BoneKind> for skeletal_model::BoneKind {
+			fn from(other: BoneKind) -> Self {
+				match other {
+					$(BoneKind::$bone => Self::$bone),*
+				}
+			}
+		}
+		/// Every `BoneKind`, in the order they were declared.
+		#[cfg(test)]
+		const BONE_KINDS: &[BoneKind] = &[$(BoneKind::$bone),*];
+	};
+}
+bone_kind!(
+	Head,
+	Neck,
+	UpperChest,
+	Chest,
+	Waist,
+	Hip,
+	ThighL,
+	ThighR,
+	AnkleL,
+	AnkleR,
+	FootL,
+	FootR,
+	ToesL,
+	ToesR,
+	ShoulderL,
+	ShoulderR,
+	UpperArmL,
+	UpperArmR,
+	ForearmL,
+	ForearmR,
+	HandL,
+	HandR,
+	ThumbProximalL,
+	ThumbIntermediateL,
+	ThumbDistalL,
+	IndexProximalL,
+	IndexIntermediateL,
+	IndexDistalL,
+	MiddleProximalL,
+	MiddleIntermediateL,
+	MiddleDistalL,
+	RingProximalL,
+	RingIntermediateL,
+	RingDistalL,
+	LittleProximalL,
+	LittleIntermediateL,
+	LittleDistalL,
+	ThumbProximalR,
+	ThumbIntermediateR,
+	ThumbDistalR,
+	IndexProximalR,
+	IndexIntermediateR,
+	IndexDistalR,
+	MiddleProximalR,
+	MiddleIntermediateR,
+	MiddleDistalR,
+	RingProximalR,
+	RingIntermediateR,
+	RingDistalR,
+	LittleProximalR,
+	LittleIntermediateR,
+	LittleDistalR,
+);
+
+/// Maps every `BoneKind` to a number. Used for bone lengths.
+#[napi]
+pub struct BoneMap(skeletal_model::BoneMap<f32>);
+#[napi]
+impl BoneMap {
+	/// Creates a map where every bone has the value `fill`.
+	#[napi(constructor)]
+	pub fn new(fill: Option<f64>) -> Self {
+		let fill = fill.unwrap_or_default() as f32;
+		Self(skeletal_model::BoneMap::new(
+			[fill; skeletal_model::BoneKind::NUM_TYPES],
+		))
+	}
+
+	#[napi]
+	pub fn get(&self, bone: BoneKind) -> f64 {
+		self.0[bone.into()].into()
+	}
+
+	#[napi]
+	pub fn set(&mut self, bone: BoneKind, value: f64) {
+		self.0[bone.into()] = value as f32;
+	}
+
+	/// The values of the map, indexed by `BoneKind`.
+	#[napi]
+	pub fn to_array(&self) -> Float32Array {
+		Float32Array::new(self.0.iter().map(|(_bone, v)| *v).collect())
+	}
+}
+
+/// Used to initialize the `Skeleton` with its initial parameters.
+#[napi]
+pub struct SkeletonConfig(sk::SkeletonConfig);
+#[napi]
+impl SkeletonConfig {
+	#[napi(constructor)]
+	pub fn new(bone_lengths: &BoneMap) -> Self {
+		Self(sk::SkeletonConfig::new(bone_lengths.0))
+	}
+}
+
+/// The position and rotation of a bone or tracker, in global space.
+#[napi(object)]
+pub struct Pose {
+	/// `[x, y, z]`
+	pub position: Float32Array,
+	/// `[x, y, z, w]`
+	pub rotation: Float32Array,
+}
+impl From<Isometry> for Pose {
+	fn from(other: Isometry) -> Self {
+		Self {
+			position: Float32Array::new(other.translation.vector.as_slice().to_vec()),
+			rotation: Float32Array::new(other.rotation.coords.as_slice().to_vec()),
+		}
+	}
+}
+
+/// The different kinds of calibration. See the rust docs for
+/// `skeletal_model::skeleton::ResetKind`.
+#[napi]
+pub enum ResetKind {
+	Full,
+	Yaw,
+	Mounting,
+}
+impl From<ResetKind> for SkResetKind {
+	fn from(other: ResetKind) -> Self {
+		match other {
+			ResetKind::Full => Self::Full,
+			ResetKind::Yaw => Self::Yaw,
+			ResetKind::Mounting => Self::Mounting,
+		}
+	}
+}
+
+/// The data of one tracker, captured while the user stands in the calibration pose.
+#[napi(object)]
+pub struct CalibrationSample {
+	pub tracker: u32,
+	/// `[x, y, z]`, only for trackers with positional data.
+	pub position: Option<Float32Array>,
+	/// `[x, y, z, w]`
+	pub rotation: Float32Array,
+}
+
+/// Models the pose of a human wearing FBT. See the rust docs for
+/// `skeletal_model::Skeleton`.
+#[napi]
+pub struct Skeleton(skeletal_model::Skeleton);
+#[napi]
+impl Skeleton {
+	#[napi(constructor)]
+	pub fn new(config: &SkeletonConfig) -> Self {
+		Self(skeletal_model::Skeleton::new(&config.0))
+	}
+
+	/// Solves for the pose of the skeleton.
+	#[napi]
+	pub fn solve(&mut self) -> Result<()> {
+		self.0.solve().map_err(to_js)?;
+		Ok(())
+	}
+
+	/// The pose of a bone, as of the last `solve()`. The position is that of the
+	/// head of the bone.
+	#[napi]
+	pub fn bone_pose(&self, bone: BoneKind) -> Pose {
+		self.0.bone_pose(bone.into()).0.into()
+	}
+
+	#[napi]
+	pub fn bone_length(&self, bone: BoneKind) -> f64 {
+		self.0[skeletal_model::BoneKind::from(bone)].length.into()
+	}
+
+	/// Attaches an input tracker to `bone`, and returns its id.
+	#[napi]
+	pub fn attach_input_tracker(
+		&mut self,
+		bone: BoneKind,
+		position: Option<Float32Array>,
+		rotation: Option<Float32Array>,
+	) -> Result<u32> {
+		let pos = position.as_deref().map(to_point).transpose()?;
+		let rot = rotation.as_deref().map(to_quat).transpose()?;
+		let id =
+			self.0
+				.attach_input_tracker(bone.into(), pos.map(Global), rot.map(Global));
+		Ok(id.into())
+	}
+
+	/// Updates the latest data of an input tracker.
+	#[napi]
+	pub fn update_input_tracker(
+		&mut self,
+		tracker: u32,
+		position: Option<Float32Array>,
+		rotation: Option<Float32Array>,
+	) -> Result<()> {
+		let pos = position.as_deref().map(to_point).transpose()?;
+		let rot = rotation.as_deref().map(to_quat).transpose()?;
+		self.0
+			.update_input_tracker(tracker.into(), pos.map(Global), rot.map(Global))
+			.map_err(to_js)
+	}
+
+	#[napi]
+	pub fn detach_input_tracker(&mut self, tracker: u32) -> Result<()> {
+		self.0.detach_input_tracker(tracker.into()).map_err(to_js)
+	}
+
+	/// Adds an output tracker to `bone`, and returns its id. The offset is relative to
+	/// the head of the bone, in the local frame of the bone.
+	#[napi]
+	pub fn add_output_tracker(
+		&mut self,
+		bone: BoneKind,
+		position: Float32Array,
+		rotation: Float32Array,
+	) -> Result<u32> {
+		let offset = to_isometry(&position, &rotation)?;
+		Ok(self.0.add_output_tracker(bone.into(), Local(offset)).into())
+	}
+
+	#[napi]
+	pub fn remove_output_tracker(&mut self, tracker: u32) -> Result<()> {
+		self.0.remove_output_tracker(tracker.into()).map_err(to_js)
+	}
+
+	/// The pose of an output tracker, as of the last `solve()`.
+	#[napi]
+	pub fn output_tracker_pose(&self, tracker: u32) -> Result<Pose> {
+		let pose = self.0.output_tracker_pose(tracker.into()).map_err(to_js)?;
+		Ok(pose.0.into())
+	}
+
+	/// Calibrates the input trackers while the user stands in the calibration pose.
+	#[napi]
+	pub fn calibrate(
+		&mut self,
+		kind: ResetKind,
+		samples: Vec<CalibrationSample>,
+	) -> Result<()> {
+		let samples = samples
+			.iter()
+			.map(|s| {
+				let rot = Global(to_quat(&s.rotation)?);
+				let data = match s.position.as_deref() {
+					Some(pos) => CalibrationData::SixDof {
+						pos: Global(to_point(pos)?),
+						rot,
+					},
+					None => CalibrationData::ThreeDof { rot },
+				};
+				Ok((s.tracker.into(), data))
+			})
+			.collect::<Result<Vec<_>>>()?;
+		self.0.calibrate(kind.into(), samples).map_err(to_js)
+	}
+}
+
+// ---- Conversion helpers ----
+
+fn to_js(err: impl std::error::Error) -> Error {
+	Error::from_reason(err.to_string())
+}
+
+fn to_point(arr: &[f32]) -> Result<Point> {
+	match *arr {
+		[x, y, z] => Ok(Point::new(x, y, z)),
+		_ => Err(Error::from_reason("positions must have 3 elements")),
+	}
+}
+
+fn to_quat(arr: &[f32]) -> Result<UnitQuat> {
+	match *arr {
+		[x, y, z, w] => Ok(UnitQuat::from_quaternion(Quaternion::new(w, x, y, z))),
+		_ => Err(Error::from_reason("rotations must have 4 elements")),
+	}
+}
+
+fn to_isometry(pos: &[f32], rot: &[f32]) -> Result<Isometry> {
+	let trans = Translation::from(to_point(pos)?.coords);
+	Ok(Isometry::from_parts(trans, to_quat(rot)?))
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
 
+	/// The bones are listed by hand, so make sure they match the rust ones.
+	#[test]
+	fn test_bone_kinds() {
+		let expected: Vec<_> = skeletal_model::BoneKind::iter().collect();
+		assert_eq!(BONE_KINDS.len(), expected.len());
+		for (i, (&bone, expected)) in BONE_KINDS.iter().zip(expected).enumerate() {
+			assert_eq!(bone as usize, i);
+			assert_eq!(skeletal_model::BoneKind::from(bone), expected);
+		}
+	}
+
+	/// `index.d.ts` is only regenerated by `napi build`, so make sure its bones match
+	/// the rust ones.
 	#[test]
-	fn it_works() {
-		let result = add(2, 2);
-		assert_eq!(result, 4);
+	fn test_type_defs() {
+		let defs = include_str!("../index.d.ts");
+		let start = defs.find("export const enum BoneKind {").unwrap();
+		let end = start + defs[start..].find('}').unwrap();
+		let bones: Vec<_> = defs[start..end]
+			.lines()
+			.skip(1)
+			.map(|line| line.trim().trim_end_matches(','))
+			.collect();
+		let expected: Vec<_> = skeletal_model::BoneKind::iter()
+			.enumerate()
+			.map(|(i, bone)| format!("{bone:?} = {i}"))
+			.collect();
+		assert_eq!(bones, expected);
 	}
 }
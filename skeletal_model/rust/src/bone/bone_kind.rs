@@ -1,5 +1,6 @@
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::FromPrimitive;
+use std::f32::consts::FRAC_PI_2;
 
 use crate::{
 	conventions::{forward_vec, up_vec},
@@ -32,12 +33,24 @@ use crate::{
 /// these values are contiguous and start at 0.** Use the variant directly or refer to
 /// the various functions implemented on this type for stability.
 ///
+/// With the `serde` feature, bones are serialized as their name in `snake_case`, such
+/// as `"upper_arm_l"`. Unlike the integer values, these names are stable.
+///
 /// [`const`]: https://doc.rust-lang.org/std/keyword.const.html
 /// [`BoneMap`]: super::BoneMap
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, FromPrimitive, ToPrimitive)]
+#[derive(
+	Debug, Clone, Copy, Eq, Hash, PartialEq, Ord, PartialOrd, FromPrimitive, ToPrimitive,
+)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize, serde::Deserialize),
+	serde(rename_all = "snake_case")
+)]
 pub enum BoneKind {
-	Neck = 0,
+	Head = 0,
+	Neck,
+	UpperChest,
 	Chest,
 	Waist,
 	Hip,
@@ -47,18 +60,54 @@ pub enum BoneKind {
 	AnkleR,
 	FootL,
 	FootR,
+	ToesL,
+	ToesR,
 
+	ShoulderL,
+	ShoulderR,
 	UpperArmL,
 	UpperArmR,
 	ForearmL,
 	ForearmR,
-	WristL,
-	WristR,
+	HandL,
+	HandR,
+
+	// Each finger has three phalanges, ordered from the knuckle to the fingertip.
+	ThumbProximalL,
+	ThumbIntermediateL,
+	ThumbDistalL,
+	IndexProximalL,
+	IndexIntermediateL,
+	IndexDistalL,
+	MiddleProximalL,
+	MiddleIntermediateL,
+	MiddleDistalL,
+	RingProximalL,
+	RingIntermediateL,
+	RingDistalL,
+	LittleProximalL,
+	LittleIntermediateL,
+	LittleDistalL,
+	ThumbProximalR,
+	ThumbIntermediateR,
+	ThumbDistalR,
+	IndexProximalR,
+	IndexIntermediateR,
+	IndexDistalR,
+	MiddleProximalR,
+	MiddleIntermediateR,
+	MiddleDistalR,
+	RingProximalR,
+	RingIntermediateR,
+	RingDistalR,
+	LittleProximalR,
+	LittleIntermediateR,
+	LittleDistalR,
 }
 impl BoneKind {
 	/// The bone with the largest integer value
 	pub const fn max() -> BoneKind {
-		BoneKind::WristR
+		BoneKind::LittleDistalR
 	}
 	pub const MAX: BoneKind = Self::max();
 
@@ -70,7 +119,7 @@ impl BoneKind {
 
 	/// The root bone of the skeletal graph/tree.
 	pub const fn root() -> Self {
-		Self::Neck
+		Self::Head
 	}
 	pub const ROOT: BoneKind = Self::root();
 
@@ -89,7 +138,9 @@ impl BoneKind {
 	pub const fn children(&self) -> &'static [Self] {
 		use BoneKind::*;
 		match self {
-			Neck => &[Chest, UpperArmL, UpperArmR],
+			Head => &[Neck],
+			Neck => &[UpperChest, ShoulderL, ShoulderR],
+			UpperChest => &[Chest],
 			Chest => &[Waist],
 			Waist => &[Hip],
 			Hip => &[ThighL, ThighR],
@@ -97,15 +148,62 @@ impl BoneKind {
 			ThighR => &[AnkleR],
 			AnkleL => &[FootL],
 			AnkleR => &[FootR],
-			FootL => &[],
-			FootR => &[],
+			FootL => &[ToesL],
+			FootR => &[ToesR],
+			ToesL => &[],
+			ToesR => &[],
 
+			ShoulderL => &[UpperArmL],
+			ShoulderR => &[UpperArmR],
 			UpperArmL => &[ForearmL],
 			UpperArmR => &[ForearmR],
-			ForearmL => &[WristL],
-			ForearmR => &[WristR],
-			WristR => &[],
-			WristL => &[],
+			ForearmL => &[HandL],
+			ForearmR => &[HandR],
+
+			HandL => &[
+				ThumbProximalL,
+				IndexProximalL,
+				MiddleProximalL,
+				RingProximalL,
+				LittleProximalL,
+			],
+			ThumbProximalL => &[ThumbIntermediateL],
+			ThumbIntermediateL => &[ThumbDistalL],
+			ThumbDistalL => &[],
+			IndexProximalL => &[IndexIntermediateL],
+			IndexIntermediateL => &[IndexDistalL],
+			IndexDistalL => &[],
+			MiddleProximalL => &[MiddleIntermediateL],
+			MiddleIntermediateL => &[MiddleDistalL],
+			MiddleDistalL => &[],
+			RingProximalL => &[RingIntermediateL],
+			RingIntermediateL => &[RingDistalL],
+			RingDistalL => &[],
+			LittleProximalL => &[LittleIntermediateL],
+			LittleIntermediateL => &[LittleDistalL],
+			LittleDistalL => &[],
+			HandR => &[
+				ThumbProximalR,
+				IndexProximalR,
+				MiddleProximalR,
+				RingProximalR,
+				LittleProximalR,
+			],
+			ThumbProximalR => &[ThumbIntermediateR],
+			ThumbIntermediateR => &[ThumbDistalR],
+			ThumbDistalR => &[],
+			IndexProximalR => &[IndexIntermediateR],
+			IndexIntermediateR => &[IndexDistalR],
+			IndexDistalR => &[],
+			MiddleProximalR => &[MiddleIntermediateR],
+			MiddleIntermediateR => &[MiddleDistalR],
+			MiddleDistalR => &[],
+			RingProximalR => &[RingIntermediateR],
+			RingIntermediateR => &[RingDistalR],
+			RingDistalR => &[],
+			LittleProximalR => &[LittleIntermediateR],
+			LittleIntermediateR => &[LittleDistalR],
+			LittleDistalR => &[],
 		}
 	}
 
@@ -113,8 +211,10 @@ impl BoneKind {
 	pub const fn parent(&self) -> Option<BoneKind> {
 		use BoneKind::*;
 		Some(match self {
-			Neck => return None,
-			Chest => Neck,
+			Head => return None,
+			Neck => Head,
+			UpperChest => Neck,
+			Chest => UpperChest,
 			Waist => Chest,
 			Hip => Waist,
 			ThighL => Hip,
@@ -123,13 +223,48 @@ impl BoneKind {
 			AnkleR => ThighR,
 			FootL => AnkleL,
 			FootR => AnkleR,
+			ToesL => FootL,
+			ToesR => FootR,
 
-			UpperArmL => Neck,
-			UpperArmR => Neck,
+			ShoulderL => Neck,
+			ShoulderR => Neck,
+			UpperArmL => ShoulderL,
+			UpperArmR => ShoulderR,
 			ForearmL => UpperArmL,
 			ForearmR => UpperArmR,
-			WristL => ForearmL,
-			WristR => ForearmR,
+			HandL => ForearmL,
+			HandR => ForearmR,
+
+			ThumbProximalL => HandL,
+			ThumbIntermediateL => ThumbProximalL,
+			ThumbDistalL => ThumbIntermediateL,
+			IndexProximalL => HandL,
+			IndexIntermediateL => IndexProximalL,
+			IndexDistalL => IndexIntermediateL,
+			MiddleProximalL => HandL,
+			MiddleIntermediateL => MiddleProximalL,
+			MiddleDistalL => MiddleIntermediateL,
+			RingProximalL => HandL,
+			RingIntermediateL => RingProximalL,
+			RingDistalL => RingIntermediateL,
+			LittleProximalL => HandL,
+			LittleIntermediateL => LittleProximalL,
+			LittleDistalL => LittleIntermediateL,
+			ThumbProximalR => HandR,
+			ThumbIntermediateR => ThumbProximalR,
+			ThumbDistalR => ThumbIntermediateR,
+			IndexProximalR => HandR,
+			IndexIntermediateR => IndexProximalR,
+			IndexDistalR => IndexIntermediateR,
+			MiddleProximalR => HandR,
+			MiddleIntermediateR => MiddleProximalR,
+			MiddleDistalR => MiddleIntermediateR,
+			RingProximalR => HandR,
+			RingIntermediateR => RingProximalR,
+			RingDistalR => RingIntermediateR,
+			LittleProximalR => HandR,
+			LittleIntermediateR => LittleProximalR,
+			LittleDistalR => LittleIntermediateR,
 		})
 	}
 
@@ -137,12 +272,84 @@ impl BoneKind {
 		(Self::MIN as u8..=Self::MAX as u8).map(|x| x.try_into().unwrap())
 	}
 
+	/// The name of the bone in `snake_case`, which is how configs refer to it.
+	pub fn snake_case_name(self) -> String {
+		let mut name = String::new();
+		for (i, c) in format!("{self:?}").chars().enumerate() {
+			if c.is_ascii_uppercase() && i > 0 {
+				name.push('_');
+			}
+			name.push(c.to_ascii_lowercase());
+		}
+		name
+	}
+
+	/// The bone on the other side of the body, like [`BoneKind::ThighR`] for
+	/// [`BoneKind::ThighL`]. Bones in the middle of the body are their own mirror.
+	pub const fn mirror(self) -> Self {
+		use BoneKind::*;
+		match self {
+			Head | Neck | UpperChest | Chest | Waist | Hip => self,
+			ThighL => ThighR,
+			ThighR => ThighL,
+			AnkleL => AnkleR,
+			AnkleR => AnkleL,
+			FootL => FootR,
+			FootR => FootL,
+			ToesL => ToesR,
+			ToesR => ToesL,
+			ShoulderL => ShoulderR,
+			ShoulderR => ShoulderL,
+			UpperArmL => UpperArmR,
+			UpperArmR => UpperArmL,
+			ForearmL => ForearmR,
+			ForearmR => ForearmL,
+			HandL => HandR,
+			HandR => HandL,
+			ThumbProximalL => ThumbProximalR,
+			ThumbProximalR => ThumbProximalL,
+			ThumbIntermediateL => ThumbIntermediateR,
+			ThumbIntermediateR => ThumbIntermediateL,
+			ThumbDistalL => ThumbDistalR,
+			ThumbDistalR => ThumbDistalL,
+			IndexProximalL => IndexProximalR,
+			IndexProximalR => IndexProximalL,
+			IndexIntermediateL => IndexIntermediateR,
+			IndexIntermediateR => IndexIntermediateL,
+			IndexDistalL => IndexDistalR,
+			IndexDistalR => IndexDistalL,
+			MiddleProximalL => MiddleProximalR,
+			MiddleProximalR => MiddleProximalL,
+			MiddleIntermediateL => MiddleIntermediateR,
+			MiddleIntermediateR => MiddleIntermediateL,
+			MiddleDistalL => MiddleDistalR,
+			MiddleDistalR => MiddleDistalL,
+			RingProximalL => RingProximalR,
+			RingProximalR => RingProximalL,
+			RingIntermediateL => RingIntermediateR,
+			RingIntermediateR => RingIntermediateL,
+			RingDistalL => RingDistalR,
+			RingDistalR => RingDistalL,
+			LittleProximalL => LittleProximalR,
+			LittleProximalR => LittleProximalL,
+			LittleIntermediateL => LittleIntermediateR,
+			LittleIntermediateR => LittleIntermediateL,
+			LittleDistalL => LittleDistalR,
+			LittleDistalR => LittleDistalL,
+		}
+	}
+
 	/// Returns the initial calibration pose of the bone. Rotating the up vector by
 	/// this rotation would cause it to point in the same target direction as the bone.
 	pub fn calibration_rotation(self) -> Global<UnitQuat> {
 		use BoneKind::*;
 		Global(match self {
-			FootL | FootR => UnitQuat::look_at_rh(&-up_vec(), &forward_vec()),
+			FootL | FootR | ToesL | ToesR => {
+				UnitQuat::look_at_rh(&-up_vec(), &forward_vec())
+			}
+			// The shoulders point sideways, away from the neck.
+			ShoulderL => UnitQuat::from_axis_angle(&forward_vec(), FRAC_PI_2),
+			ShoulderR => UnitQuat::from_axis_angle(&forward_vec(), -FRAC_PI_2),
 			_ => UnitQuat::default(),
 		})
 	}
@@ -178,3 +385,52 @@ impl From<BoneKind> for usize {
 		other as _
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::conventions::mirror_rot;
+
+	#[test]
+	fn test_hierarchy() {
+		for b in BoneKind::iter() {
+			for child in b.children() {
+				assert_eq!(child.parent(), Some(b));
+			}
+			match b.parent() {
+				Some(parent) => assert!(parent.children().contains(&b)),
+				None => assert_eq!(b, BoneKind::root()),
+			}
+		}
+		assert_eq!(BoneKind::iter().count(), BoneKind::NUM_TYPES);
+	}
+
+	#[test]
+	fn test_snake_case_name() {
+		assert_eq!(BoneKind::Head.snake_case_name(), "head");
+		assert_eq!(
+			BoneKind::ThumbProximalL.snake_case_name(),
+			"thumb_proximal_l"
+		);
+		#[cfg(feature = "serde")]
+		for b in BoneKind::iter() {
+			assert_eq!(serde_json::to_value(b).unwrap(), b.snake_case_name());
+		}
+	}
+
+	#[test]
+	fn test_mirror() {
+		assert_eq!(BoneKind::ThighL.mirror(), BoneKind::ThighR);
+		assert_eq!(BoneKind::LittleDistalR.mirror(), BoneKind::LittleDistalL);
+		assert_eq!(BoneKind::Chest.mirror(), BoneKind::Chest);
+		for b in BoneKind::iter() {
+			assert_eq!(b.mirror().mirror(), b);
+			// Mirroring keeps the hierarchy.
+			assert_eq!(b.mirror().parent(), b.parent().map(|p| p.mirror()));
+			// Mirrored bones start out mirrored.
+			let rot = b.calibration_rotation().0;
+			let mirrored = b.mirror().calibration_rotation().0;
+			assert!(rot.angle_to(&mirror_rot(&mirrored)) < 1e-6, "{b:?}");
+		}
+	}
+}
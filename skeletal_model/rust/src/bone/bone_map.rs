@@ -23,8 +23,15 @@ use std::iter::{Enumerate, Map};
 ///
 /// assert_eq!(m[BoneKind::Chest], format!("this is a {:?}", BoneKind::Chest))
 /// ```
-#[derive(Debug, Default, Clone, Copy, From, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, From, Eq, PartialEq)]
 pub struct BoneMap<T>([T; BoneKind::num_types()]);
+// `Default` can't be derived, the standard library only implements it for arrays of
+// up to 32 elements.
+impl<T: Default> Default for BoneMap<T> {
+	fn default() -> Self {
+		Self(std::array::from_fn(|_| T::default()))
+	}
+}
 impl<T> BoneMap<T> {
 	pub fn new(map: [T; BoneKind::num_types()]) -> Self {
 		Self(map)
@@ -51,6 +58,17 @@ impl<T> BoneMap<T> {
 		let it = self.into_iter().map(|(kind, item)| (kind, f(kind, item)));
 		it.try_collect().unwrap()
 	}
+
+	/// Swaps the values of the left and right bones, as in [`BoneKind::mirror()`].
+	pub fn mirrored(&self) -> Self
+	where
+		T: Clone,
+	{
+		Self(std::array::from_fn(|i| {
+			let bone = BoneKind::try_from(i).unwrap();
+			self[bone.mirror()].clone()
+		}))
+	}
 }
 
 // ---- Type conversion stuff ----
@@ -80,6 +98,36 @@ impl<T> TryFromIterator<(BoneKind, T)> for BoneMap<T> {
 	}
 }
 
+// ---- Serde stuff ----
+
+/// Serializes as a map from [`BoneKind`] to `T`.
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for BoneMap<T> {
+	fn serialize<S: serde::Serializer>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		serializer.collect_map(self.iter())
+	}
+}
+
+/// Deserializes from a map from [`BoneKind`] to `T`. Every bone must be present.
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for BoneMap<T> {
+	fn deserialize<D: serde::Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Self, D::Error> {
+		use serde::de::Error;
+
+		let map = HashMap::<BoneKind, T>::deserialize(deserializer)?;
+		if let Some(missing) = BoneKind::iter().find(|b| !map.contains_key(b)) {
+			let missing = missing.snake_case_name();
+			return Err(D::Error::custom(format!("missing bone {missing}")));
+		}
+		Ok(Self::try_from(map).unwrap())
+	}
+}
+
 // ---- Index stuff ----
 impl<T> Index<BoneKind> for BoneMap<T> {
 	type Output = T;
@@ -159,6 +207,33 @@ mod tests {
 		assert_eq!(nones, BoneMap::default())
 	}
 
+	#[test]
+	fn test_mirrored() {
+		let map = BoneMap::new([0u8; BoneKind::NUM_TYPES]).map(|kind, _| kind as u8);
+		let mirrored = map.mirrored();
+		assert_eq!(mirrored[BoneKind::HandL], BoneKind::HandR as u8);
+		assert_eq!(mirrored[BoneKind::HandR], BoneKind::HandL as u8);
+		assert_eq!(mirrored[BoneKind::Hip], BoneKind::Hip as u8);
+		assert_eq!(mirrored.mirrored(), map);
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn test_serde() {
+		let map = BoneMap::new([0u8; BoneKind::NUM_TYPES]).map(|kind, _| kind as u8);
+		let json = serde_json::to_value(map).unwrap();
+		assert_eq!(json["upper_arm_l"], BoneKind::UpperArmL as u8);
+		assert_eq!(
+			serde_json::from_value::<BoneMap<u8>>(json.clone()).unwrap(),
+			map
+		);
+
+		let mut partial = json;
+		partial.as_object_mut().unwrap().remove("hip");
+		let err = serde_json::from_value::<BoneMap<u8>>(partial).unwrap_err();
+		assert!(err.to_string().contains("missing bone hip"), "{err}");
+	}
+
 	#[test]
 	fn test_map() {
 		let zeros = BoneMap::new([0u8; BoneKind::NUM_TYPES]);
@@ -5,7 +5,9 @@
 
 mod bone_kind;
 pub mod bone_map;
+mod proportions;
 
 pub use self::bone_kind::BoneKind;
 #[doc(inline)]
 pub use self::bone_map::BoneMap;
+pub use self::proportions::BodyProportions;
@@ -0,0 +1,186 @@
+use super::{BoneKind, BoneMap};
+
+/// The length of each bone, as a fraction of the user's height.
+///
+/// This is used to get reasonable bone lengths for a user before they have measured
+/// their body. See [`BodyProportions::bone_lengths()`].
+///
+/// The [`Default`] proportions are those of an average adult, based on the
+/// anthropometric segment ratios of [Drillis and Contini][drillis]. The bones from the
+/// headset down to the ankle, plus the height of the ankle, add up to the eye height
+/// of 93.6% of the user's height. There are also presets for other body types, such
+/// as [`BodyProportions::child()`].
+///
+/// [drillis]: https://en.wikipedia.org/wiki/Body_proportions
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize, serde::Deserialize),
+	serde(transparent)
+)]
+pub struct BodyProportions(pub BoneMap<f32>);
+impl BodyProportions {
+	/// The proportions of an average adult. This is also the [`Default`].
+	pub fn average_adult() -> Self {
+		use BoneKind::*;
+		let ratios =
+			BoneMap::new([0.; BoneKind::NUM_TYPES]).map(|kind, _| match kind {
+				// From the eyes to the base of the skull.
+				Head => 0.063,
+				Neck => 0.055,
+				// The torso, from the shoulders to the hip joints, is 28.8% of the height.
+				UpperChest => 0.070,
+				Chest => 0.080,
+				Waist => 0.080,
+				Hip => 0.058,
+				ThighL | ThighR => 0.245,
+				AnkleL | AnkleR => 0.246,
+				// The foot is 15.2% of the height, and its head is above the heel.
+				FootL | FootR => 0.110,
+				ToesL | ToesR => 0.040,
+
+				ShoulderL | ShoulderR => 0.100,
+				UpperArmL | UpperArmR => 0.186,
+				ForearmL | ForearmR => 0.146,
+				// The hand is 10.8% of the height, including the fingers.
+				HandL | HandR => 0.060,
+				ThumbProximalL | ThumbProximalR => 0.016,
+				ThumbIntermediateL | ThumbIntermediateR => 0.011,
+				ThumbDistalL | ThumbDistalR => 0.009,
+				IndexProximalL | IndexProximalR => 0.019,
+				IndexIntermediateL | IndexIntermediateR => 0.012,
+				IndexDistalL | IndexDistalR => 0.011,
+				MiddleProximalL | MiddleProximalR => 0.021,
+				MiddleIntermediateL | MiddleIntermediateR => 0.015,
+				MiddleDistalL | MiddleDistalR => 0.012,
+				RingProximalL | RingProximalR => 0.020,
+				RingIntermediateL | RingIntermediateR => 0.014,
+				RingDistalL | RingDistalR => 0.011,
+				LittleProximalL | LittleProximalR => 0.016,
+				LittleIntermediateL | LittleIntermediateR => 0.011,
+				LittleDistalL | LittleDistalR => 0.009,
+			});
+		Self(ratios)
+	}
+
+	/// The proportions of an average adult woman. Compared to
+	/// [`BodyProportions::average_adult()`], the shoulders are narrower and the arms
+	/// are slightly shorter.
+	pub fn adult_female() -> Self {
+		use BoneKind::*;
+		Self::average_adult().with(|kind| match kind {
+			ShoulderL | ShoulderR => Some(0.092),
+			UpperArmL | UpperArmR => Some(0.182),
+			ForearmL | ForearmR => Some(0.142),
+			_ => None,
+		})
+	}
+
+	/// The proportions of an average adult man. Compared to
+	/// [`BodyProportions::average_adult()`], the shoulders are wider and the arms are
+	/// slightly longer.
+	pub fn adult_male() -> Self {
+		use BoneKind::*;
+		Self::average_adult().with(|kind| match kind {
+			ShoulderL | ShoulderR => Some(0.108),
+			UpperArmL | UpperArmR => Some(0.189),
+			ForearmL | ForearmR => Some(0.150),
+			_ => None,
+		})
+	}
+
+	/// The proportions of a child of about six years. The head is about a sixth of the
+	/// height instead of an eighth, so the eyes are at 91.6% of the height, and the
+	/// legs and arms are relatively shorter.
+	pub fn child() -> Self {
+		use BoneKind::*;
+		let adult = Self::average_adult();
+		// The fingers keep the same proportions to the hand as an adult's.
+		let hand = 0.055;
+		let finger_scale = hand / adult.0[HandL];
+		adult.with(|kind| match kind {
+			Head => Some(0.075),
+			Neck => Some(0.050),
+			UpperChest => Some(0.072),
+			Chest => Some(0.082),
+			Hip => Some(0.060),
+			ThighL | ThighR => Some(0.225),
+			AnkleL | AnkleR => Some(0.233),
+			FootL | FootR => Some(0.100),
+
+			ShoulderL | ShoulderR => Some(0.095),
+			UpperArmL | UpperArmR => Some(0.170),
+			ForearmL | ForearmR => Some(0.135),
+			HandL | HandR => Some(hand),
+			Waist | ToesL | ToesR => None,
+			// Everything else is a finger.
+			_ => Some(adult.0[kind] * finger_scale),
+		})
+	}
+
+	/// Replaces the ratios of the bones that `f` returns `Some` for.
+	fn with(self, f: impl Fn(BoneKind) -> Option<f32>) -> Self {
+		Self(self.0.map(|kind, ratio| f(kind).unwrap_or(ratio)))
+	}
+
+	/// Computes the length of each bone for a user that is `height` tall.
+	pub fn bone_lengths(&self, height: f32) -> BoneMap<f32> {
+		self.0.map(|_kind, ratio| ratio * height)
+	}
+}
+impl Default for BodyProportions {
+	fn default() -> Self {
+		Self::average_adult()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use approx::assert_relative_eq;
+
+	/// The height of the eyes, as a fraction of the height.
+	fn eye_height(proportions: &BodyProportions) -> f32 {
+		use BoneKind::*;
+		let lengths = proportions.bone_lengths(1.8);
+		// Only the ankle joint is missing, which is 3.9% of the height above the floor.
+		let eye_height: f32 =
+			[Head, Neck, UpperChest, Chest, Waist, Hip, ThighL, AnkleL]
+				.iter()
+				.map(|b| lengths[*b])
+				.sum();
+		(eye_height + 0.039 * 1.8) / 1.8
+	}
+
+	#[test]
+	fn test_eye_height() {
+		let presets = [
+			(BodyProportions::default(), 0.936),
+			(BodyProportions::adult_female(), 0.936),
+			(BodyProportions::adult_male(), 0.936),
+			(BodyProportions::child(), 0.916),
+		];
+		for (proportions, expected) in presets {
+			assert_relative_eq!(eye_height(&proportions), expected, epsilon = 1e-3);
+			for (_bone, length) in proportions.bone_lengths(1.8).iter() {
+				assert!(*length > 0.);
+			}
+		}
+	}
+
+	#[test]
+	fn test_presets() {
+		use BoneKind::*;
+		let female = BodyProportions::adult_female().0;
+		let male = BodyProportions::adult_male().0;
+		let child = BodyProportions::child().0;
+		let adult = BodyProportions::average_adult().0;
+		assert!(female[ShoulderL] < adult[ShoulderL]);
+		assert!(male[ShoulderL] > adult[ShoulderL]);
+		assert!(child[Head] > adult[Head]);
+		assert!(child[ThighL] < adult[ThighL]);
+		assert!(child[IndexDistalR] < adult[IndexDistalR]);
+		assert_eq!(female[ThighL], adult[ThighL]);
+	}
+}
@@ -26,6 +26,11 @@
 //!   particular bone in the skeleton. Technically there are many local spaces, one for
 //!   each bone. We usually omit specifying which bone the space belongs to, as its
 //!   implied that we are referring to the parent bone.
+//!
+//! # Mirroring
+//! Mirroring reflects across the sagittal plane, which divides the body into its left
+//! and right halves. The plane goes through the origin, and [`right_vec()`] is normal
+//! to it. See [`mirror_vec()`] and [`mirror_rot()`].
 
 use nalgebra::{Unit, Vector3};
 use num_traits::Zero;
@@ -66,7 +71,7 @@ pub fn right_vec() -> Unit<Vector3<f32>> {
 /// ```
 /// # use approx::assert_relative_eq;
 /// # use nalgebra::{Unit, Vector3};
-/// # use skeletal_model::prelude::UnitQuat;
+/// # use skeletal_model::UnitQuat;
 /// # use skeletal_model::conventions::{up_vec, forward_vec, look_towards};
 /// let dir = Vector3::new(1.0, 2.0, 3.0);
 /// let q = look_towards(&dir, &up_vec());
@@ -84,6 +89,23 @@ pub fn look_towards(dir: &Vector3<f32>, up: &Vector3<f32>) -> UnitQuat {
 	UnitQuat::face_towards(&-dir, up)
 }
 
+/// Reflects `v` across the sagittal plane, by negating its component along
+/// [`right_vec()`].
+#[inline]
+pub fn mirror_vec(v: &Vector3<f32>) -> Vector3<f32> {
+	v - right_vec().into_inner() * (2. * v.dot(&right_vec()))
+}
+
+/// Reflects `rot` across the sagittal plane. Rotating a vector by the result is the same
+/// as mirroring the vector, rotating it by `rot`, and mirroring it back.
+#[inline]
+pub fn mirror_rot(rot: &UnitQuat) -> UnitQuat {
+	// Reflecting the rotation axis turns it into a pseudovector, which flips the
+	// direction of the rotation.
+	let axis = mirror_vec(&rot.imag());
+	UnitQuat::new_unchecked(nalgebra::Quaternion::from_parts(rot.w, -axis))
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -94,6 +116,16 @@ mod tests {
 
 	use super::look_towards;
 
+	#[test]
+	fn test_mirror() {
+		let v = Vector3::new(1., 2., 3.);
+		assert_relative_eq!(mirror_vec(&v), Vector3::new(-1., 2., 3.));
+		let rot = UnitQuat::from_euler_angles(0.1, 0.2, 0.3);
+		let mirrored = mirror_rot(&rot);
+		assert_relative_eq!(mirrored * mirror_vec(&v), mirror_vec(&(rot * v)));
+		assert_relative_eq!(mirror_rot(&mirrored), rot);
+	}
+
 	/// Example and sanity check of how to use various functions from `nalgebra` to
 	/// describe rotations.
 	#[test]
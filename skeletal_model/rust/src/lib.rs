@@ -70,11 +70,14 @@
 pub mod bone;
 pub mod conventions;
 mod newtypes;
+pub mod recording;
 pub mod skeleton;
 
 pub use crate::bone::{BoneKind, BoneMap};
-pub use crate::skeleton::Skeleton;
+pub use crate::newtypes::{Global, Local};
+pub use crate::skeleton::{Skeleton, TrackerId};
 
+pub type Isometry = nalgebra::Isometry3<f32>;
 pub type Translation = nalgebra::Translation3<f32>;
 pub type UnitQuat = nalgebra::UnitQuaternion<f32>;
 pub type Point = nalgebra::Point3<f32>;
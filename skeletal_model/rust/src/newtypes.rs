@@ -2,7 +2,12 @@
 
 /// A newtype on `T` that indicates that it is a global transform. See also
 /// [`crate::conventions`].
-#[derive(Debug, PartialEq, Eq, Default)]
+#[derive(Debug, PartialEq, Eq, Default, Clone, Copy)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize, serde::Deserialize),
+	serde(transparent)
+)]
 pub struct Global<T: private::Sealed>(pub T);
 
 /// Implements `From<T> for $ident<T>`
@@ -20,7 +25,12 @@ impl_helper!(Local);
 
 /// A newtype on `T` that indicates that it is a local transform. See also
 /// [`crate::conventions`].
-#[derive(Debug, PartialEq, Eq, Default)]
+#[derive(Debug, PartialEq, Eq, Default, Clone, Copy)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize, serde::Deserialize),
+	serde(transparent)
+)]
 pub struct Local<T: private::Sealed>(pub T);
 
 mod private {
@@ -33,4 +43,5 @@ mod private {
 	impl Sealed for crate::Translation {}
 	impl Sealed for crate::UnitQuat {}
 	impl Sealed for crate::Point {}
+	impl Sealed for crate::Isometry {}
 }
@@ -0,0 +1,353 @@
+//! Buffers solved poses, so that they can be sampled at a different rate than the one
+//! they were solved at.
+
+use super::PoseFrame;
+use crate::bone::BoneMap;
+use crate::newtypes::Global;
+use crate::{BoneKind, Point};
+
+use nalgebra::Vector3;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+/// Settings for a [`PoseBuffer`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoseBufferSettings {
+	/// The maximum number of frames that the buffer holds. Older frames get dropped.
+	pub capacity: usize,
+	/// How far past the newest frame, in seconds, the pose may be predicted. Sampling
+	/// any later than that gives the same pose as sampling at the limit. Zero turns
+	/// prediction off.
+	pub max_prediction: f32,
+	/// Smooths the frames as they get pushed, if it is `Some`.
+	pub filter: Option<OneEuroSettings>,
+}
+impl Default for PoseBufferSettings {
+	fn default() -> Self {
+		Self {
+			capacity: 16,
+			max_prediction: 0.,
+			filter: None,
+		}
+	}
+}
+
+/// Settings for a [One-Euro filter][1euro], which smooths out jitter when the input
+/// moves slowly, and lags behind less when it moves quickly.
+///
+/// [1euro]: https://gery.casiez.net/1euro/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OneEuroSettings {
+	/// The cutoff frequency in Hz when the input is still. Lower values smooth more.
+	pub min_cutoff: f32,
+	/// How much the cutoff frequency increases with speed. Higher values lag less.
+	pub beta: f32,
+	/// The cutoff frequency in Hz of the filter on the velocity itself.
+	pub derivative_cutoff: f32,
+}
+impl Default for OneEuroSettings {
+	fn default() -> Self {
+		Self {
+			min_cutoff: 1.,
+			beta: 0.5,
+			derivative_cutoff: 1.,
+		}
+	}
+}
+impl OneEuroSettings {
+	/// The smoothing factor of a low-pass filter with `cutoff`, over `dt` seconds.
+	fn alpha(cutoff: f32, dt: f32) -> f32 {
+		let tau = 1. / (2. * PI * cutoff);
+		1. / (1. + tau / dt)
+	}
+}
+
+/// Stores timestamped solved poses, and samples them at arbitrary times.
+///
+/// Between two frames, the pose is interpolated. Past the newest frame, the pose is
+/// extrapolated from the velocity between the two newest frames, up to
+/// [`PoseBufferSettings::max_prediction`].
+///
+/// The prediction and the One-Euro filter work differently from the ones in the
+/// SlimeVR server. To match the server, use a [`ServerFilter`](super::ServerFilter).
+#[derive(Debug, Clone)]
+pub struct PoseBuffer {
+	settings: PoseBufferSettings,
+	frames: VecDeque<PoseFrame>,
+	/// The filtered velocity of the root, and the filtered angular velocity of every
+	/// bone.
+	velocities: Option<(Vector3<f32>, BoneMap<Vector3<f32>>)>,
+}
+impl PoseBuffer {
+	pub fn new(settings: PoseBufferSettings) -> Self {
+		Self {
+			settings,
+			frames: VecDeque::with_capacity(settings.capacity),
+			velocities: None,
+		}
+	}
+
+	pub fn settings(&self) -> &PoseBufferSettings {
+		&self.settings
+	}
+
+	/// The frames in the buffer, from oldest to newest. If there is a filter, these are
+	/// the filtered frames.
+	pub fn frames(&self) -> impl Iterator<Item = &PoseFrame> + '_ {
+		self.frames.iter()
+	}
+
+	/// Removes all frames, and resets the filter.
+	pub fn clear(&mut self) {
+		self.frames.clear();
+		self.velocities = None;
+	}
+
+	/// Adds a frame to the buffer, which must be newer than all the others and have a
+	/// finite timestamp.
+	pub fn push(&mut self, mut frame: PoseFrame) -> Result<(), PoseBufferError> {
+		if !frame.timestamp.is_finite() {
+			return Err(PoseBufferError::NonFinite(frame.timestamp));
+		}
+		if let Some(newest) = self.frames.back() {
+			if frame.timestamp <= newest.timestamp {
+				return Err(PoseBufferError::OutOfOrder {
+					timestamp: frame.timestamp,
+					newest: newest.timestamp,
+				});
+			}
+			if let Some(filter) = self.settings.filter {
+				frame = self.filter(&filter, frame);
+			}
+		}
+		if self.frames.len() >= self.settings.capacity {
+			self.frames.pop_front();
+		}
+		if self.settings.capacity > 0 {
+			self.frames.push_back(frame);
+		}
+		Ok(())
+	}
+
+	/// Gets the pose at `time`, in the same clock as the timestamps of the frames.
+	/// Before the oldest frame, this is the oldest frame. Returns `None` if the buffer
+	/// is empty, or if `time` is NaN.
+	pub fn sample(&self, time: f32) -> Option<PoseFrame> {
+		if time.is_nan() {
+			return None;
+		}
+		let newest = self.frames.back()?;
+		if time >= newest.timestamp {
+			let time = time.min(newest.timestamp + self.settings.max_prediction);
+			let Some(previous) = self.frames.iter().nth_back(1) else {
+				return Some(PoseFrame {
+					timestamp: time,
+					..newest.clone()
+				});
+			};
+			return Some(previous.interpolate(newest, time));
+		}
+		let next = self.frames.iter().position(|f| f.timestamp > time).unwrap();
+		let Some(previous) = next.checked_sub(1) else {
+			return self.frames.front().cloned();
+		};
+		Some(self.frames[previous].interpolate(&self.frames[next], time))
+	}
+
+	/// Applies the One-Euro filter to `frame`, relative to the newest frame.
+	fn filter(&mut self, settings: &OneEuroSettings, frame: PoseFrame) -> PoseFrame {
+		let prev = self.frames.back().unwrap();
+		let dt = frame.timestamp - prev.timestamp;
+		let (root_vel, bone_vels) =
+			self.velocities.get_or_insert_with(Default::default);
+		let d_alpha = OneEuroSettings::alpha(settings.derivative_cutoff, dt);
+		// Filters the velocity, and returns how far to move towards the new value.
+		let step = |filtered_vel: &mut Vector3<f32>, vel: Vector3<f32>| {
+			*filtered_vel += (vel - *filtered_vel) * d_alpha;
+			let cutoff = settings.min_cutoff + settings.beta * filtered_vel.norm();
+			OneEuroSettings::alpha(cutoff, dt)
+		};
+
+		let delta = frame.root_pos.0 - prev.root_pos.0;
+		let alpha = step(root_vel, delta / dt);
+		let root_pos = prev.root_pos.0 + delta * alpha;
+		let bone_rots = prev.bone_rots.map(|bone, prev_rot| {
+			let rot = frame.bone_rots[bone].0;
+			let angular_vel = (rot * prev_rot.0.inverse()).scaled_axis() / dt;
+			let alpha = step(&mut bone_vels[bone], angular_vel);
+			Global(prev_rot.0.slerp(&rot, alpha))
+		});
+		PoseFrame {
+			timestamp: frame.timestamp,
+			root_pos: Global(root_pos),
+			bone_rots,
+		}
+	}
+}
+
+impl PoseFrame {
+	/// Interpolates between `self` and `other` at `time`. Rotations are spherically
+	/// interpolated, and the root position is linearly interpolated. A `time` outside
+	/// of the two timestamps extrapolates, continuing with the same velocity.
+	pub fn interpolate(&self, other: &PoseFrame, time: f32) -> PoseFrame {
+		let span = other.timestamp - self.timestamp;
+		let t = if span == 0. {
+			1.
+		} else {
+			(time - self.timestamp) / span
+		};
+		let root: Vector3<f32> = other.root_pos.0 - self.root_pos.0;
+		let bone_rots = BoneMap::default().map(|bone: BoneKind, ()| {
+			let (from, to) = (self.bone_rots[bone].0, other.bone_rots[bone].0);
+			// Unlike `slerp()`, this also works for `t` outside of `[0, 1]`.
+			let delta = to * from.inverse();
+			Global(delta.powf(t) * from)
+		});
+		PoseFrame {
+			timestamp: time,
+			root_pos: Global(Point::from(self.root_pos.0.coords + root * t)),
+			bone_rots,
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum PoseBufferError {
+	#[error("Frame at {timestamp} is not newer than the newest frame, at {newest}")]
+	OutOfOrder { timestamp: f32, newest: f32 },
+	#[error("Frame has a timestamp of {0}, which is not finite")]
+	NonFinite(f32),
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::UnitQuat;
+
+	use approx::assert_relative_eq;
+
+	/// A frame with the root at `x`, and every bone turned by `angle` around Y.
+	fn frame(timestamp: f32, x: f32, angle: f32) -> PoseFrame {
+		let rot = UnitQuat::from_axis_angle(&Vector3::y_axis(), angle);
+		PoseFrame {
+			timestamp,
+			root_pos: Global(Point::new(x, 1., 0.)),
+			bone_rots: BoneMap::default().map(|_, ()| Global(rot)),
+		}
+	}
+
+	fn assert_frame(actual: &PoseFrame, expected: &PoseFrame) {
+		assert_relative_eq!(actual.timestamp, expected.timestamp, epsilon = 1e-5);
+		assert_relative_eq!(actual.root_pos.0, expected.root_pos.0, epsilon = 1e-5);
+		for bone in BoneKind::iter() {
+			assert_relative_eq!(
+				actual.bone_rots[bone].0,
+				expected.bone_rots[bone].0,
+				epsilon = 1e-5
+			);
+		}
+	}
+
+	#[test]
+	fn test_interpolation() {
+		let mut buffer = PoseBuffer::new(PoseBufferSettings::default());
+		assert_eq!(buffer.sample(0.), None);
+		buffer.push(frame(0., 0., 0.)).unwrap();
+		assert_frame(&buffer.sample(1.).unwrap(), &frame(0., 0., 0.));
+		buffer.push(frame(0.1, 1., 1.)).unwrap();
+		buffer.push(frame(0.3, 2., 3.)).unwrap();
+
+		assert_frame(&buffer.sample(-1.).unwrap(), &frame(0., 0., 0.));
+		assert_frame(&buffer.sample(0.05).unwrap(), &frame(0.05, 0.5, 0.5));
+		assert_frame(&buffer.sample(0.25).unwrap(), &frame(0.25, 1.75, 2.5));
+		// Without prediction, the newest frame is held.
+		assert_frame(&buffer.sample(0.5).unwrap(), &frame(0.3, 2., 3.));
+
+		assert_eq!(
+			buffer.push(frame(0.3, 0., 0.)),
+			Err(PoseBufferError::OutOfOrder {
+				timestamp: 0.3,
+				newest: 0.3
+			})
+		);
+	}
+
+	#[test]
+	fn test_non_finite() {
+		let mut buffer = PoseBuffer::new(PoseBufferSettings::default());
+		buffer.push(frame(0., 0., 0.)).unwrap();
+		buffer.push(frame(0.1, 1., 1.)).unwrap();
+		for timestamp in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+			assert!(matches!(
+				buffer.push(frame(timestamp, 0., 0.)),
+				Err(PoseBufferError::NonFinite(_))
+			));
+		}
+		assert_eq!(buffer.frames().count(), 2);
+
+		assert_eq!(buffer.sample(f32::NAN), None);
+		assert_frame(&buffer.sample(f32::INFINITY).unwrap(), &frame(0.1, 1., 1.));
+		assert_frame(
+			&buffer.sample(f32::NEG_INFINITY).unwrap(),
+			&frame(0., 0., 0.),
+		);
+	}
+
+	#[test]
+	fn test_prediction() {
+		let mut buffer = PoseBuffer::new(PoseBufferSettings {
+			max_prediction: 0.1,
+			..Default::default()
+		});
+		buffer.push(frame(0., 0., 0.)).unwrap();
+		buffer.push(frame(0.1, 0.1, 0.5)).unwrap();
+		// Both the position and the angular velocity are extrapolated.
+		assert_frame(&buffer.sample(0.15).unwrap(), &frame(0.15, 0.15, 0.75));
+		assert_frame(&buffer.sample(1.).unwrap(), &frame(0.2, 0.2, 1.));
+	}
+
+	#[test]
+	fn test_capacity() {
+		let mut buffer = PoseBuffer::new(PoseBufferSettings {
+			capacity: 2,
+			..Default::default()
+		});
+		for i in 0..5 {
+			buffer.push(frame(i as f32, 0., 0.)).unwrap();
+		}
+		let timestamps: Vec<_> = buffer.frames().map(|f| f.timestamp).collect();
+		assert_eq!(timestamps, [3., 4.]);
+	}
+
+	#[test]
+	fn test_one_euro() {
+		let mut buffer = PoseBuffer::new(PoseBufferSettings {
+			capacity: 1,
+			filter: Some(OneEuroSettings {
+				beta: 5.,
+				..Default::default()
+			}),
+			..Default::default()
+		});
+		// Jitter around a still pose gets smoothed out.
+		for i in 0..60 {
+			let noise = if i % 2 == 0 { 0.01 } else { -0.01 };
+			buffer.push(frame(i as f32 / 60., noise, noise)).unwrap();
+		}
+		let newest = buffer.frames().next().unwrap();
+		assert!(newest.root_pos.0.x.abs() < 0.002);
+		assert!(newest.bone_rots[BoneKind::Hip].0.angle() < 0.002);
+
+		// A constant fast motion is followed closely, because the cutoff goes up.
+		buffer.clear();
+		for i in 0..60 {
+			let t = i as f32 / 60.;
+			buffer.push(frame(t, 2. * t, t)).unwrap();
+		}
+		let newest = buffer.frames().next().unwrap();
+		assert!((newest.root_pos.0.x - 2. * newest.timestamp).abs() < 0.1);
+		assert!(
+			(newest.bone_rots[BoneKind::Hip].0.angle() - newest.timestamp).abs() < 0.1
+		);
+	}
+}
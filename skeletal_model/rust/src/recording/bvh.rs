@@ -0,0 +1,130 @@
+//! Exports a [`PoseRecording`] to the BVH format.
+
+use super::{visit_bones, PoseRecording};
+use crate::bone::BoneMap;
+use crate::BoneKind;
+
+use nalgebra::Vector3;
+use std::io::{self, Write};
+
+impl PoseRecording {
+	/// Writes the recording in the [BVH] format, which most animation software can
+	/// import as motion capture.
+	///
+	/// BVH only supports a fixed frame rate, so the frames are assumed to be evenly
+	/// spaced, [`Self::frame_time()`] apart. Joint rotations are in degrees, in the
+	/// order Z, Y, X.
+	///
+	/// [BVH]: https://research.cs.wisc.edu/graphics/Courses/cs-838-1999/Jeff/BVH.html
+	pub fn write_bvh(&self, mut w: impl Write) -> io::Result<()> {
+		writeln!(w, "HIERARCHY")?;
+		self.write_joint(&mut w, BoneKind::root(), 0, &self.rest_offsets())?;
+
+		// The channels are in the same depth-first order as the joints.
+		let mut order = Vec::with_capacity(BoneKind::NUM_TYPES);
+		visit_bones(|bone, _depth| order.push(bone));
+
+		writeln!(w, "MOTION")?;
+		writeln!(w, "Frames: {}", self.frames.len())?;
+		writeln!(w, "Frame Time: {:.6}", self.frame_time())?;
+		for frame in &self.frames {
+			let joints = frame.joint_rotations();
+			let mut line = vector(&frame.root_pos.0.coords);
+			for bone in &order {
+				let (x, y, z) = joints[*bone].euler_angles();
+				let zyx = Vector3::new(z, y, x).map(f32::to_degrees);
+				line.push(' ');
+				line.push_str(&vector(&zyx));
+			}
+			writeln!(w, "{line}")?;
+		}
+		Ok(())
+	}
+
+	/// Writes the joint of `bone` and all of its children.
+	fn write_joint(
+		&self,
+		w: &mut impl Write,
+		bone: BoneKind,
+		depth: usize,
+		offsets: &BoneMap<Vector3<f32>>,
+	) -> io::Result<()> {
+		let indent = "\t".repeat(depth);
+		if depth == 0 {
+			writeln!(w, "ROOT {bone:?}")?;
+		} else {
+			writeln!(w, "{indent}JOINT {bone:?}")?;
+		}
+		writeln!(w, "{indent}{{")?;
+		writeln!(w, "{indent}\tOFFSET {}", vector(&offsets[bone]))?;
+		if depth == 0 {
+			writeln!(
+				w,
+				"{indent}\tCHANNELS 6 Xposition Yposition Zposition Zrotation Yrotation \
+				 Xrotation"
+			)?;
+		} else {
+			writeln!(w, "{indent}\tCHANNELS 3 Zrotation Yrotation Xrotation")?;
+		}
+		for child in bone.children() {
+			self.write_joint(w, *child, depth + 1, offsets)?;
+		}
+		// Without an end site, the length of the last bone would be lost.
+		if bone.children().is_empty() {
+			writeln!(w, "{indent}\tEnd Site")?;
+			writeln!(w, "{indent}\t{{")?;
+			let offset = self.rest_head_to_tail(bone);
+			writeln!(w, "{indent}\t\tOFFSET {}", vector(&offset))?;
+			writeln!(w, "{indent}\t}}")?;
+		}
+		writeln!(w, "{indent}}}")
+	}
+}
+
+fn vector(v: &Vector3<f32>) -> String {
+	// Avoids printing "-0.000000".
+	let v = v.map(|x| if x.abs() < 5e-7 { 0. } else { x });
+	format!("{:.6} {:.6} {:.6}", v.x, v.y, v.z)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::tests::make_recording;
+
+	use std::path::Path;
+
+	/// Compares the output of the solver against a known good recording. To update
+	/// the golden file after an intentional change, run the test with
+	/// `UPDATE_GOLDEN=1`.
+	#[test]
+	fn test_golden_bvh() {
+		let mut bvh = Vec::new();
+		make_recording().write_bvh(&mut bvh).unwrap();
+		let bvh = String::from_utf8(bvh).unwrap();
+
+		let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/squat.bvh");
+		if std::env::var_os("UPDATE_GOLDEN").is_some() {
+			std::fs::write(&path, &bvh).unwrap();
+		}
+		let golden = std::fs::read_to_string(&path).unwrap();
+
+		// Numbers may differ slightly between platforms, so they are compared with a
+		// tolerance.
+		let actual_lines: Vec<_> = bvh.lines().collect();
+		let golden_lines: Vec<_> = golden.lines().collect();
+		assert_eq!(actual_lines.len(), golden_lines.len());
+		for (i, (actual, golden)) in actual_lines.iter().zip(golden_lines).enumerate() {
+			let actual: Vec<_> = actual.split_whitespace().collect();
+			let golden: Vec<_> = golden.split_whitespace().collect();
+			assert_eq!(actual.len(), golden.len(), "line {}", i + 1);
+			for (a, g) in actual.iter().zip(golden) {
+				match (a.parse::<f32>(), g.parse::<f32>()) {
+					(Ok(a), Ok(g)) => {
+						assert!((a - g).abs() < 1e-3, "line {}: {a} != {g}", i + 1)
+					}
+					_ => assert_eq!(*a, g, "line {}", i + 1),
+				}
+			}
+		}
+	}
+}
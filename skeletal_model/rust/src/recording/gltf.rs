@@ -0,0 +1,305 @@
+//! Exports a [`PoseRecording`] to the binary form of the glTF format.
+//!
+//! The file has a node for every joint, a skin that turns those nodes into an armature,
+//! and an animation with a rotation channel for every joint, plus a translation
+//! channel for the root. All the data is in a single buffer, laid out as:
+//! 1. The timestamps of the frames.
+//! 2. The translations of the root joint.
+//! 3. The rotations of each joint, one joint after the other.
+//! 4. The inverse bind matrices of the skin.
+
+use super::{visit_bones, PoseRecording};
+use crate::bone::BoneMap;
+use crate::BoneKind;
+
+use nalgebra::Vector3;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// `FLOAT` in the `componentType` of accessors.
+const FLOAT: u32 = 5126;
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: &[u8; 4] = b"JSON";
+const CHUNK_BIN: &[u8; 4] = b"BIN\0";
+
+impl PoseRecording {
+	/// Writes the recording as a binary [glTF] (`.glb`) file, with an animation that
+	/// has a keyframe for every frame.
+	///
+	/// [glTF]: https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html
+	pub fn write_glb(&self, mut w: impl Write) -> io::Result<()> {
+		let mut order = Vec::with_capacity(BoneKind::NUM_TYPES);
+		visit_bones(|bone, _depth| order.push(bone));
+		let node_of = |bone: BoneKind| order.iter().position(|b| *b == bone).unwrap();
+
+		let offsets = self.rest_offsets();
+		let mut rest_pos = BoneMap::<Vector3<f32>>::default();
+		for bone in &order {
+			if let Some(parent) = bone.parent() {
+				rest_pos[*bone] = rest_pos[parent] + offsets[*bone];
+			}
+		}
+
+		let mut buffer = Buffer::default();
+		let mut json = String::new();
+		let mut channels = Vec::new();
+		if !self.frames.is_empty() {
+			let timestamps: Vec<_> = self.frames.iter().map(|f| f.timestamp).collect();
+			let min = timestamps.iter().copied().fold(f32::INFINITY, f32::min);
+			let max = timestamps.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+			let times = buffer.push(&timestamps, "SCALAR", Some((min, max)));
+
+			let root: Vec<_> = self
+				.frames
+				.iter()
+				.flat_map(|f| f.root_pos.0.coords.data.0[0])
+				.collect();
+			let root = buffer.push(&root, "VEC3", None);
+			channels.push((times, root, node_of(BoneKind::root()), "translation"));
+
+			let joints: Vec<_> =
+				self.frames.iter().map(|f| f.joint_rotations()).collect();
+			for bone in &order {
+				let rots: Vec<_> = joints
+					.iter()
+					.flat_map(|j| j[*bone].coords.data.0[0])
+					.collect();
+				let rots = buffer.push(&rots, "VEC4", None);
+				channels.push((times, rots, node_of(*bone), "rotation"));
+			}
+		}
+		let ibms: Vec<_> = order
+			.iter()
+			.flat_map(|bone| {
+				let p = -rest_pos[*bone];
+				let mut m = [0.; 16];
+				m[0] = 1.;
+				m[5] = 1.;
+				m[10] = 1.;
+				m[12..15].copy_from_slice(p.as_slice());
+				m[15] = 1.;
+				m
+			})
+			.collect();
+		let ibms = buffer.push(&ibms, "MAT4", None);
+
+		// Assemble the JSON by hand, it is simple enough not to need a dependency.
+		json.push_str(r#"{"asset":{"version":"2.0","generator":"skeletal_model"},"#);
+		json.push_str(r#""scene":0,"scenes":[{"nodes":[0]}],"nodes":["#);
+		for (i, bone) in order.iter().enumerate() {
+			let sep = if i == 0 { "" } else { "," };
+			let children: Vec<_> = bone
+				.children()
+				.iter()
+				.map(|c| node_of(*c).to_string())
+				.collect();
+			let t = offsets[*bone];
+			write!(
+				json,
+				r#"{sep}{{"name":"{bone:?}","translation":[{},{},{}],"children":[{}]}}"#,
+				t.x,
+				t.y,
+				t.z,
+				children.join(",")
+			)
+			.unwrap();
+		}
+		let joints: Vec<_> = (0..order.len()).map(|i| i.to_string()).collect();
+		write!(
+			json,
+			r#"],"skins":[{{"joints":[{}],"skeleton":0,"inverseBindMatrices":{ibms}}}],"#,
+			joints.join(",")
+		)
+		.unwrap();
+		if !channels.is_empty() {
+			let mut samplers = Vec::new();
+			let mut targets = Vec::new();
+			for (i, (input, output, node, path)) in channels.iter().enumerate() {
+				samplers.push(format!(
+					r#"{{"input":{input},"output":{output},"interpolation":"LINEAR"}}"#
+				));
+				targets.push(format!(
+					r#"{{"sampler":{i},"target":{{"node":{node},"path":"{path}"}}}}"#
+				));
+			}
+			write!(
+				json,
+				r#""animations":[{{"name":"Recording","samplers":[{}],"channels":[{}]}}],"#,
+				samplers.join(","),
+				targets.join(",")
+			)
+			.unwrap();
+		}
+		buffer.write_json(&mut json);
+		json.push('}');
+
+		write_glb(&mut w, json.into_bytes(), buffer.data)
+	}
+}
+
+/// The binary buffer of the file, along with the accessors and buffer views that
+/// describe its contents. Each accessor gets its own buffer view.
+#[derive(Default)]
+struct Buffer {
+	data: Vec<u8>,
+	accessors: Vec<String>,
+	views: Vec<String>,
+}
+impl Buffer {
+	/// Adds `values` to the buffer, and returns the index of their accessor.
+	fn push(
+		&mut self,
+		values: &[f32],
+		kind: &str,
+		min_max: Option<(f32, f32)>,
+	) -> usize {
+		let components = match kind {
+			"SCALAR" => 1,
+			"VEC3" => 3,
+			"VEC4" => 4,
+			"MAT4" => 16,
+			_ => unreachable!("unsupported accessor type {kind}"),
+		};
+		let offset = self.data.len();
+		for v in values {
+			self.data.extend_from_slice(&v.to_le_bytes());
+		}
+		let index = self.accessors.len();
+		self.views.push(format!(
+			r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{}}}"#,
+			values.len() * 4
+		));
+		let min_max = min_max
+			.map(|(min, max)| format!(r#","min":[{min}],"max":[{max}]"#))
+			.unwrap_or_default();
+		self.accessors.push(format!(
+			r#"{{"bufferView":{index},"componentType":{FLOAT},"count":{},"type":"{kind}"{min_max}}}"#,
+			values.len() / components
+		));
+		index
+	}
+
+	fn write_json(&self, json: &mut String) {
+		write!(
+			json,
+			r#""buffers":[{{"byteLength":{}}}],"bufferViews":[{}],"accessors":[{}]"#,
+			self.data.len(),
+			self.views.join(","),
+			self.accessors.join(",")
+		)
+		.unwrap();
+	}
+}
+
+/// Writes the GLB container, which consists of a header, a JSON chunk and a binary
+/// chunk. Chunks are padded to a multiple of 4 bytes.
+fn write_glb(
+	w: &mut impl Write,
+	mut json: Vec<u8>,
+	mut bin: Vec<u8>,
+) -> io::Result<()> {
+	while json.len() % 4 != 0 {
+		json.push(b' ');
+	}
+	while bin.len() % 4 != 0 {
+		bin.push(0);
+	}
+	let length = 12 + 8 + json.len() + 8 + bin.len();
+	w.write_all(GLB_MAGIC)?;
+	w.write_all(&GLB_VERSION.to_le_bytes())?;
+	w.write_all(&(length as u32).to_le_bytes())?;
+	for (kind, chunk) in [(CHUNK_JSON, json), (CHUNK_BIN, bin)] {
+		w.write_all(&(chunk.len() as u32).to_le_bytes())?;
+		w.write_all(kind)?;
+		w.write_all(&chunk)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::tests::make_recording;
+	use super::*;
+
+	use approx::assert_relative_eq;
+
+	fn read_u32(bytes: &[u8]) -> usize {
+		u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize
+	}
+
+	fn read_f32s(bin: &[u8], offset: usize, count: usize) -> Vec<f32> {
+		bin[offset..offset + count * 4]
+			.chunks(4)
+			.map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+			.collect()
+	}
+
+	#[test]
+	fn test_glb() {
+		let recording = make_recording();
+		let mut glb = Vec::new();
+		recording.write_glb(&mut glb).unwrap();
+
+		assert_eq!(&glb[..4], GLB_MAGIC);
+		assert_eq!(read_u32(&glb[4..]), 2);
+		assert_eq!(read_u32(&glb[8..]), glb.len());
+		let json_len = read_u32(&glb[12..]);
+		assert_eq!(&glb[16..20], CHUNK_JSON);
+		let json: serde_json::Value =
+			serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+		let bin_start = 20 + json_len;
+		assert_eq!(&glb[bin_start + 4..bin_start + 8], CHUNK_BIN);
+		let bin = &glb[bin_start + 8..];
+
+		let nodes = json["nodes"].as_array().unwrap();
+		assert_eq!(nodes.len(), BoneKind::NUM_TYPES);
+		assert_eq!(nodes[0]["name"], "Head");
+		let animation = &json["animations"][0];
+		assert_eq!(
+			animation["channels"].as_array().unwrap().len(),
+			BoneKind::NUM_TYPES + 1
+		);
+
+		// Every accessor fits in the buffer.
+		let views = json["bufferViews"].as_array().unwrap();
+		let byte_length = json["buffers"][0]["byteLength"].as_u64().unwrap() as usize;
+		for view in views {
+			let end = view["byteOffset"].as_u64().unwrap()
+				+ view["byteLength"].as_u64().unwrap();
+			assert!(end as usize <= byte_length);
+		}
+
+		// Check the rotation of the left thigh in the last frame.
+		let node = nodes.iter().position(|n| n["name"] == "ThighL").unwrap();
+		let channel = animation["channels"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.find(|c| c["target"]["node"] == node && c["target"]["path"] == "rotation")
+			.unwrap();
+		let sampler =
+			&animation["samplers"][channel["sampler"].as_u64().unwrap() as usize];
+		let accessor = &json["accessors"][sampler["output"].as_u64().unwrap() as usize];
+		let view = &views[accessor["bufferView"].as_u64().unwrap() as usize];
+		let count = accessor["count"].as_u64().unwrap() as usize;
+		assert_eq!(count, recording.frames.len());
+		let offset = view["byteOffset"].as_u64().unwrap() as usize;
+		let rots = read_f32s(bin, offset, count * 4);
+		let expected = recording.frames[count - 1].joint_rotations()[BoneKind::ThighL];
+		assert_relative_eq!(rots[rots.len() - 4..], expected.coords.as_slice()[..]);
+	}
+
+	#[test]
+	fn test_empty() {
+		let mut recording = make_recording();
+		recording.frames.clear();
+		let mut glb = Vec::new();
+		recording.write_glb(&mut glb).unwrap();
+		let json_len = read_u32(&glb[12..]);
+		let json: serde_json::Value =
+			serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+		assert!(json.get("animations").is_none());
+		assert_eq!(json["accessors"].as_array().unwrap().len(), 1);
+	}
+}
@@ -0,0 +1,230 @@
+//! Records the solved pose of the [`Skeleton`] over time, and exports it to animation
+//! formats.
+//!
+//! A [`PoseRecording`] is a series of [`PoseFrame`]s, each of which holds the output
+//! rotation of every bone, the position of the root bone, and a timestamp. Recordings
+//! can be exported to:
+//! * [BVH], with [`PoseRecording::write_bvh()`].
+//! * [glTF] in its binary `.glb` form, with [`PoseRecording::write_glb()`].
+//!
+//! Poses can also be mirrored with [`PoseFrame::mirrored()`], and retargeted onto a
+//! skeleton with different bone lengths with [`PoseFrame::retargeted()`]. Use
+//! [`Skeleton::set_pose()`] to put a skeleton into a recorded pose.
+//!
+//! To consume poses at a different rate than they are solved at, such as the refresh
+//! rate of a display, push them into a [`PoseBuffer`] instead. It interpolates between
+//! frames, can predict a short time ahead, and can smooth them with a One-Euro filter.
+//! To smooth or predict rotations the same way as the SlimeVR server, use a
+//! [`ServerFilter`].
+//!
+//! Both formats describe the skeleton as a hierarchy of joints, one per [`BoneKind`],
+//! which follows [`BoneKind::children()`]. Each joint is at the head of its bone. In the
+//! rest pose of the joints, every bone is in its calibration pose, so an unrotated
+//! joint means that the bone has its [`BoneKind::calibration_rotation()`]. Distances
+//! are in meters, and the axes follow [`crate::conventions`].
+//!
+//! [BVH]: https://research.cs.wisc.edu/graphics/Courses/cs-838-1999/Jeff/BVH.html
+//! [glTF]: https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html
+
+mod buffer;
+mod bvh;
+mod gltf;
+mod retarget;
+mod server_filter;
+
+pub use buffer::{OneEuroSettings, PoseBuffer, PoseBufferError, PoseBufferSettings};
+pub use server_filter::{ServerFilter, ServerFilterKind, ServerFilterSettings};
+
+use crate::bone::BoneMap;
+use crate::conventions::up_vec;
+use crate::newtypes::Global;
+use crate::{BoneKind, Point, Skeleton, UnitQuat};
+
+use nalgebra::Vector3;
+
+/// A recording of the solved pose of a [`Skeleton`] over time. See the
+/// [`recording`](self) module.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoseRecording {
+	pub bone_lengths: BoneMap<f32>,
+	pub frames: Vec<PoseFrame>,
+}
+impl PoseRecording {
+	/// Creates an empty recording, using the bone lengths of `skeleton`.
+	pub fn new(skeleton: &Skeleton) -> Self {
+		Self {
+			bone_lengths: BoneMap::default().map(|bone, ()| skeleton[bone].length),
+			frames: Vec::new(),
+		}
+	}
+
+	/// Records the current pose of `skeleton`. This is typically done every frame, after
+	/// calling [`Skeleton::solve()`].
+	pub fn push_frame(&mut self, skeleton: &Skeleton, timestamp: f32) {
+		self.frames.push(PoseFrame::new(skeleton, timestamp));
+	}
+
+	/// The average time between frames, in seconds. Formats that only support a fixed
+	/// frame rate use this.
+	pub fn frame_time(&self) -> f32 {
+		match (self.frames.first(), self.frames.last()) {
+			(Some(first), Some(last)) if self.frames.len() > 1 => {
+				(last.timestamp - first.timestamp) / (self.frames.len() - 1) as f32
+			}
+			_ => DEFAULT_FRAME_TIME,
+		}
+	}
+
+	/// The position of the head of each bone relative to the head of its parent, in
+	/// the rest pose. The root bone has no parent, so its offset is zero.
+	fn rest_offsets(&self) -> BoneMap<Vector3<f32>> {
+		BoneMap::default().map(|bone, ()| match bone.parent() {
+			Some(parent) => {
+				let rot = parent.calibration_rotation().0;
+				-(rot * up_vec()).into_inner() * self.bone_lengths[parent]
+			}
+			None => Vector3::zeros(),
+		})
+	}
+
+	/// The vector from the head of `bone` to its tail, in the rest pose.
+	fn rest_head_to_tail(&self, bone: BoneKind) -> Vector3<f32> {
+		let rot = bone.calibration_rotation().0;
+		-(rot * up_vec()).into_inner() * self.bone_lengths[bone]
+	}
+}
+
+/// The rate that [`PoseRecording::frame_time()`] falls back to, when there are less
+/// than two frames.
+const DEFAULT_FRAME_TIME: f32 = 1. / 60.;
+
+/// The pose of the skeleton at a single point in time.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoseFrame {
+	/// The time of the frame, in seconds.
+	pub timestamp: f32,
+	/// The position of the head of the root bone.
+	pub root_pos: Global<Point>,
+	/// The global rotation of every bone, as in [`Skeleton::bone_pose()`].
+	pub bone_rots: BoneMap<Global<UnitQuat>>,
+}
+impl PoseFrame {
+	/// Captures the current pose of `skeleton`.
+	pub fn new(skeleton: &Skeleton, timestamp: f32) -> Self {
+		let root = skeleton.bone_pose(BoneKind::root()).0;
+		Self {
+			timestamp,
+			root_pos: Global(Point::from(root.translation.vector)),
+			bone_rots: BoneMap::default().map(|bone, ()| skeleton[bone].output_rot_g),
+		}
+	}
+
+	/// The rotation of each joint relative to its parent joint, where the identity is
+	/// the rest pose.
+	fn joint_rotations(&self) -> BoneMap<UnitQuat> {
+		// The rotation of each bone away from its calibration pose.
+		let from_rest = self
+			.bone_rots
+			.map(|bone, rot| rot.0 * bone.calibration_rotation().0.inverse());
+		from_rest.map(|bone, rot| match bone.parent() {
+			Some(parent) => from_rest[parent].inverse() * rot,
+			None => rot,
+		})
+	}
+}
+
+/// Visits every bone in depth-first order, starting at the root. `f` gets called with
+/// each bone and its depth in the hierarchy.
+fn visit_bones(mut f: impl FnMut(BoneKind, usize)) {
+	let mut stack = vec![(BoneKind::root(), 0)];
+	while let Some((bone, depth)) = stack.pop() {
+		f(bone, depth);
+		// Reversed, so that the first child is visited first.
+		stack.extend(bone.children().iter().rev().map(|&c| (c, depth + 1)));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::skeleton::SkeletonConfig;
+
+	use approx::assert_relative_eq;
+
+	/// Records the user squatting and raising their left arm. The headset is the only
+	/// positional tracker.
+	pub(super) fn make_recording() -> PoseRecording {
+		use BoneKind::*;
+		let mut skeleton = Skeleton::new(&SkeletonConfig::from_height(1.8));
+		let hmd = skeleton.attach_input_tracker(Head, None, None);
+		let trackers = [Chest, ThighL, ThighR, AnkleL, AnkleR, UpperArmL]
+			.map(|bone| (bone, skeleton.attach_input_tracker(bone, None, None)));
+
+		let mut recording = PoseRecording::new(&skeleton);
+		for i in 0..5 {
+			let t = i as f32 / 4.;
+			let angle =
+				|scale: f32| UnitQuat::from_axis_angle(&Vector3::x_axis(), scale * t);
+			let hmd_pos = Point::new(0., 1.7 - 0.3 * t, 0.);
+			skeleton
+				.update_input_tracker(hmd, Some(Global(hmd_pos)), None)
+				.unwrap();
+			for (bone, id) in trackers {
+				let rot = match bone {
+					Chest => angle(-0.4),
+					ThighL | ThighR => angle(1.),
+					AnkleL | AnkleR => angle(-1.),
+					_ => angle(2.),
+				};
+				let rot = Global(rot * bone.calibration_rotation().0);
+				skeleton.update_input_tracker(id, None, Some(rot)).unwrap();
+			}
+			skeleton.solve().unwrap();
+			recording.push_frame(&skeleton, t / 30.);
+		}
+		recording
+	}
+
+	#[test]
+	fn test_recording() {
+		let recording = make_recording();
+		assert_eq!(recording.frames.len(), 5);
+		assert_relative_eq!(recording.frame_time(), 1. / 120.);
+		assert_eq!(
+			recording.bone_lengths,
+			SkeletonConfig::from_height(1.8).bone_lengths
+		);
+
+		// The root is where the headset is.
+		let last = recording.frames.last().unwrap();
+		assert_relative_eq!(last.root_pos.0, Point::new(0., 1.4, 0.));
+
+		// Untracked bones stay in their calibration pose, like the hip below the
+		// upright waist.
+		let first = recording.frames[0].joint_rotations();
+		let joints = last.joint_rotations();
+		for bone in BoneKind::iter() {
+			assert_relative_eq!(first[bone], UnitQuat::identity());
+		}
+		assert_relative_eq!(joints[BoneKind::Hip], UnitQuat::identity());
+		assert_relative_eq!(joints[BoneKind::ThighL].angle(), 1., epsilon = 1e-5);
+		assert_relative_eq!(joints[BoneKind::AnkleL].angle(), 2., epsilon = 1e-5);
+	}
+
+	#[test]
+	fn test_visit_bones() {
+		let mut visited = Vec::new();
+		visit_bones(|bone, depth| visited.push((bone, depth)));
+		assert_eq!(visited.len(), BoneKind::NUM_TYPES);
+		assert_eq!(visited[0], (BoneKind::root(), 0));
+		// Every bone comes after its parent, one level deeper.
+		for (i, (bone, depth)) in visited.iter().enumerate().skip(1) {
+			let parent = bone.parent().unwrap();
+			let p = visited.iter().position(|(b, _)| *b == parent).unwrap();
+			assert!(p < i);
+			assert_eq!(visited[p].1 + 1, *depth);
+		}
+	}
+}
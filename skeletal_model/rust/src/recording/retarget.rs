@@ -0,0 +1,230 @@
+//! Mirrors poses, and retargets them onto skeletons with different bone lengths.
+
+use super::{PoseFrame, PoseRecording};
+use crate::bone::BoneMap;
+use crate::conventions::{mirror_rot, mirror_vec, up_vec};
+use crate::newtypes::Global;
+use crate::{BoneKind, Point};
+
+use nalgebra::Vector3;
+
+impl PoseFrame {
+	/// Reflects the pose across the sagittal plane, as described in
+	/// [`crate::conventions`]. The left side of the body takes the pose of the right
+	/// side, and the other way around.
+	pub fn mirrored(&self) -> Self {
+		Self {
+			timestamp: self.timestamp,
+			root_pos: Global(Point::from(mirror_vec(&self.root_pos.0.coords))),
+			bone_rots: self
+				.bone_rots
+				.mirrored()
+				.map(|_bone, rot| Global(mirror_rot(&rot.0))),
+		}
+	}
+
+	/// Adapts the pose of a skeleton with the lengths in `from`, to one with the
+	/// lengths in `to`.
+	///
+	/// The global rotation of every bone stays the same, so the limbs point the same
+	/// way. Only the position of the root changes. Its height is solved so that the
+	/// lowest point of the feet stays at the same height, so feet that touch the floor
+	/// keep touching it, even if the legs and the torso have different proportions.
+	/// Horizontally, the root is scaled by how much taller or shorter the skeleton is,
+	/// measured from the head to the ankles.
+	pub fn retargeted(&self, from: &BoneMap<f32>, to: &BoneMap<f32>) -> Self {
+		let (from_height, to_height) = (standing_height(from), standing_height(to));
+		let scale = if from_height > 0. {
+			to_height / from_height
+		} else {
+			1.
+		};
+		let mut root_pos = self.root_pos.0 * scale;
+		root_pos.y = self.root_pos.0.y + self.lowest_foot(from) - self.lowest_foot(to);
+		Self {
+			root_pos: Global(root_pos),
+			..self.clone()
+		}
+	}
+
+	/// The height of the lowest tail of the bones of the feet, relative to the root,
+	/// for a skeleton with `bone_lengths`.
+	fn lowest_foot(&self, bone_lengths: &BoneMap<f32>) -> f32 {
+		use BoneKind::*;
+		let mut tails = BoneMap::<Vector3<f32>>::default();
+		let mut lowest = f32::INFINITY;
+		let mut bone_stack = vec![BoneKind::root()];
+		while let Some(bone) = bone_stack.pop() {
+			let head_to_tail = self.bone_rots[bone].0 * -up_vec().into_inner();
+			let head = bone.parent().map_or_else(Vector3::zeros, |p| tails[p]);
+			tails[bone] = head + head_to_tail * bone_lengths[bone];
+			if matches!(bone, AnkleL | AnkleR | FootL | FootR | ToesL | ToesR) {
+				lowest = lowest.min(tails[bone].y);
+			}
+			bone_stack.extend(bone.children());
+		}
+		lowest
+	}
+}
+
+impl PoseRecording {
+	/// Mirrors every frame, as in [`PoseFrame::mirrored()`]. The bone lengths are
+	/// swapped between the two sides too.
+	pub fn mirrored(&self) -> Self {
+		Self {
+			bone_lengths: self.bone_lengths.mirrored(),
+			frames: self.frames.iter().map(PoseFrame::mirrored).collect(),
+		}
+	}
+
+	/// Retargets every frame onto a skeleton with `bone_lengths`, as in
+	/// [`PoseFrame::retargeted()`].
+	pub fn retargeted(&self, bone_lengths: &BoneMap<f32>) -> Self {
+		Self {
+			bone_lengths: *bone_lengths,
+			frames: self
+				.frames
+				.iter()
+				.map(|f| f.retargeted(&self.bone_lengths, bone_lengths))
+				.collect(),
+		}
+	}
+}
+
+/// The distance from the head to the left ankle, when standing upright.
+fn standing_height(bone_lengths: &BoneMap<f32>) -> f32 {
+	let mut height = 0.;
+	let mut bone = Some(BoneKind::AnkleL);
+	while let Some(b) = bone {
+		height += bone_lengths[b];
+		bone = b.parent();
+	}
+	height
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::tests::make_recording;
+	use super::*;
+	use crate::skeleton::SkeletonConfig;
+	use crate::{Skeleton, UnitQuat};
+
+	use approx::assert_relative_eq;
+
+	#[test]
+	fn test_mirrored() {
+		let recording = make_recording();
+		let frame = recording.frames.last().unwrap();
+		let config = SkeletonConfig::new(recording.bone_lengths);
+		let mut skeleton = Skeleton::new(&config);
+		let mut mirrored = skeleton.clone();
+		skeleton.set_pose(frame);
+		mirrored.set_pose(&frame.mirrored());
+
+		// Every bone is where its mirror image was.
+		for bone in BoneKind::iter() {
+			let pose = skeleton.bone_pose(bone).0;
+			let mirrored_pose = mirrored.bone_pose(bone.mirror()).0;
+			assert_relative_eq!(
+				mirrored_pose.translation.vector,
+				mirror_vec(&pose.translation.vector),
+				epsilon = 1e-5
+			);
+		}
+		// The left arm was raised, now the right arm is.
+		let hand = |s: &Skeleton, bone| s.bone_pose(bone).0.translation.y;
+		assert!(hand(&skeleton, BoneKind::HandL) > hand(&skeleton, BoneKind::HandR));
+		assert!(hand(&mirrored, BoneKind::HandR) > hand(&mirrored, BoneKind::HandL));
+
+		let twice = recording.mirrored().mirrored();
+		assert_eq!(twice.bone_lengths, recording.bone_lengths);
+		for (a, b) in twice.frames.iter().zip(&recording.frames) {
+			assert_relative_eq!(a.root_pos.0, b.root_pos.0);
+			for bone in BoneKind::iter() {
+				assert_relative_eq!(a.bone_rots[bone].0, b.bone_rots[bone].0);
+			}
+		}
+	}
+
+	#[test]
+	fn test_retargeted() {
+		let tall = SkeletonConfig::from_height(1.8).bone_lengths;
+		let short = SkeletonConfig::from_height(1.5).bone_lengths;
+		// Standing with the ankles on the floor, and the left thigh raised.
+		let raised = UnitQuat::from_axis_angle(&Vector3::x_axis(), 0.5);
+		let mut frame = PoseFrame {
+			timestamp: 0.,
+			root_pos: Global(Point::new(0., standing_height(&tall), 0.)),
+			bone_rots: BoneMap::default()
+				.map(|bone: BoneKind, ()| bone.calibration_rotation()),
+		};
+		frame.bone_rots[BoneKind::ThighL] = Global(raised);
+
+		let retargeted = frame.retargeted(&tall, &short);
+		let mut skeleton = Skeleton::new(&SkeletonConfig::new(short));
+		skeleton.set_pose(&retargeted);
+		// The right shin hangs straight down from the knee to the floor.
+		let knee = skeleton.bone_pose(BoneKind::AnkleR).0.translation.vector;
+		assert_relative_eq!(knee.y, short[BoneKind::AnkleR], epsilon = 1e-5);
+		assert_relative_eq!(
+			skeleton[BoneKind::ThighL].output_rot_g.0,
+			raised,
+			epsilon = 1e-6
+		);
+		// Posing the skeleton round-trips.
+		let captured = PoseFrame::new(&skeleton, 0.);
+		assert_relative_eq!(captured.root_pos.0, retargeted.root_pos.0, epsilon = 1e-5);
+	}
+
+	#[test]
+	fn test_retargeted_proportions() {
+		use BoneKind::*;
+		let from = SkeletonConfig::from_height(1.8).bone_lengths;
+		// Longer legs and a shorter torso, at the same height.
+		let mut to = from;
+		for bone in [ThighL, ThighR, AnkleL, AnkleR] {
+			to[bone] += 0.05;
+		}
+		for bone in [Chest, Waist] {
+			to[bone] -= 0.05;
+		}
+		// Bending over, with the right knee bent.
+		let mut frame = PoseFrame {
+			timestamp: 0.,
+			root_pos: Global(Point::origin()),
+			bone_rots: BoneMap::default()
+				.map(|bone: BoneKind, ()| bone.calibration_rotation()),
+		};
+		let thigh = UnitQuat::from_axis_angle(&Vector3::x_axis(), 1.2);
+		let shin = UnitQuat::from_axis_angle(&Vector3::x_axis(), -0.8);
+		frame.bone_rots[ThighR] = Global(thigh);
+		frame.bone_rots[AnkleR] = Global(shin);
+		let bent = UnitQuat::from_axis_angle(&Vector3::x_axis(), -1.2);
+		frame.bone_rots[Chest] = Global(bent);
+		frame.bone_rots[Waist] = Global(bent);
+		frame.root_pos.0.y = -frame.lowest_foot(&from);
+
+		let floor = |frame: &PoseFrame, lengths| {
+			let mut skeleton = Skeleton::new(&SkeletonConfig::new(lengths));
+			skeleton.set_pose(frame);
+			[AnkleL, AnkleR, FootL, FootR, ToesL, ToesR]
+				.map(|b| {
+					let pose = skeleton.bone_pose(b).0;
+					(pose * Point::from(-up_vec().into_inner() * lengths[b])).y
+				})
+				.into_iter()
+				.fold(f32::INFINITY, f32::min)
+		};
+		assert_relative_eq!(floor(&frame, from), 0., epsilon = 1e-5);
+		let retargeted = frame.retargeted(&from, &to);
+		assert_relative_eq!(floor(&retargeted, to), 0., epsilon = 1e-5);
+		// Both skeletons are as tall when standing, so scaling the root by their height
+		// would have left it in place, and sunk the feet into the floor.
+		assert_relative_eq!(
+			standing_height(&from),
+			standing_height(&to),
+			epsilon = 1e-6
+		);
+		assert!(floor(&frame, to) < -0.05);
+	}
+}
@@ -0,0 +1,264 @@
+//! A port of the tracker filters of the SlimeVR server, so that poses get smoothed and
+//! predicted the same way as there.
+
+use super::PoseFrame;
+use crate::bone::BoneMap;
+use crate::newtypes::Global;
+use crate::UnitQuat;
+
+use std::collections::VecDeque;
+
+/// How much the prediction slerps towards the predicted rotation per second, at an
+/// amount of 1, on top of [`PREDICT_MIN`].
+const PREDICT_MULTIPLIER: f32 = 15.;
+/// How much the prediction slerps towards the predicted rotation per second, at an
+/// amount of 0.
+const PREDICT_MIN: f32 = 10.;
+/// How many of the latest rotation changes get applied to predict the next rotation.
+const PREDICT_BUFFER: usize = 6;
+/// How fast the smoothing reaches a new rotation, at an amount of 0, on top of
+/// [`SMOOTH_MIN`].
+const SMOOTH_MULTIPLIER: f32 = 42.;
+/// How fast the smoothing reaches a new rotation, at an amount of 1.
+const SMOOTH_MIN: f32 = 11.;
+
+/// The kinds of filters of the server. These match its `TrackerFilters`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ServerFilterKind {
+	/// Lags behind new rotations, and smooths out jitter between them.
+	Smoothing,
+	/// Rotates ahead of the newest rotation, by repeating the latest changes.
+	Prediction,
+}
+
+/// Settings for a [`ServerFilter`]. These match the `filters` section of the server's
+/// config.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServerFilterSettings {
+	pub kind: ServerFilterKind,
+	/// How strong the filter is, from 0 to 1.
+	pub amount: f32,
+}
+impl Default for ServerFilterSettings {
+	fn default() -> Self {
+		Self {
+			kind: ServerFilterKind::Prediction,
+			amount: 0.2,
+		}
+	}
+}
+
+/// Filters the rotations of the bones like the SlimeVR server does.
+///
+/// The server filters each rotation with a `QuaternionMovingAverage`. New rotations
+/// get added whenever they arrive, and the filtered rotation is advanced on every tick
+/// of the server, by the time that passed since the last tick. This does the same for
+/// every bone of a [`PoseFrame`]: [`ServerFilter::push()`] adds newly solved frames,
+/// and [`ServerFilter::update()`] advances the filter, for example once per display
+/// frame. Like in the server, only the rotations are filtered.
+#[derive(Debug, Clone)]
+pub struct ServerFilter {
+	settings: ServerFilterSettings,
+	bones: Option<BoneMap<QuaternionMovingAverage>>,
+	/// The newest frame, and the time since it was pushed.
+	latest: Option<(PoseFrame, f32)>,
+}
+impl ServerFilter {
+	pub fn new(settings: ServerFilterSettings) -> Self {
+		Self {
+			settings,
+			bones: None,
+			latest: None,
+		}
+	}
+
+	pub fn settings(&self) -> &ServerFilterSettings {
+		&self.settings
+	}
+
+	/// Adds a newly solved frame.
+	pub fn push(&mut self, frame: PoseFrame) {
+		match &mut self.bones {
+			Some(bones) => {
+				for (bone, filter) in bones.iter_mut() {
+					filter.add(frame.bone_rots[bone].0);
+				}
+			}
+			None => {
+				let settings = &self.settings;
+				self.bones = Some(BoneMap::default().map(|bone, ()| {
+					QuaternionMovingAverage::new(settings, frame.bone_rots[bone].0)
+				}));
+			}
+		}
+		self.latest = Some((frame, 0.));
+	}
+
+	/// Advances the filter by `dt` seconds, and returns the filtered pose. Its
+	/// timestamp is that of the newest frame, plus the time since it was pushed.
+	/// Returns `None` if no frame was pushed yet.
+	pub fn update(&mut self, dt: f32) -> Option<PoseFrame> {
+		let (latest, elapsed) = self.latest.as_mut()?;
+		let bones = self.bones.as_mut()?;
+		*elapsed += dt;
+		for (_bone, filter) in bones.iter_mut() {
+			filter.update(dt);
+		}
+		Some(PoseFrame {
+			timestamp: latest.timestamp + *elapsed,
+			root_pos: latest.root_pos,
+			bone_rots: BoneMap::default().map(|bone, ()| Global(bones[bone].filtered)),
+		})
+	}
+
+	/// Forgets all frames.
+	pub fn clear(&mut self) {
+		self.bones = None;
+		self.latest = None;
+	}
+}
+
+/// A port of the server's `QuaternionMovingAverage`, which filters one rotation.
+#[derive(Debug, Clone)]
+struct QuaternionMovingAverage {
+	kind: ServerFilterKind,
+	/// For smoothing, how much of the way to the newest rotation is covered per second.
+	/// For prediction, how much of the way to the predicted rotation is covered per
+	/// second.
+	factor: f32,
+	filtered: UnitQuat,
+	latest: UnitQuat,
+	/// The rotation changes between the latest rotations, oldest first.
+	deltas: VecDeque<UnitQuat>,
+	/// Where the smoothing started from, and the time since then.
+	smoothing_from: UnitQuat,
+	smoothing_t: f32,
+}
+impl QuaternionMovingAverage {
+	fn new(settings: &ServerFilterSettings, initial: UnitQuat) -> Self {
+		let amount = settings.amount.max(0.);
+		let factor = match settings.kind {
+			ServerFilterKind::Smoothing => {
+				SMOOTH_MULTIPLIER * (1. - amount.min(1.)) + SMOOTH_MIN
+			}
+			ServerFilterKind::Prediction => PREDICT_MULTIPLIER * amount + PREDICT_MIN,
+		};
+		Self {
+			kind: settings.kind,
+			factor,
+			filtered: initial,
+			latest: initial,
+			deltas: VecDeque::with_capacity(PREDICT_BUFFER),
+			smoothing_from: initial,
+			smoothing_t: 0.,
+		}
+	}
+
+	fn add(&mut self, rot: UnitQuat) {
+		let old = std::mem::replace(&mut self.latest, rot);
+		match self.kind {
+			ServerFilterKind::Prediction => {
+				if self.deltas.len() == PREDICT_BUFFER {
+					self.deltas.pop_front();
+				}
+				self.deltas.push_back(old.inverse() * rot);
+			}
+			ServerFilterKind::Smoothing => {
+				self.smoothing_from = self.filtered;
+				self.smoothing_t = 0.;
+			}
+		}
+	}
+
+	fn update(&mut self, dt: f32) {
+		match self.kind {
+			ServerFilterKind::Prediction => {
+				if self.deltas.is_empty() {
+					return;
+				}
+				let predicted = self.deltas.iter().fold(self.latest, |q, d| q * d);
+				let amount = (self.factor * dt).min(1.);
+				self.filtered = self.filtered.slerp(&predicted, amount);
+			}
+			ServerFilterKind::Smoothing => {
+				self.smoothing_t += dt;
+				let amount = (self.factor * self.smoothing_t).min(1.);
+				self.filtered = self.smoothing_from.slerp(&self.latest, amount);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{BoneKind, Point};
+
+	use approx::assert_relative_eq;
+	use nalgebra::Vector3;
+
+	/// A frame with every bone turned by `angle` around Y.
+	fn frame(timestamp: f32, angle: f32) -> PoseFrame {
+		let rot = UnitQuat::from_axis_angle(&Vector3::y_axis(), angle);
+		PoseFrame {
+			timestamp,
+			root_pos: Global(Point::new(0., 1., 0.)),
+			bone_rots: BoneMap::default().map(|_, ()| Global(rot)),
+		}
+	}
+
+	fn angle(frame: &PoseFrame) -> f32 {
+		frame.bone_rots[BoneKind::Hip].0.scaled_axis().y
+	}
+
+	/// The server ticks at about 1000 Hz.
+	const TICK: f32 = 0.001;
+
+	#[test]
+	fn test_smoothing() {
+		let mut filter = ServerFilter::new(ServerFilterSettings {
+			kind: ServerFilterKind::Smoothing,
+			amount: 0.5,
+		});
+		assert!(filter.update(TICK).is_none());
+		filter.push(frame(0., 0.));
+		filter.push(frame(0.1, 1.));
+		// At an amount of 0.5, the smoothing covers 42 * 0.5 + 11 = 32 times the way to
+		// the new rotation per second.
+		let smoothed = filter.update(0.01).unwrap();
+		assert_relative_eq!(smoothed.timestamp, 0.11);
+		assert_relative_eq!(angle(&smoothed), 0.32, epsilon = 1e-5);
+		assert_relative_eq!(smoothed.root_pos.0, Point::new(0., 1., 0.));
+		// And it arrives after 1/32 of a second.
+		let smoothed = filter.update(0.03).unwrap();
+		assert_relative_eq!(angle(&smoothed), 1., epsilon = 1e-5);
+
+		// A new rotation starts from where the smoothing currently is.
+		filter.push(frame(0.2, 0.));
+		filter.update(0.01);
+		let smoothed = filter.update(0.01).unwrap();
+		assert_relative_eq!(angle(&smoothed), 1. - 0.64, epsilon = 1e-5);
+	}
+
+	#[test]
+	fn test_prediction() {
+		let mut filter = ServerFilter::new(ServerFilterSettings::default());
+		filter.push(frame(0., 0.));
+		// Without any change in rotation yet, there is nothing to predict.
+		assert_relative_eq!(angle(&filter.update(TICK).unwrap()), 0.);
+
+		// Turning at a steady 0.01 per frame, for more frames than the buffer holds.
+		for i in 1..=10 {
+			filter.push(frame(i as f32 / 100., i as f32 / 100.));
+		}
+		// At an amount of 0.2, the prediction covers 15 * 0.2 + 10 = 13 times the way
+		// to 6 frames ahead per second.
+		let predicted = filter.update(TICK).unwrap();
+		assert_relative_eq!(angle(&predicted), 0.16 * 13. * TICK, epsilon = 1e-5);
+		let mut predicted = predicted;
+		for _ in 0..1000 {
+			predicted = filter.update(TICK).unwrap();
+		}
+		assert_relative_eq!(angle(&predicted), 0.16, epsilon = 1e-4);
+	}
+}
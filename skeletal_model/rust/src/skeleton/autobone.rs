@@ -0,0 +1,454 @@
+//! Contains "autobone", which estimates the bone lengths of the user from a recording
+//! of them moving around.
+//!
+//! Measuring every bone with a tape measure is tedious and error prone. Instead, the
+//! user wears their calibrated trackers and moves around for a short while, for
+//! example by squatting, leaning and walking in place. Autobone then looks for the
+//! bone lengths that best explain the recording, using the skeletal solver as the
+//! forward model:
+//! * The skeleton is solved for each frame of the recording, anchored at the first
+//!   positional tracker, which is usually the headset. The positions of the other
+//!   positional trackers are *not* given to the solver.
+//! * The foot that is closer to the floor is assumed to be planted. It should not slide
+//!   around between frames, and it should stay at the height of the floor.
+//! * The other positional trackers, such as the controllers, should end up where they
+//!   were recorded.
+//! * The bone lengths should not stray too far from the ones the skeleton started with.
+//!   This keeps bones that the recording says little about at reasonable lengths.
+//!
+//! The weighted sum of the squares of these errors is minimized with the
+//! [Levenberg-Marquardt] algorithm, where the Jacobian is estimated with finite
+//! differences. The result is similar to the
+//! AutoBone of the [Java server](https://github.com/SlimeVR/SlimeVR-Server/).
+//!
+//! [Levenberg-Marquardt]: https://en.wikipedia.org/wiki/Levenberg%E2%80%93Marquardt_algorithm
+
+use crate::bone::BoneMap;
+use crate::newtypes::Global;
+use crate::skeleton::{SolveError, SolverMode, TrackerError, TrackerId};
+use crate::{BoneKind, Point, Skeleton, UnitQuat};
+
+use nalgebra::{DMatrix, DVector, Vector3};
+
+/// A recording of the input trackers, used by [`Skeleton::auto_bone()`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputRecording {
+	pub frames: Vec<InputFrame>,
+}
+
+/// The inputs of every tracker at a single point in time.
+///
+/// Trackers that are missing from the frame have no inputs during that frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputFrame {
+	pub inputs: Vec<TrackerInput>,
+}
+impl InputFrame {
+	/// Adds the input of a tracker to the frame. The arguments are the same as for
+	/// [`Skeleton::update_input_tracker()`].
+	pub fn push(
+		&mut self,
+		id: TrackerId,
+		pos: Option<Global<Point>>,
+		rot: Option<Global<UnitQuat>>,
+	) {
+		self.inputs.push(TrackerInput { id, pos, rot });
+	}
+}
+
+/// The input of a single tracker. See [`Skeleton::update_input_tracker()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackerInput {
+	pub id: TrackerId,
+	pub pos: Option<Global<Point>>,
+	pub rot: Option<Global<UnitQuat>>,
+}
+
+/// Settings for [`Skeleton::auto_bone()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoBoneSettings {
+	/// The bones whose lengths get optimized. All other bones keep their length.
+	pub bones: Vec<BoneKind>,
+	/// The number of Levenberg-Marquardt iterations.
+	pub iterations: usize,
+	/// The initial damping of the Levenberg-Marquardt steps. It adapts as the
+	/// optimization progresses.
+	pub damping: f32,
+	/// The height of the floor, if it is known. Usually, the headset reports positions
+	/// with the floor at a height of zero.
+	pub floor_height: Option<f32>,
+	/// How much a planted foot sliding between frames counts towards the error.
+	pub slide_weight: f32,
+	/// How much a planted foot not touching the floor counts towards the error.
+	pub floor_weight: f32,
+	/// How much the positional trackers differing from the recording counts towards
+	/// the error.
+	pub position_weight: f32,
+	/// How much the relative change of the bone lengths counts towards the error.
+	pub proportion_weight: f32,
+	/// No bone gets shorter than this.
+	pub min_length: f32,
+}
+impl Default for AutoBoneSettings {
+	fn default() -> Self {
+		use BoneKind::*;
+		Self {
+			bones: vec![
+				Neck, UpperChest, Chest, Waist, Hip, ThighL, ThighR, AnkleL, AnkleR,
+				UpperArmL, UpperArmR, ForearmL, ForearmR,
+			],
+			iterations: 20,
+			damping: 0.01,
+			floor_height: Some(0.),
+			slide_weight: 1.,
+			floor_weight: 1.,
+			position_weight: 1.,
+			proportion_weight: 0.01,
+			min_length: 0.01,
+		}
+	}
+}
+
+/// The results of [`Skeleton::auto_bone()`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoBoneReport {
+	/// The estimated length of every bone.
+	pub bone_lengths: BoneMap<f32>,
+	/// The error of the initial bone lengths.
+	pub initial_error: f32,
+	/// The error of the estimated bone lengths.
+	pub final_error: f32,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AutoBoneError {
+	#[error("The recording has no frames")]
+	EmptyRecording,
+	#[error("Frame {0} has no positional tracker to anchor the skeleton")]
+	NoAnchor(usize),
+	#[error(transparent)]
+	Tracker(#[from] TrackerError),
+	#[error(transparent)]
+	Solve(#[from] SolveError),
+}
+
+impl Skeleton {
+	/// Estimates the bone lengths of the user from `recording`, by finding the lengths
+	/// that minimize how much the planted foot slides around, how far it is from the
+	/// floor, and how far the other positional trackers are from where they were
+	/// recorded. The first positional tracker of each frame, usually the headset,
+	/// anchors the skeleton.
+	///
+	/// The trackers should already be calibrated. The skeleton itself is left
+	/// untouched, to apply the results use [`Skeleton::set_bone_lengths()`].
+	pub fn auto_bone(
+		&self,
+		recording: &InputRecording,
+		settings: &AutoBoneSettings,
+	) -> Result<AutoBoneReport, AutoBoneError> {
+		if recording.frames.is_empty() {
+			return Err(AutoBoneError::EmptyRecording);
+		}
+		let initial = self.bone_map.map(|_kind, edge| self.graph[edge].length);
+		let mut model = self.clone();
+		// IK would hide how well the bone lengths fit.
+		model.set_solver_mode(SolverMode::Fk);
+		let mut residuals_of = |lengths: &BoneMap<f32>| {
+			model.set_bone_lengths(lengths);
+			model
+				.recording_residuals(recording, settings, &initial)
+				.map(DVector::from_vec)
+		};
+
+		let n = settings.bones.len();
+		let mut lengths = initial;
+		let mut residuals = residuals_of(&lengths)?;
+		let initial_error = residuals.norm_squared();
+		let mut damping = settings.damping;
+		for _ in 0..settings.iterations {
+			let mut jacobian = DMatrix::zeros(residuals.len(), n);
+			for (i, bone) in settings.bones.iter().enumerate() {
+				let mut probe = lengths;
+				probe[*bone] += STEP;
+				let column = (residuals_of(&probe)? - &residuals) / STEP;
+				jacobian.set_column(i, &column);
+			}
+
+			// Only take the step if it helps, otherwise retry with more damping, which
+			// makes the step smaller and closer to plain gradient descent.
+			let jtj = jacobian.tr_mul(&jacobian);
+			let gradient = jacobian.tr_mul(&residuals);
+			let damped = &jtj + DMatrix::from_diagonal(&jtj.diagonal()) * damping;
+			let Some(step) = damped.cholesky().map(|c| c.solve(&-gradient)) else {
+				damping *= 10.;
+				continue;
+			};
+			let mut candidate = lengths;
+			for (bone, delta) in settings.bones.iter().zip(step.iter()) {
+				candidate[*bone] = (lengths[*bone] + delta).max(settings.min_length);
+			}
+			let candidate_residuals = residuals_of(&candidate)?;
+			if candidate_residuals.norm_squared() < residuals.norm_squared() {
+				lengths = candidate;
+				residuals = candidate_residuals;
+				damping /= 10.;
+			} else {
+				damping *= 10.;
+			}
+		}
+
+		Ok(AutoBoneReport {
+			bone_lengths: lengths,
+			initial_error,
+			final_error: residuals.norm_squared(),
+		})
+	}
+
+	/// Solves every frame of `recording`, and computes the weighted residuals of the
+	/// current bone lengths. The error is the sum of their squares.
+	///
+	/// The number of residuals only depends on the recording, not on the bone lengths.
+	fn recording_residuals(
+		&mut self,
+		recording: &InputRecording,
+		settings: &AutoBoneSettings,
+		initial: &BoneMap<f32>,
+	) -> Result<Vec<f32>, AutoBoneError> {
+		let trackers: Vec<_> = self.input_trackers().map(|(id, _bone)| id).collect();
+		let mut slide = Term::default();
+		let mut floor = Term::default();
+		let mut position = Term::default();
+		let mut prev_planted: Option<(BoneKind, Point)> = None;
+		for (i, frame) in recording.frames.iter().enumerate() {
+			let mut inputs = frame.inputs.clone();
+			inputs.sort_by_key(|input| input.id);
+			let anchor = inputs
+				.iter()
+				.find(|input| input.pos.is_some())
+				.ok_or(AutoBoneError::NoAnchor(i))?
+				.id;
+
+			for id in &trackers {
+				self.update_input_tracker(*id, None, None)?;
+			}
+			for input in &inputs {
+				let pos = if input.id == anchor { input.pos } else { None };
+				self.update_input_tracker(input.id, pos, input.rot)?;
+			}
+			self.solve()?;
+
+			for input in &inputs {
+				if let (Some(pos), true) = (input.pos, input.id != anchor) {
+					let (_bone_head, node) =
+						self.graph.edge_endpoints(self.trackers[&input.id]).unwrap();
+					let solved = self.graph[node].output_pos_g.0;
+					position.add((solved - pos.0).as_slice());
+				}
+			}
+
+			// The tail of the foot is the head of the toes.
+			let planted = [BoneKind::ToesL, BoneKind::ToesR]
+				.map(|bone| {
+					let pos = self.bone_pose(bone).0.translation.vector;
+					(bone, Point::from(pos))
+				})
+				.into_iter()
+				.min_by(|(_, a), (_, b)| a.y.total_cmp(&b.y))
+				.unwrap();
+			if let Some(floor_height) = settings.floor_height {
+				floor.add(&[planted.1.y - floor_height]);
+			}
+			if let Some((prev_bone, prev_pos)) = prev_planted {
+				// When the user steps from one foot to the other, nothing slides.
+				let delta = if prev_bone == planted.0 {
+					planted.1 - prev_pos
+				} else {
+					Vector3::zeros()
+				};
+				slide.add(&[delta.x, delta.z]);
+			}
+			prev_planted = Some(planted);
+		}
+
+		let mut proportion = Term::default();
+		for bone in &settings.bones {
+			let change = if initial[*bone] > 0. {
+				(self[*bone].length - initial[*bone]) / initial[*bone]
+			} else {
+				0.
+			};
+			proportion.add(&[change]);
+		}
+
+		let mut residuals = Vec::new();
+		residuals.extend(slide.weighted(settings.slide_weight));
+		residuals.extend(floor.weighted(settings.floor_weight));
+		residuals.extend(position.weighted(settings.position_weight));
+		residuals.extend(proportion.weighted(settings.proportion_weight));
+		Ok(residuals)
+	}
+}
+
+/// The change in bone length used to estimate the Jacobian.
+const STEP: f32 = 0.001;
+
+/// One of the terms of the error. The term is the mean of the squared norm of its
+/// samples, which may each have several components.
+#[derive(Default)]
+struct Term {
+	components: Vec<f32>,
+	samples: usize,
+}
+impl Term {
+	fn add(&mut self, sample: &[f32]) {
+		self.components.extend_from_slice(sample);
+		self.samples += 1;
+	}
+
+	/// Scales the components, so that the sum of their squares is `weight` times the
+	/// mean of the samples.
+	fn weighted(self, weight: f32) -> impl Iterator<Item = f32> {
+		let scale = (weight / self.samples.max(1) as f32).sqrt();
+		self.components.into_iter().map(move |c| c * scale)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::skeleton::SkeletonConfig;
+
+	const TRACKED: [BoneKind; 10] = {
+		use BoneKind::*;
+		[
+			Head, Chest, Hip, ThighL, ThighR, AnkleL, AnkleR, UpperArmL, ForearmL,
+			HandL,
+		]
+	};
+
+	fn make_skeleton(height: f32) -> Skeleton {
+		let mut skeleton = Skeleton::new(&SkeletonConfig::from_height(height));
+		for bone in TRACKED {
+			skeleton.attach_input_tracker(bone, None, None);
+		}
+		skeleton
+	}
+
+	/// Records the user squatting, leaning forward, lifting their right foot and
+	/// swinging their left arm. The headset is on the head, and the controller is in the left
+	/// hand.
+	fn record(truth: &mut Skeleton) -> InputRecording {
+		use BoneKind::*;
+		let ids: Vec<_> = truth.input_trackers().map(|(id, _bone)| id).collect();
+		let mut recording = InputRecording::default();
+		for i in 0..20 {
+			let t = i as f32 * 0.3;
+			let squat = 0.8 * t.sin().powi(2);
+			let lean = 0.6 * (0.45 * t).sin().powi(2);
+			let lift = 0.8 * (0.6 * t).sin().max(0.);
+			let swing = 1.2 * (0.7 * t).sin();
+			let rot = |bone: BoneKind, angle: f32| {
+				let rot = UnitQuat::from_axis_angle(&Vector3::x_axis(), angle);
+				Global(rot * bone.calibration_rotation().0)
+			};
+			let rots = [
+				rot(Head, 0.),
+				rot(Chest, -lean),
+				rot(Hip, 0.),
+				rot(ThighL, squat),
+				rot(ThighR, squat + lift),
+				rot(AnkleL, -squat),
+				rot(AnkleR, -squat + lift),
+				rot(UpperArmL, swing),
+				rot(ForearmL, swing + 0.5),
+				rot(HandL, swing + 0.5),
+			];
+
+			// Solve once to find the feet, then move the headset so that the left
+			// foot is on the floor at the origin.
+			for (id, rot) in ids.iter().zip(rots) {
+				truth.update_input_tracker(*id, None, Some(rot)).unwrap();
+			}
+			truth
+				.update_input_tracker(
+					ids[0],
+					Some(Global(Point::origin())),
+					Some(rots[0]),
+				)
+				.unwrap();
+			truth.solve().unwrap();
+			let foot = truth.bone_pose(ToesL).0.translation.vector;
+			let hmd = Global(Point::from(-foot));
+			truth
+				.update_input_tracker(ids[0], Some(hmd), Some(rots[0]))
+				.unwrap();
+			truth.solve().unwrap();
+			let hand = truth.bone_pose(HandL).0.translation.vector;
+
+			let mut frame = InputFrame::default();
+			for (id, rot) in ids.iter().zip(rots) {
+				frame.push(*id, None, Some(rot));
+			}
+			frame.inputs[0].pos = Some(hmd);
+			frame.inputs[9].pos = Some(Global(Point::from(hand)));
+			recording.frames.push(frame);
+		}
+		recording
+	}
+
+	#[test]
+	fn test_auto_bone() {
+		use BoneKind::*;
+		let mut truth = make_skeleton(1.8);
+		let recording = record(&mut truth);
+		let expected = truth.config().bone_lengths;
+
+		// The user guessed the bones that the recording tells us about 10% too short.
+		let settings = AutoBoneSettings {
+			bones: vec![Chest, Waist, ThighL, AnkleL, UpperArmL, ForearmL],
+			proportion_weight: 0.,
+			..Default::default()
+		};
+		let mut guess = expected;
+		for bone in &settings.bones {
+			guess[*bone] *= 0.9;
+		}
+		let mut skeleton = make_skeleton(1.8);
+		skeleton.set_bone_lengths(&guess);
+
+		let report = skeleton.auto_bone(&recording, &settings).unwrap();
+		assert!(
+			report.final_error < report.initial_error * 0.01,
+			"{} -> {}",
+			report.initial_error,
+			report.final_error
+		);
+		for bone in &settings.bones {
+			let error = report.bone_lengths[*bone] - expected[*bone];
+			assert!(error.abs() < 0.001, "{bone:?} is off by {error}");
+		}
+
+		// Bones that weren't optimized keep their length.
+		assert_eq!(report.bone_lengths[ShoulderL], expected[ShoulderL]);
+
+		skeleton.set_bone_lengths(&report.bone_lengths);
+		assert_eq!(skeleton.config().bone_lengths, report.bone_lengths);
+	}
+
+	#[test]
+	fn test_errors() {
+		let skeleton = make_skeleton(1.8);
+		let settings = AutoBoneSettings::default();
+		let err = skeleton.auto_bone(&InputRecording::default(), &settings);
+		assert!(matches!(err, Err(AutoBoneError::EmptyRecording)));
+
+		let id = skeleton.input_trackers().next().unwrap().0;
+		let mut frame = InputFrame::default();
+		frame.push(id, None, Some(Global(UnitQuat::identity())));
+		let recording = InputRecording {
+			frames: vec![frame],
+		};
+		let err = skeleton.auto_bone(&recording, &settings);
+		assert!(matches!(err, Err(AutoBoneError::NoAnchor(0))));
+	}
+}
@@ -1,7 +1,33 @@
-use crate::newtypes::Global;
-use crate::{Point, UnitQuat};
+//! Contains the calibration routine of the [`Skeleton`].
+//!
+//! Calibration happens while the user stands in the calibration pose, which is the
+//! pose described by [`BoneKind::calibration_rotation()`]. Because we know the rotation
+//! of every bone in this pose, we can compare it against the rotation (and for 6DoF
+//! trackers, the position) of each input tracker, to figure out how the tracker is
+//! mounted on its bone.
+//!
+//! Each input tracker edge stores the result of the calibration:
+//! * [`Edge::calib_rot_l`]: The rotation of the tracker relative to its bone.
+//! * [`Edge::offset_l`]: The position of the tracker relative to the head of its bone.
+//!   Only computed for 6DoF trackers.
+//! * [`Edge::yaw_fix_g`]: The yaw offset between the tracker's sensor space and global
+//!   space. Only computed for 3DoF trackers, because 6DoF trackers already report
+//!   their rotation in global space.
+//!
+//! [`Edge::calib_rot_l`]: crate::skeleton::Edge::calib_rot_l
+//! [`Edge::offset_l`]: crate::skeleton::Edge::offset_l
+//! [`Edge::yaw_fix_g`]: crate::skeleton::Edge::yaw_fix_g
 
-#[allow(dead_code)]
+use crate::bone::BoneMap;
+use crate::conventions::up_vec;
+use crate::newtypes::{Global, Local};
+use crate::skeleton::{TrackerError, TrackerId};
+use crate::{BoneKind, Point, Skeleton, Translation, UnitQuat};
+
+use nalgebra::{Quaternion, Unit, Vector3};
+
+/// A sample of an input tracker, taken while the user is in the calibration pose.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CalibrationData {
 	SixDof {
 		pos: Global<Point>,
@@ -11,3 +37,485 @@ pub enum CalibrationData {
 		rot: Global<UnitQuat>,
 	},
 }
+impl CalibrationData {
+	pub fn rot(&self) -> Global<UnitQuat> {
+		match *self {
+			Self::SixDof { rot, .. } | Self::ThreeDof { rot } => rot,
+		}
+	}
+
+	pub fn pos(&self) -> Option<Global<Point>> {
+		match *self {
+			Self::SixDof { pos, .. } => Some(pos),
+			Self::ThreeDof { .. } => None,
+		}
+	}
+}
+
+/// The different kinds of calibration. These match the `ActionType` variants of
+/// `firmware_protocol`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ResetKind {
+	/// Recomputes everything about how the trackers are mounted on the body. This
+	/// corresponds to `ActionType::Reset`.
+	///
+	/// 3DoF trackers are taken to face the same way as their bone, and their
+	/// heading goes into the yaw fix, like the Java server's gyro fix.
+	Full,
+	/// Only fixes the yaw drift of 3DoF trackers, leaving everything else untouched.
+	/// This corresponds to `ActionType::ResetYaw`.
+	Yaw,
+	/// Only recomputes the rotation of each tracker around the long axis of its bone,
+	/// for example if a thigh tracker slid around to the side of the thigh. This
+	/// corresponds to `ActionType::ResetMounting`.
+	Mounting,
+}
+
+impl Skeleton {
+	/// Calibrates the input trackers, using `samples` that were taken while the user
+	/// was in the calibration pose.
+	///
+	/// Trackers that don't have a sample are left untouched. If there are 6DoF samples
+	/// during a [`ResetKind::Full`], the one with the smallest [`TrackerId`] is used to
+	/// position the calibration pose. Its offset can't be measured against anything, so
+	/// it keeps the offset it already had, from the
+	/// [`TrackerConfig`](crate::skeleton::TrackerConfig) or
+	/// [`Skeleton::set_input_tracker_offset()`]. By default, that is the head of its
+	/// bone.
+	///
+	/// The samples also become the latest inputs of the trackers, as if they were
+	/// passed to [`Skeleton::update_input_tracker()`].
+	pub fn calibrate(
+		&mut self,
+		kind: ResetKind,
+		samples: impl IntoIterator<Item = (TrackerId, CalibrationData)>,
+	) -> Result<(), TrackerError> {
+		let mut samples: Vec<_> = samples.into_iter().collect();
+		samples.sort_by_key(|(id, _data)| *id);
+		// Validate all the ids up front, so that a bad id doesn't leave the skeleton
+		// half calibrated.
+		let trackers = samples
+			.iter()
+			.map(|(id, _data)| self.input_tracker(*id))
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let heads = self.calibration_heads();
+		// Moves the calibration pose from `heads` to where the user actually is.
+		let mut with_bones = samples.iter().zip(&trackers);
+		let anchor = with_bones.find_map(|((_id, data), (edge, bone))| {
+			let offset_l = self.graph[*edge].offset_l.0.vector;
+			let offset_g = bone.calibration_rotation().0 * offset_l;
+			data.pos().map(|pos| pos.0 - heads[*bone] - offset_g)
+		});
+
+		for ((_id, data), (edge_idx, bone)) in samples.into_iter().zip(trackers) {
+			let bone_rot_g = bone.calibration_rotation().0;
+			let tracker_rot_g = data.rot().0;
+			let edge = &mut self.graph[edge_idx];
+			match kind {
+				ResetKind::Full => {
+					// The heading of a 3DoF tracker's sensor space is arbitrary, and
+					// can't be told apart from the tracker being turned around the
+					// vertical axis on its bone. So we take it to be the heading of the
+					// bone, like the yaw reset does, and only keep the rest of the
+					// mounting. A tracker that was mounted turned around the vertical
+					// axis needs a `ResetKind::Mounting` afterwards.
+					let yaw_fix_g = match data {
+						CalibrationData::ThreeDof { .. } => {
+							twist(bone_rot_g * tracker_rot_g.inverse(), &up_vec())
+						}
+						CalibrationData::SixDof { .. } => UnitQuat::identity(),
+					};
+					edge.yaw_fix_g = Global(yaw_fix_g);
+					edge.calib_rot_l =
+						Local(bone_rot_g.inverse() * yaw_fix_g * tracker_rot_g);
+					if let (Some(pos), Some(anchor)) = (data.pos(), anchor) {
+						let head_pos = anchor + heads[bone];
+						let offset = bone_rot_g.inverse() * (pos.0 - head_pos);
+						edge.offset_l = Local(Translation::from(offset));
+					}
+				}
+				ResetKind::Yaw => {
+					if let CalibrationData::ThreeDof { .. } = data {
+						// Rotates the tracker in global space so that its bone faces
+						// the same way as in the calibration pose.
+						let delta =
+							bone_rot_g * edge.calib_rot_l.0 * tracker_rot_g.inverse();
+						edge.yaw_fix_g = Global(twist(delta, &up_vec()));
+					}
+				}
+				ResetKind::Mounting => {
+					// Rotates the tracker in the local space of its bone, but only
+					// around the axis that the bone points along.
+					let fixed_rot_g = edge.yaw_fix_g.0 * tracker_rot_g;
+					let full_calib_rot_l = bone_rot_g.inverse() * fixed_rot_g;
+					let delta = full_calib_rot_l * edge.calib_rot_l.0.inverse();
+					edge.calib_rot_l =
+						Local(twist(delta, &up_vec()) * edge.calib_rot_l.0);
+				}
+			}
+
+			edge.input_rot_g = Some(Global(edge.yaw_fix_g.0 * tracker_rot_g));
+			let (_bone_head, tracker_node) =
+				self.graph.edge_endpoints(edge_idx).unwrap();
+			self.graph[tracker_node].input_pos_g = data.pos();
+		}
+		Ok(())
+	}
+
+	/// Computes the position of the head of each bone in the calibration pose,
+	/// relative to the head of the root bone.
+	fn calibration_heads(&self) -> BoneMap<Vector3<f32>> {
+		let mut heads = BoneMap::<Vector3<f32>>::default();
+		let mut bone_stack = vec![BoneKind::root()];
+		while let Some(bone) = bone_stack.pop() {
+			let length = self[bone].length;
+			let head_to_tail = -(bone.calibration_rotation().0 * up_vec()).into_inner();
+			for &child in bone.children() {
+				heads[child] = heads[bone] + head_to_tail * length;
+				bone_stack.push(child);
+			}
+		}
+		heads
+	}
+}
+
+/// Decomposes `q` into a swing and a twist around `axis`, and returns only the twist.
+fn twist(q: UnitQuat, axis: &Unit<Vector3<f32>>) -> UnitQuat {
+	let projected = axis.into_inner() * q.imag().dot(axis);
+	let twist = Quaternion::from_parts(q.w, projected);
+	// When the swing is 180 degrees, the twist is undefined. We pick no twist.
+	UnitQuat::try_new(twist, f32::EPSILON).unwrap_or_else(UnitQuat::identity)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::skeleton::{SkeletonConfig, TrackerConfig};
+	use crate::Isometry;
+
+	use approx::assert_relative_eq;
+	use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+	fn make_skeleton() -> Skeleton {
+		let bone_lengths = BoneMap::new([0.5; BoneKind::NUM_TYPES]);
+		Skeleton::new(&SkeletonConfig::new(bone_lengths))
+	}
+
+	fn axis_angle(axis: Unit<Vector3<f32>>, angle: f32) -> UnitQuat {
+		UnitQuat::from_axis_angle(&axis, angle)
+	}
+
+	fn three_dof(rot: UnitQuat) -> CalibrationData {
+		CalibrationData::ThreeDof { rot: Global(rot) }
+	}
+
+	/// Attaches a 3DoF tracker to the thigh, that is tilted at an odd angle. It faces
+	/// the same way as the thigh, since a full reset can't tell that apart from the
+	/// heading of the sensor.
+	fn thigh_tracker(skeleton: &mut Skeleton) -> (TrackerId, UnitQuat) {
+		let axis = Unit::new_normalize(Vector3::new(1., 0., 0.5));
+		let mounting = axis_angle(axis, 0.4);
+		let id = skeleton.attach_input_tracker(
+			BoneKind::ThighL,
+			None,
+			Some(Global(mounting)),
+		);
+		(id, mounting)
+	}
+
+	#[test]
+	fn test_twist() {
+		let q = axis_angle(Vector3::y_axis(), 0.5) * axis_angle(Vector3::x_axis(), 0.2);
+		assert_relative_eq!(
+			twist(q, &Vector3::y_axis()),
+			axis_angle(Vector3::y_axis(), 0.5),
+			epsilon = 1e-6
+		);
+		// A rotation that is all swing has no twist.
+		let swing = axis_angle(Vector3::x_axis(), 0.2);
+		assert_relative_eq!(twist(swing, &Vector3::y_axis()), UnitQuat::identity());
+	}
+
+	#[test]
+	fn test_full_reset() {
+		let mut skeleton = make_skeleton();
+		skeleton.attach_input_tracker(
+			BoneKind::Neck,
+			Some(Global(Point::new(0., 2., 0.))),
+			None,
+		);
+		let (thigh, mounting) = thigh_tracker(&mut skeleton);
+		// The foot points forward in the calibration pose, and the foot tracker is
+		// mounted upside down.
+		let foot_mounting = axis_angle(Vector3::z_axis(), std::f32::consts::PI);
+		let foot_rot = BoneKind::FootL.calibration_rotation().0 * foot_mounting;
+		let foot = skeleton.attach_input_tracker(
+			BoneKind::FootL,
+			None,
+			Some(Global(foot_rot)),
+		);
+
+		// Before calibration, the trackers make the bones rotate.
+		skeleton.solve().unwrap();
+		assert_relative_eq!(skeleton[BoneKind::ThighL].output_rot_g.0, mounting);
+
+		skeleton
+			.calibrate(
+				ResetKind::Full,
+				[(thigh, three_dof(mounting)), (foot, three_dof(foot_rot))],
+			)
+			.unwrap();
+		skeleton.solve().unwrap();
+		for bone in [BoneKind::ThighL, BoneKind::FootL] {
+			assert_relative_eq!(
+				skeleton[bone].output_rot_g.0,
+				bone.calibration_rotation().0,
+				epsilon = 1e-6
+			);
+		}
+
+		// Raising the thigh rotates the tracker with it.
+		let raised = axis_angle(Vector3::x_axis(), FRAC_PI_2);
+		skeleton
+			.update_input_tracker(thigh, None, Some(Global(raised * mounting)))
+			.unwrap();
+		skeleton.solve().unwrap();
+		assert_relative_eq!(
+			skeleton[BoneKind::ThighL].output_rot_g.0,
+			raised,
+			epsilon = 1e-6
+		);
+	}
+
+	#[test]
+	fn test_full_reset_heading() {
+		let mut skeleton = make_skeleton();
+		skeleton.attach_input_tracker(
+			BoneKind::Neck,
+			Some(Global(Point::origin())),
+			None,
+		);
+		let (thigh, mounting) = thigh_tracker(&mut skeleton);
+		// The sensor space of the tracker is turned sideways against global space.
+		let heading = axis_angle(Vector3::y_axis(), FRAC_PI_2);
+		skeleton
+			.calibrate(ResetKind::Full, [(thigh, three_dof(heading * mounting))])
+			.unwrap();
+		let edge = &skeleton.graph[skeleton.trackers[&thigh]];
+		assert_relative_eq!(edge.yaw_fix_g.0, heading.inverse(), epsilon = 1e-6);
+		assert_relative_eq!(edge.calib_rot_l.0, mounting, epsilon = 1e-6);
+
+		// Raising the thigh forward doesn't make it swing sideways.
+		let raised = axis_angle(Vector3::x_axis(), FRAC_PI_2);
+		skeleton
+			.update_input_tracker(
+				thigh,
+				None,
+				Some(Global(heading * raised * mounting)),
+			)
+			.unwrap();
+		skeleton.solve().unwrap();
+		assert_relative_eq!(
+			skeleton[BoneKind::ThighL].output_rot_g.0,
+			raised,
+			epsilon = 1e-6
+		);
+	}
+
+	#[test]
+	fn test_yaw_reset() {
+		let mut skeleton = make_skeleton();
+		skeleton.attach_input_tracker(
+			BoneKind::Neck,
+			Some(Global(Point::origin())),
+			None,
+		);
+		let (thigh, mounting) = thigh_tracker(&mut skeleton);
+		skeleton
+			.calibrate(ResetKind::Full, [(thigh, three_dof(mounting))])
+			.unwrap();
+
+		// The tracker drifts in yaw, which makes the thigh appear to twist.
+		let drift = axis_angle(Vector3::y_axis(), FRAC_PI_4);
+		let drifted = drift * mounting;
+		let raised = axis_angle(Vector3::x_axis(), FRAC_PI_2);
+		skeleton
+			.update_input_tracker(thigh, None, Some(Global(drifted)))
+			.unwrap();
+		skeleton.solve().unwrap();
+		assert_relative_eq!(skeleton[BoneKind::ThighL].output_rot_g.0, drift);
+
+		skeleton
+			.calibrate(ResetKind::Yaw, [(thigh, three_dof(drifted))])
+			.unwrap();
+		let calib_rot_l = skeleton.graph[skeleton.trackers[&thigh]].calib_rot_l.0;
+		assert_relative_eq!(calib_rot_l, mounting, epsilon = 1e-6);
+		skeleton.solve().unwrap();
+		assert_relative_eq!(
+			skeleton[BoneKind::ThighL].output_rot_g.0,
+			UnitQuat::identity(),
+			epsilon = 1e-6
+		);
+
+		// The drift is corrected in global space, so it also applies after moving.
+		skeleton
+			.update_input_tracker(thigh, None, Some(Global(drift * raised * mounting)))
+			.unwrap();
+		skeleton.solve().unwrap();
+		assert_relative_eq!(
+			skeleton[BoneKind::ThighL].output_rot_g.0,
+			raised,
+			epsilon = 1e-6
+		);
+	}
+
+	#[test]
+	fn test_mounting_reset() {
+		let mut skeleton = make_skeleton();
+		skeleton.attach_input_tracker(
+			BoneKind::Neck,
+			Some(Global(Point::origin())),
+			None,
+		);
+		let (thigh, mounting) = thigh_tracker(&mut skeleton);
+		skeleton
+			.calibrate(ResetKind::Full, [(thigh, three_dof(mounting))])
+			.unwrap();
+
+		// The tracker slides around the thigh, and also is slightly tilted.
+		let slid = axis_angle(Vector3::y_axis(), FRAC_PI_2) * mounting;
+		let tilt = axis_angle(Vector3::z_axis(), 0.1);
+		skeleton
+			.calibrate(ResetKind::Mounting, [(thigh, three_dof(tilt * slid))])
+			.unwrap();
+
+		// Only the sliding got fixed, the tilt is still there.
+		let calib_rot_l = skeleton.graph[skeleton.trackers[&thigh]].calib_rot_l.0;
+		assert!(calib_rot_l.angle_to(&(tilt * slid)) > 0.05);
+		assert_relative_eq!(
+			twist(calib_rot_l * slid.inverse(), &up_vec()),
+			UnitQuat::identity(),
+			epsilon = 1e-5
+		);
+		assert_relative_eq!(
+			skeleton.graph[skeleton.trackers[&thigh]].yaw_fix_g.0,
+			UnitQuat::identity()
+		);
+	}
+
+	#[test]
+	fn test_six_dof_offsets() {
+		let mut skeleton = make_skeleton();
+		let hmd_pos = Point::new(1., 2., 3.);
+		let hmd = skeleton.attach_input_tracker(BoneKind::Neck, None, None);
+		let foot = skeleton.attach_input_tracker(BoneKind::FootL, None, None);
+		// The trackers are mounted at arbitrary rotations, and the foot tracker sits 10cm
+		// above the ankle.
+		let facing = axis_angle(Vector3::y_axis(), -FRAC_PI_2);
+		let foot_pos = Point::new(1., 2. - 3.5 + 0.1, 3.);
+		let samples = [
+			(
+				hmd,
+				CalibrationData::SixDof {
+					pos: Global(hmd_pos),
+					rot: Global(facing),
+				},
+			),
+			(
+				foot,
+				CalibrationData::SixDof {
+					pos: Global(foot_pos),
+					rot: Global(BoneKind::FootL.calibration_rotation().0 * facing),
+				},
+			),
+		];
+		skeleton.calibrate(ResetKind::Full, samples).unwrap();
+		let offset_l = skeleton.graph[skeleton.trackers[&foot]].offset_l.0;
+		let foot_rot_g = BoneKind::FootL.calibration_rotation().0;
+		assert_relative_eq!(
+			foot_rot_g * offset_l.vector,
+			Vector3::new(0., 0.1, 0.),
+			epsilon = 1e-6
+		);
+
+		// Only the foot tracker constrains position, the output tracker sits on the head
+		// of the neck.
+		skeleton.update_input_tracker(hmd, None, None).unwrap();
+		let out =
+			skeleton.add_output_tracker(BoneKind::Neck, Local(Isometry::identity()));
+		skeleton.solve().unwrap();
+		assert_relative_eq!(
+			skeleton[BoneKind::FootL].output_rot_g.0,
+			foot_rot_g,
+			epsilon = 1e-6
+		);
+		let head = skeleton.output_tracker_pose(out).unwrap().0;
+		assert_relative_eq!(head.translation.vector, hmd_pos.coords, epsilon = 1e-5);
+	}
+
+	#[test]
+	fn test_anchor_offset() {
+		let bone_lengths = BoneMap::new([0.5; BoneKind::NUM_TYPES]);
+		let mut config = SkeletonConfig::new(bone_lengths);
+		// The HMD sits 10cm in front of and above the head of the neck.
+		let hmd_offset = Translation::new(0., 0.1, -0.1);
+		config.input_trackers = vec![
+			TrackerConfig {
+				bone: BoneKind::Neck,
+				offset: Local(Isometry::from_parts(hmd_offset, UnitQuat::identity())),
+				..TrackerConfig::new(BoneKind::Neck)
+			},
+			TrackerConfig::new(BoneKind::FootL),
+		];
+		let mut skeleton = Skeleton::new(&config);
+		let ids: Vec<_> = skeleton.input_trackers().map(|(id, _bone)| id).collect();
+		let (hmd, foot) = (ids[0], ids[1]);
+
+		// The head of the neck is at (1, 2, 3), and the foot tracker sits 10cm above
+		// the ankle.
+		let six_dof = |bone: BoneKind, pos| CalibrationData::SixDof {
+			pos: Global(pos),
+			rot: bone.calibration_rotation(),
+		};
+		let samples = [
+			(hmd, six_dof(BoneKind::Neck, Point::new(1., 2.1, 2.9))),
+			(
+				foot,
+				six_dof(BoneKind::FootL, Point::new(1., 2. - 3.5 + 0.1, 3.)),
+			),
+		];
+		skeleton.calibrate(ResetKind::Full, samples).unwrap();
+
+		let hmd_offset_l = skeleton.input_tracker_offset(hmd).unwrap().0;
+		assert_relative_eq!(hmd_offset_l.translation, hmd_offset, epsilon = 1e-6);
+		let foot_offset_l = skeleton.input_tracker_offset(foot).unwrap().0;
+		let foot_rot_g = BoneKind::FootL.calibration_rotation().0;
+		assert_relative_eq!(
+			foot_rot_g * foot_offset_l.translation.vector,
+			Vector3::new(0., 0.1, 0.),
+			epsilon = 1e-6
+		);
+	}
+
+	#[test]
+	fn test_invalid_id() {
+		let mut skeleton = make_skeleton();
+		let (thigh, mounting) = thigh_tracker(&mut skeleton);
+		let out =
+			skeleton.add_output_tracker(BoneKind::Hip, Local(Isometry::identity()));
+		let result = skeleton.calibrate(
+			ResetKind::Full,
+			[
+				(thigh, three_dof(UnitQuat::identity())),
+				(out, three_dof(mounting)),
+			],
+		);
+		assert!(result.is_err());
+		// Nothing was calibrated.
+		assert_relative_eq!(
+			skeleton.graph[skeleton.trackers[&thigh]].calib_rot_l.0,
+			UnitQuat::identity()
+		);
+	}
+}
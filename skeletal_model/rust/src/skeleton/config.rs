@@ -0,0 +1,344 @@
+//! Contains the [`SkeletonConfig`], which describes the body and trackers of a
+//! particular user.
+
+use crate::bone::{BodyProportions, BoneKind, BoneMap};
+use crate::newtypes::{Global, Local};
+use crate::skeleton::EdgeKind;
+use crate::{Isometry, Skeleton, UnitQuat};
+
+/// Used to initialize the [`Skeleton`] with its initial parameters.
+///
+/// With the `serde` feature, configs can be saved to and loaded from any format that
+/// serde supports, such as TOML or JSON. Bones are referred to by their names in
+/// `snake_case`. If a config specifies the user's `height`, any bone lengths missing
+/// from it are computed using the default [`BodyProportions`]. Offsets and yaw fixes
+/// default to the identity. For example:
+///
+/// ```toml
+/// height = 1.75
+///
+/// [bone_lengths]
+/// thigh_l = 0.45
+/// thigh_r = 0.45
+///
+/// [[input_trackers]]
+/// bone = "chest"
+/// offset = { translation = [0.0, 0.0, -0.1], rotation = [0.0, 0.0, 0.0, 1.0] }
+///
+/// [[input_trackers]]
+/// bone = "thigh_l"
+///
+/// [[output_trackers]]
+/// bone = "hip"
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize, serde::Deserialize),
+	serde(try_from = "ConfigFile", into = "ConfigFile")
+)]
+pub struct SkeletonConfig {
+	pub bone_lengths: BoneMap<f32>,
+	/// The input trackers to attach to the skeleton, in order of their
+	/// [`TrackerId`](crate::skeleton::TrackerId).
+	pub input_trackers: Vec<TrackerConfig>,
+	/// The output trackers to add to the skeleton, in order of their
+	/// [`TrackerId`](crate::skeleton::TrackerId).
+	pub output_trackers: Vec<TrackerConfig>,
+}
+impl SkeletonConfig {
+	pub fn new(bone_lengths: BoneMap<f32>) -> Self {
+		SkeletonConfig {
+			bone_lengths,
+			input_trackers: Vec::new(),
+			output_trackers: Vec::new(),
+		}
+	}
+
+	/// Creates a config for a user that is `height` tall, using the default
+	/// [`BodyProportions`].
+	pub fn from_height(height: f32) -> Self {
+		Self::new(BodyProportions::default().bone_lengths(height))
+	}
+}
+
+/// Describes how a tracker is attached to the skeleton.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackerConfig {
+	pub bone: BoneKind,
+	/// The pose of the tracker relative to the head of the bone, in the local frame
+	/// of that bone. For input trackers, this is usually computed by calibration.
+	#[cfg_attr(feature = "serde", serde(default = "identity_offset"))]
+	pub offset: Local<Isometry>,
+	/// The yaw correction between the tracker's sensor space and global space, which
+	/// is found by a yaw reset. Only input trackers use this.
+	#[cfg_attr(feature = "serde", serde(default = "identity_yaw_fix"))]
+	pub yaw_fix: Global<UnitQuat>,
+}
+impl TrackerConfig {
+	pub fn new(bone: BoneKind) -> Self {
+		Self {
+			bone,
+			offset: identity_offset(),
+			yaw_fix: identity_yaw_fix(),
+		}
+	}
+}
+
+fn identity_offset() -> Local<Isometry> {
+	Local(Isometry::identity())
+}
+
+fn identity_yaw_fix() -> Global<UnitQuat> {
+	Global(UnitQuat::identity())
+}
+
+impl Skeleton {
+	/// Gets the current config of the skeleton, for example to save the results of
+	/// calibration. Creating a new skeleton from it restores the bone lengths, and the
+	/// trackers along with their offsets and yaw fixes.
+	pub fn config(&self) -> SkeletonConfig {
+		let bone_lengths = self.bone_map.map(|_kind, edge| self.graph[edge].length);
+		let mut config = SkeletonConfig::new(bone_lengths);
+		// The trackers are visited in order of their ids.
+		for edge in self.trackers.values() {
+			let edge = &self.graph[*edge];
+			let (bone, trackers) = match edge.kind {
+				EdgeKind::InputTracker(bone) => (bone, &mut config.input_trackers),
+				EdgeKind::OutputTracker(bone) => (bone, &mut config.output_trackers),
+				EdgeKind::Bone(_) => unreachable!("Trackers are never bones"),
+			};
+			trackers.push(TrackerConfig {
+				bone,
+				offset: Local(Isometry::from_parts(
+					edge.offset_l.0,
+					edge.calib_rot_l.0,
+				)),
+				yaw_fix: edge.yaw_fix_g,
+			});
+		}
+		config
+	}
+
+	/// Attaches the trackers of `config`, in order.
+	pub(crate) fn attach_configured_trackers(&mut self, config: &SkeletonConfig) {
+		for tracker in &config.input_trackers {
+			let id = self.attach_input_tracker(tracker.bone, None, None);
+			self.set_input_tracker_offset(id, tracker.offset).unwrap();
+			let (edge, _bone) = self.input_tracker(id).unwrap();
+			self.graph[edge].yaw_fix_g = tracker.yaw_fix;
+		}
+		for tracker in &config.output_trackers {
+			self.add_output_tracker(tracker.bone, tracker.offset);
+		}
+	}
+}
+
+#[cfg(feature = "serde")]
+pub use self::file::{ConfigError, ConfigFile};
+
+#[cfg(feature = "serde")]
+mod file {
+	use super::*;
+
+	use std::collections::BTreeMap;
+
+	/// The serialized form of a [`SkeletonConfig`].
+	#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+	#[serde(deny_unknown_fields)]
+	pub struct ConfigFile {
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		pub height: Option<f32>,
+		#[serde(default)]
+		pub bone_lengths: BTreeMap<BoneKind, f32>,
+		#[serde(default)]
+		pub input_trackers: Vec<TrackerConfig>,
+		#[serde(default)]
+		pub output_trackers: Vec<TrackerConfig>,
+	}
+
+	#[derive(thiserror::Error, Debug)]
+	pub enum ConfigError {
+		#[error(
+			"bone {} has no length, and there is no height to compute it from",
+			.0.snake_case_name()
+		)]
+		MissingBoneLength(BoneKind),
+	}
+
+	impl TryFrom<ConfigFile> for SkeletonConfig {
+		type Error = ConfigError;
+
+		fn try_from(other: ConfigFile) -> Result<Self, Self::Error> {
+			let defaults = other
+				.height
+				.map(|h| BodyProportions::default().bone_lengths(h));
+			let mut bone_lengths = BoneMap::new([0.; BoneKind::NUM_TYPES]);
+			for bone in BoneKind::iter() {
+				bone_lengths[bone] = match (other.bone_lengths.get(&bone), defaults) {
+					(Some(length), _) => *length,
+					(None, Some(defaults)) => defaults[bone],
+					(None, None) => return Err(ConfigError::MissingBoneLength(bone)),
+				};
+			}
+			Ok(Self {
+				bone_lengths,
+				input_trackers: other.input_trackers,
+				output_trackers: other.output_trackers,
+			})
+		}
+	}
+
+	impl From<SkeletonConfig> for ConfigFile {
+		fn from(other: SkeletonConfig) -> Self {
+			Self {
+				height: None,
+				bone_lengths: other.bone_lengths.into_iter().collect(),
+				input_trackers: other.input_trackers,
+				output_trackers: other.output_trackers,
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::skeleton::{CalibrationData, ResetKind};
+	use crate::Translation;
+
+	use approx::assert_relative_eq;
+	use nalgebra::Vector3;
+	use std::f32::consts::FRAC_PI_2;
+
+	fn make_config() -> SkeletonConfig {
+		let mut config = SkeletonConfig::from_height(1.75);
+		config
+			.input_trackers
+			.push(TrackerConfig::new(BoneKind::Neck));
+		config.input_trackers.push(TrackerConfig {
+			bone: BoneKind::ThighL,
+			offset: Local(Isometry::from_parts(
+				Translation::new(0., -0.2, -0.05),
+				UnitQuat::from_axis_angle(&Vector3::y_axis(), 0.5),
+			)),
+			yaw_fix: Global(UnitQuat::from_axis_angle(&Vector3::y_axis(), -0.3)),
+		});
+		config
+			.output_trackers
+			.push(TrackerConfig::new(BoneKind::Hip));
+		config
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let config = make_config();
+		let skeleton = Skeleton::new(&config);
+		assert_eq!(skeleton.config(), config);
+
+		let ids: Vec<_> = skeleton.input_trackers().collect();
+		assert_eq!(ids.len(), 2);
+		assert_eq!(ids[1].1, BoneKind::ThighL);
+		let offset = skeleton.input_tracker_offset(ids[1].0).unwrap();
+		assert_eq!(offset, config.input_trackers[1].offset);
+	}
+
+	#[test]
+	fn test_round_trip_yaw_reset() {
+		let mut skeleton = Skeleton::new(&make_config());
+		let (neck, _bone) = skeleton.input_trackers().next().unwrap();
+		// The neck tracker has drifted by 90 degrees of yaw.
+		let drift = UnitQuat::from_axis_angle(&Vector3::y_axis(), FRAC_PI_2);
+		let rot = Global(drift * BoneKind::Neck.calibration_rotation().0);
+		let sample = CalibrationData::ThreeDof { rot };
+		skeleton
+			.calibrate(ResetKind::Yaw, [(neck, sample)])
+			.unwrap();
+
+		let config = skeleton.config();
+		assert_relative_eq!(
+			config.input_trackers[0].yaw_fix.0,
+			drift.inverse(),
+			epsilon = 1e-6
+		);
+		assert_eq!(Skeleton::new(&config).config(), config);
+	}
+
+	#[test]
+	fn test_tracker_order() {
+		// Reattaching a tracker gives it the largest id, so it moves to the end.
+		let mut skeleton = Skeleton::new(&make_config());
+		let (neck, _bone) = skeleton.input_trackers().next().unwrap();
+		skeleton.detach_input_tracker(neck).unwrap();
+		skeleton.attach_input_tracker(BoneKind::Neck, None, None);
+
+		let bones: Vec<_> = skeleton
+			.config()
+			.input_trackers
+			.iter()
+			.map(|t| t.bone)
+			.collect();
+		assert_eq!(bones, [BoneKind::ThighL, BoneKind::Neck]);
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn test_serde() {
+		let config = make_config();
+		let json = serde_json::to_string(&config).unwrap();
+		assert_eq!(
+			serde_json::from_str::<SkeletonConfig>(&json).unwrap(),
+			config
+		);
+		let toml = toml::to_string(&config).unwrap();
+		assert_eq!(toml::from_str::<SkeletonConfig>(&toml).unwrap(), config);
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn test_file_format() {
+		// This is the example from the docs of `SkeletonConfig`.
+		let file = r#"
+			height = 1.75
+
+			[bone_lengths]
+			thigh_l = 0.45
+			thigh_r = 0.45
+
+			[[input_trackers]]
+			bone = "chest"
+			offset = { translation = [0.0, 0.0, -0.1], rotation = [0.0, 0.0, 0.0, 1.0] }
+
+			[[input_trackers]]
+			bone = "thigh_l"
+
+			[[output_trackers]]
+			bone = "hip"
+		"#;
+		let config: SkeletonConfig = toml::from_str(file).unwrap();
+		let defaults = SkeletonConfig::from_height(1.75).bone_lengths;
+		assert_eq!(config.bone_lengths[BoneKind::ThighL], 0.45);
+		assert_eq!(
+			config.bone_lengths[BoneKind::Neck],
+			defaults[BoneKind::Neck]
+		);
+		assert_eq!(
+			config.input_trackers[0].offset.0.translation,
+			Translation::new(0., 0., -0.1)
+		);
+		assert_eq!(
+			config.input_trackers[1],
+			TrackerConfig::new(BoneKind::ThighL)
+		);
+		assert_eq!(
+			config.output_trackers,
+			vec![TrackerConfig::new(BoneKind::Hip)]
+		);
+
+		// Without a height, every bone length is required.
+		let err =
+			toml::from_str::<SkeletonConfig>("[bone_lengths]\nneck = 0.1").unwrap_err();
+		assert!(err.to_string().contains("bone head has no length"), "{err}");
+	}
+}
@@ -0,0 +1,432 @@
+//! Contains the optional joint constraints stage of the solver.
+//!
+//! Nothing in the inputs stops a bone from rotating in a way that a human joint can't.
+//! A tracker that slipped or drifted can bend a knee backwards, or twist the head all
+//! the way around. This stage projects the rotation of each bone relative to its
+//! parent onto the range that its [`JointConstraint`] allows.
+//!
+//! Rotations are measured from the calibration pose, so the identity means that a bone
+//! is posed the same way relative to its parent as during calibration. Joints are
+//! constrained outward from the anchors, which are the bones with a positional tracker
+//! such as the headset. Those trackers don't drift, so the anchors are never rotated.
+//! Each joint is fixed by rotating the bone on the side away from the anchors,
+//! relative to the already constrained bone on the other side. So when a drifted chest
+//! tracker makes the neck look twisted, the chest gives way instead of the head. A
+//! joint between two bones that are both reached from different anchors is left as it
+//! is. Without any positional trackers, the hip is the anchor.
+//!
+//! This stage runs right after forward-kinematics. If it changed any rotation, the
+//! positions of the nodes are solved again from the new rotations.
+
+use crate::bone::BoneMap;
+use crate::conventions::{right_vec, up_vec};
+use crate::newtypes::Global;
+use crate::skeleton::EdgeKind;
+use crate::{BoneKind, Skeleton, UnitQuat};
+
+use nalgebra::{Unit, Vector3};
+
+/// Constraints that are violated by less than this angle, in radians, are not
+/// reported.
+const TOLERANCE: f32 = 1e-4;
+
+/// The range of rotations that a bone may have relative to its parent bone. Axes are
+/// in global space, as it is in the calibration pose.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JointConstraint {
+	/// The bone may rotate freely.
+	Free,
+	/// The bone may only rotate around `axis`, between `min` and `max` radians.
+	/// Rotation around any other axis is removed.
+	Hinge {
+		axis: Unit<Vector3<f32>>,
+		min: f32,
+		max: f32,
+	},
+	/// The bone may swing away from its calibration direction by up to `swing`
+	/// radians in any direction, and twist around its own length by up to `twist`
+	/// radians either way.
+	SwingTwist { swing: f32, twist: f32 },
+}
+impl JointConstraint {
+	/// The range of motion of a typical human joint at the head of `bone`. The fingers
+	/// are left free.
+	pub fn anatomical(bone: BoneKind) -> Self {
+		use BoneKind::*;
+		let swing_twist = |swing: f32, twist: f32| Self::SwingTwist {
+			swing: swing.to_radians(),
+			twist: twist.to_radians(),
+		};
+		let hinge = |min: f32, max: f32| Self::Hinge {
+			axis: right_vec(),
+			min: min.to_radians(),
+			max: max.to_radians(),
+		};
+		match bone {
+			Neck => swing_twist(60., 80.),
+			UpperChest | Chest | Waist | Hip => swing_twist(30., 30.),
+			ThighL | ThighR => swing_twist(120., 45.),
+			// Knees bend backwards, which is a negative rotation around +X.
+			AnkleL | AnkleR => hinge(-150., 5.),
+			FootL | FootR => swing_twist(50., 30.),
+			ToesL | ToesR => hinge(-30., 60.),
+			ShoulderL | ShoulderR => swing_twist(30., 20.),
+			UpperArmL | UpperArmR => swing_twist(180., 90.),
+			// Elbows bend forwards, which is a positive rotation around +X.
+			ForearmL | ForearmR => hinge(-5., 150.),
+			HandL | HandR => swing_twist(80., 90.),
+			_ => Self::Free,
+		}
+	}
+
+	/// [`Self::anatomical()`] for every bone.
+	pub fn anatomical_limits() -> BoneMap<Self> {
+		BoneMap::default().map(|bone, ()| Self::anatomical(bone))
+	}
+
+	/// Finds the closest rotation to `local` that is allowed, where `local` is the
+	/// rotation of `bone` relative to its parent.
+	fn project(&self, bone: BoneKind, local: UnitQuat) -> UnitQuat {
+		match *self {
+			Self::Free => local,
+			Self::Hinge { axis, min, max } => {
+				let (_swing, angle) = swing_twist(&local, &axis);
+				UnitQuat::from_axis_angle(&axis, angle.clamp(min, max))
+			}
+			Self::SwingTwist { swing, twist } => {
+				let dir = -(bone.calibration_rotation().0 * up_vec());
+				let (swing_rot, twist_angle) = swing_twist(&local, &dir);
+				let swing_angle = swing_rot.angle();
+				let swing_rot = if swing_angle > swing {
+					swing_rot.powf(swing / swing_angle)
+				} else {
+					swing_rot
+				};
+				swing_rot
+					* UnitQuat::from_axis_angle(&dir, twist_angle.clamp(-twist, twist))
+			}
+		}
+	}
+}
+
+/// Describes which joint constraints had to correct the pose.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConstraintReport {
+	/// The bones whose joint was outside of its constraint, and the angle in radians
+	/// that the joint was corrected by. A joint is identified by the bone whose head
+	/// it is at.
+	pub active: Vec<(BoneKind, f32)>,
+}
+
+impl Skeleton {
+	/// Sets the constraints that [`Skeleton::solve()`] applies to each bone, or turns
+	/// them off with `None`, which is the default.
+	pub fn set_joint_constraints(
+		&mut self,
+		constraints: Option<BoneMap<JointConstraint>>,
+	) {
+		self.joint_constraints = constraints;
+	}
+
+	pub fn joint_constraints(&self) -> Option<&BoneMap<JointConstraint>> {
+		self.joint_constraints.as_ref()
+	}
+
+	/// Constrains the rotations of an already solved skeleton. Only rotations are
+	/// updated, the caller has to solve the positions again if any constraint was
+	/// active.
+	pub(crate) fn apply_constraints(
+		&mut self,
+		constraints: &BoneMap<JointConstraint>,
+	) -> ConstraintReport {
+		// The rotations of the bones away from their calibration pose.
+		let mut from_rest = self.bone_map.map(|bone, edge| {
+			self.graph[edge].output_rot_g.0 * bone.calibration_rotation().0.inverse()
+		});
+		let mut visited = BoneMap::<bool>::default();
+		let mut report = ConstraintReport::default();
+		let mut stack = self.constraint_anchors();
+		for &anchor in &stack {
+			visited[anchor] = true;
+		}
+		while let Some(bone) = stack.pop() {
+			// The joints to the children of `bone` are at their heads, and the joint
+			// to its parent is at its own head.
+			let joints = bone
+				.children()
+				.iter()
+				.map(|&child| (child, child, bone))
+				.chain(bone.parent().map(|parent| (parent, bone, parent)));
+			for (next, child, parent) in joints {
+				if visited[next] {
+					continue;
+				}
+				visited[next] = true;
+				stack.push(next);
+
+				let local = from_rest[parent].inverse() * from_rest[child];
+				let projected = constraints[child].project(child, local);
+				let error = local.angle_to(&projected);
+				if error <= TOLERANCE {
+					continue;
+				}
+				from_rest[next] = if next == child {
+					from_rest[parent] * projected
+				} else {
+					from_rest[child] * projected.inverse()
+				};
+				let rot = Global(from_rest[next] * next.calibration_rotation().0);
+				self.graph[self.bone_map[next]].output_rot_g = rot;
+				report.active.push((child, error));
+			}
+		}
+
+		// Trackers without an input rotation follow their bone.
+		if !report.active.is_empty() {
+			let edges: Vec<_> = self.graph.edge_indices().collect();
+			for edge in edges {
+				let e = &self.graph[edge];
+				let bone = match e.kind {
+					EdgeKind::InputTracker(bone) | EdgeKind::OutputTracker(bone)
+						if e.input_rot_g.is_none() =>
+					{
+						bone
+					}
+					_ => continue,
+				};
+				let bone_rot_g = self.graph[self.bone_map[bone]].output_rot_g.0;
+				let e = &mut self.graph[edge];
+				e.output_rot_g = Global(bone_rot_g * e.calib_rot_l.0);
+			}
+		}
+		report.active.sort_by_key(|(bone, _error)| *bone);
+		report
+	}
+
+	/// The bones that the joint constraints start from. See the module docs.
+	fn constraint_anchors(&self) -> Vec<BoneKind> {
+		let mut anchors: Vec<_> = self
+			.graph
+			.edge_indices()
+			.filter_map(|e| {
+				let EdgeKind::InputTracker(bone) = self.graph[e].kind else {
+					return None;
+				};
+				let (_bone_head, node) = self.graph.edge_endpoints(e).unwrap();
+				self.graph[node].input_pos_g.map(|_| bone)
+			})
+			.collect();
+		anchors.sort();
+		anchors.dedup();
+		if anchors.is_empty() {
+			anchors.push(BoneKind::Hip);
+		}
+		// The stack pops from the end, so the first anchor gets to go first.
+		anchors.reverse();
+		anchors
+	}
+}
+
+/// Splits `q` into a swing followed by a twist around `axis`, so that
+/// `q = swing * twist`. Returns the swing, and the angle of the twist in `[-PI, PI]`.
+fn swing_twist(q: &UnitQuat, axis: &Unit<Vector3<f32>>) -> (UnitQuat, f32) {
+	// `q` and `-q` are the same rotation, picking the one with a positive `w` keeps the
+	// angle in range.
+	let (w, imag) = if q.w < 0. {
+		(-q.w, -q.imag())
+	} else {
+		(q.w, q.imag())
+	};
+	let angle = 2. * imag.dot(axis).atan2(w);
+	let twist = UnitQuat::from_axis_angle(axis, angle);
+	(q * twist.inverse(), angle)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::skeleton::SkeletonConfig;
+	use crate::{Point, TrackerId};
+
+	use approx::assert_relative_eq;
+	use std::f32::consts::PI;
+
+	fn rot_x(angle: f32) -> UnitQuat {
+		UnitQuat::from_axis_angle(&Vector3::x_axis(), angle)
+	}
+
+	/// A skeleton with a headset, and 3DoF trackers on the spine and the left leg.
+	fn make_skeleton() -> (Skeleton, TrackerId, BoneMap<Option<TrackerId>>) {
+		use BoneKind::*;
+		let mut skeleton = Skeleton::new(&SkeletonConfig::from_height(1.8));
+		let hmd = skeleton.attach_input_tracker(
+			Head,
+			Some(Global(Point::new(0., 1.7, 0.))),
+			None,
+		);
+		let mut trackers = BoneMap::default();
+		for bone in [Chest, Hip, ThighL, AnkleL, FootL] {
+			trackers[bone] = Some(skeleton.attach_input_tracker(bone, None, None));
+		}
+		(skeleton, hmd, trackers)
+	}
+
+	fn set_rot(
+		skeleton: &mut Skeleton,
+		trackers: &BoneMap<Option<TrackerId>>,
+		bone: BoneKind,
+		rot: UnitQuat,
+	) {
+		let rot = Global(rot * bone.calibration_rotation().0);
+		let id = trackers[bone].unwrap();
+		skeleton.update_input_tracker(id, None, Some(rot)).unwrap();
+	}
+
+	/// Checks that every bone is within its anatomical range, and that the positions
+	/// of the nodes agree with the rotations.
+	fn assert_within_limits(skeleton: &Skeleton) {
+		for bone in BoneKind::iter() {
+			let from_rest = |b: BoneKind| {
+				skeleton[b].output_rot_g.0 * b.calibration_rotation().0.inverse()
+			};
+			if let Some(parent) = bone.parent() {
+				let local = from_rest(parent).inverse() * from_rest(bone);
+				let projected = JointConstraint::anatomical(bone).project(bone, local);
+				assert!(local.angle_to(&projected) < 1e-3, "{bone:?}");
+
+				let head = skeleton.bone_pose(bone).0.translation.vector;
+				let parent_tail = skeleton.bone_pose(parent).0
+					* Point::from(-up_vec().into_inner() * skeleton[parent].length);
+				assert_relative_eq!(head, parent_tail.coords, epsilon = 1e-5);
+			}
+		}
+	}
+
+	#[test]
+	fn test_swing_twist() {
+		let axis = Vector3::y_axis();
+		let twist = UnitQuat::from_axis_angle(&axis, 2.5);
+		let swing = rot_x(0.3);
+		let (s, angle) = swing_twist(&(swing * twist), &axis);
+		assert_relative_eq!(angle, 2.5, epsilon = 1e-5);
+		assert_relative_eq!(s, swing, epsilon = 1e-5);
+		// The twist stays in range, even past half a turn.
+		let (_s, angle) = swing_twist(&UnitQuat::from_axis_angle(&axis, 4.), &axis);
+		assert_relative_eq!(angle, 4. - 2. * PI, epsilon = 1e-5);
+
+		let cone = JointConstraint::SwingTwist {
+			swing: 0.1,
+			twist: 1.,
+		};
+		let projected = cone.project(BoneKind::Chest, swing * twist);
+		let (s, angle) = swing_twist(&projected, &axis);
+		assert_relative_eq!(angle, 1., epsilon = 1e-5);
+		assert_relative_eq!(s, rot_x(0.1), epsilon = 1e-5);
+	}
+
+	#[test]
+	fn test_disabled() {
+		let (mut skeleton, _hmd, trackers) = make_skeleton();
+		set_rot(&mut skeleton, &trackers, BoneKind::AnkleL, rot_x(1.));
+		let report = skeleton.solve().unwrap();
+		assert_eq!(report.constraints, None);
+		assert_relative_eq!(skeleton[BoneKind::AnkleL].output_rot_g.0, rot_x(1.));
+	}
+
+	#[test]
+	fn test_within_limits() {
+		use BoneKind::*;
+		let (mut skeleton, _hmd, trackers) = make_skeleton();
+		set_rot(&mut skeleton, &trackers, ThighL, rot_x(1.5));
+		set_rot(&mut skeleton, &trackers, AnkleL, rot_x(-0.5));
+		skeleton.solve().unwrap();
+		let unconstrained = skeleton.clone();
+
+		skeleton.set_joint_constraints(Some(JointConstraint::anatomical_limits()));
+		let report = skeleton.solve().unwrap();
+		assert_eq!(report.constraints.unwrap().active, []);
+		for bone in BoneKind::iter() {
+			assert_relative_eq!(
+				skeleton.bone_pose(bone).0,
+				unconstrained.bone_pose(bone).0,
+				epsilon = 1e-6
+			);
+		}
+	}
+
+	#[test]
+	fn test_backwards_knee() {
+		use BoneKind::*;
+		let (mut skeleton, _hmd, trackers) = make_skeleton();
+		skeleton.set_joint_constraints(Some(JointConstraint::anatomical_limits()));
+		// The shin swings forward past the thigh, and twists a bit.
+		let twist = UnitQuat::from_axis_angle(&Vector3::y_axis(), 0.2);
+		set_rot(&mut skeleton, &trackers, AnkleL, rot_x(1.) * twist);
+		let report = skeleton.solve().unwrap().constraints.unwrap();
+
+		assert_eq!(report.active.len(), 1);
+		let (bone, error) = report.active[0];
+		assert_eq!(bone, AnkleL);
+		assert!(error > 0.9);
+		assert_relative_eq!(
+			skeleton[AnkleL].output_rot_g.0,
+			rot_x(5f32.to_radians()),
+			epsilon = 1e-5
+		);
+		// The foot tracker follows its bone, and the foot still hangs off the shin.
+		assert_within_limits(&skeleton);
+		// The shin tracker keeps its input.
+		let id = trackers[AnkleL].unwrap();
+		let tracker = &skeleton.graph[skeleton.trackers[&id]];
+		assert_relative_eq!(tracker.output_rot_g.0, rot_x(1.) * twist);
+	}
+
+	#[test]
+	fn test_neck_twist() {
+		use BoneKind::*;
+		let (mut skeleton, hmd, _trackers) = make_skeleton();
+		skeleton.set_joint_constraints(Some(JointConstraint::anatomical_limits()));
+		// The headset turns around, but the chest tracker doesn't.
+		let turned = UnitQuat::from_axis_angle(&Vector3::y_axis(), 3.);
+		let pos = Some(Global(Point::new(0., 1.7, 0.)));
+		skeleton
+			.update_input_tracker(hmd, pos, Some(Global(turned)))
+			.unwrap();
+		let report = skeleton.solve().unwrap().constraints.unwrap();
+		let bones: Vec<_> = report.active.iter().map(|(b, _)| *b).collect();
+		assert_eq!(bones[0], Neck);
+		// The torso turns with the headset, as far as it has to.
+		assert_relative_eq!(skeleton[Head].output_rot_g.0, turned, epsilon = 1e-5);
+		assert_within_limits(&skeleton);
+	}
+
+	#[test]
+	fn test_head_turn_keeps_head() {
+		use BoneKind::*;
+		let (mut skeleton, hmd, trackers) = make_skeleton();
+		skeleton.set_joint_constraints(Some(JointConstraint::anatomical_limits()));
+		// The chest tracker drifted around by half a turn, so the neck looks twisted
+		// against the headset.
+		let pos = Some(Global(Point::new(0., 1.7, 0.)));
+		skeleton
+			.update_input_tracker(hmd, pos, Some(Global(UnitQuat::identity())))
+			.unwrap();
+		let drifted = UnitQuat::from_axis_angle(&Vector3::y_axis(), PI);
+		set_rot(&mut skeleton, &trackers, Chest, drifted);
+		let report = skeleton.solve().unwrap().constraints.unwrap();
+
+		// The headset doesn't drift, so the head keeps its rotation and the chest
+		// gives way.
+		assert_relative_eq!(
+			skeleton[Head].output_rot_g.0,
+			UnitQuat::identity(),
+			epsilon = 1e-5
+		);
+		let chest_rot = skeleton[Chest].output_rot_g.0;
+		assert!(chest_rot.angle_to(&drifted) > 1.);
+		assert_relative_eq!(chest_rot.angle(), 30f32.to_radians(), epsilon = 1e-3);
+		let bones: Vec<_> = report.active.iter().map(|(b, _)| *b).collect();
+		assert_eq!(bones, [Chest]);
+		assert_within_limits(&skeleton);
+	}
+}
@@ -1,8 +1,10 @@
 use derive_more::From;
+use nalgebra::Vector3;
 
 use crate::{
+	conventions::up_vec,
 	newtypes::{Global, Local},
-	BoneKind, UnitQuat,
+	BoneKind, Translation, UnitQuat,
 };
 
 /// The different kinds of edges.
@@ -11,11 +13,13 @@ pub enum EdgeKind {
 	/// Represents a regular bone in the skeleton.
 	Bone(BoneKind),
 	/// Represents a tracker that is providing pose information as an input to the
-	/// skeleton.
-	InputTracker,
+	/// skeleton. Contains the bone that the tracker is attached to.
+	#[from(ignore)]
+	InputTracker(BoneKind),
 	/// Represents a computed/synthetic tracker that will act as an output tracker for
-	/// the skeleton.
-	OutputTracker,
+	/// the skeleton. Contains the bone that the tracker is attached to.
+	#[from(ignore)]
+	OutputTracker(BoneKind),
 }
 
 /// `Edge`s represent the connections between the [`Node`]s of the
@@ -33,16 +37,28 @@ pub enum EdgeKind {
 /// For more information, see the [`skeleton`](crate::skeleton) module.
 ///
 /// [`Node`]: crate::skeleton::Node
+#[derive(Debug, Clone)]
 #[non_exhaustive]
 pub struct Edge {
 	pub kind: EdgeKind,
 	/// Input rotation in global space. If it is unconstrained, it is `None`.
+	///
+	/// For bones, this is computed from the attached input trackers when solving.
 	pub input_rot_g: Option<Global<UnitQuat>>,
 	/// Local rotation of the edge with respect to the parent edge at calibration time.
 	/// Maps from parent frame to child frame.
 	pub calib_rot_l: Local<UnitQuat>,
 	/// Length of the edge. May be set by the user, or may be computed at calibration.
+	///
+	/// Tracker edges don't use this, and use [`Self::offset_l`] instead.
 	pub length: f32,
+	/// For tracker edges, the position of the tracker relative to the head of the bone
+	/// it is attached to, in the local frame of that bone. Bones don't use this.
+	pub offset_l: Local<Translation>,
+	/// For input trackers, a rotation that gets applied to the tracker's rotation before
+	/// it is stored in [`Self::input_rot_g`]. This aligns the yaw of the tracker's
+	/// sensor space with global space, and is computed during calibration.
+	pub yaw_fix_g: Global<UnitQuat>,
 	/// The output rotation of the edge. Solving the skeleton updates this.
 	pub output_rot_g: Global<UnitQuat>,
 }
@@ -58,7 +74,38 @@ impl Edge {
 			input_rot_g: None,
 			calib_rot_l,
 			length,
+			offset_l: Translation::identity().into(),
+			yaw_fix_g: UnitQuat::identity().into(),
 			output_rot_g: Default::default(),
 		}
 	}
+
+	/// The global rotation of the edge when the skeleton is in the calibration pose.
+	///
+	/// For bones, this is what the solver outputs when there is no
+	/// [`Self::input_rot_g`].
+	pub fn calibration_rot_g(&self) -> Global<UnitQuat> {
+		match self.kind {
+			EdgeKind::Bone(k) => k.calibration_rotation(),
+			EdgeKind::InputTracker(k) | EdgeKind::OutputTracker(k) => {
+				Global(k.calibration_rotation().0 * self.calib_rot_l.0)
+			}
+		}
+	}
+
+	/// The vector from the head of the edge to its tail, in global space. This is
+	/// computed from [`Self::output_rot_g`], so it is only meaningful after solving.
+	pub(crate) fn head_to_tail_g(&self) -> Vector3<f32> {
+		match self.kind {
+			// The head of a bone is the end nearer the root, but its rotation turns the
+			// up vector to point from the tail back to the head. So we negate it.
+			EdgeKind::Bone(_) => {
+				-(self.output_rot_g.0 * up_vec()).into_inner() * self.length
+			}
+			EdgeKind::InputTracker(_) | EdgeKind::OutputTracker(_) => {
+				let bone_rot_g = self.output_rot_g.0 * self.calib_rot_l.0.inverse();
+				bone_rot_g * self.offset_l.0.vector
+			}
+		}
+	}
 }
@@ -0,0 +1,447 @@
+//! Contains the optional inverse-kinematics (IK) stage of the solver.
+//!
+//! The forward-kinematics pass in [`solver`](super::solver) pins every node that has an
+//! input position, and expands outward from them. When there are several positional
+//! constraints, such as a headset and two foot trackers, this means that the bones
+//! where the traversals meet are stretched or squashed.
+//!
+//! The IK stage fixes this by using [FABRIK] over the tree of the skeleton. The
+//! positional constraint with the smallest index becomes the "anchor" of the tree, and
+//! all other positional constraints become targets that the skeleton reaches towards.
+//! Each iteration has two passes:
+//! * Backward pass: Starting at the targets, move each node towards its child, keeping
+//!   the length of the edge between them. Nodes with several children (like the hip)
+//!   take the average of what each child wants.
+//! * Forward pass: Starting at the anchor, move each node back towards its parent to
+//!   restore the length of the edge, and limit how far the edge may bend away from
+//!   the direction that forward-kinematics gave it.
+//!
+//! Afterwards, the bones are rotated to match the new node positions, and every node
+//! is recomputed from the anchor. Whatever distance remains between each target and
+//! its tracker is reported as the residual error.
+//!
+//! [FABRIK]: http://www.andreasaristidou.com/FABRIK.html
+
+use crate::bone::BoneMap;
+use crate::newtypes::Global;
+use crate::skeleton::{EdgeKind, TrackerId};
+use crate::{BoneKind, Point, Skeleton, UnitQuat};
+
+use nalgebra::Vector3;
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, FRAC_PI_6, PI};
+
+/// Controls which stages the solver runs. See [`Skeleton::set_solver_mode()`].
+// The mode is rarely changed and never stored in bulk, so its size doesn't matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SolverMode {
+	/// Only forward-kinematics. This is the default.
+	#[default]
+	Fk,
+	/// Forward-kinematics, followed by inverse-kinematics to satisfy all positional
+	/// constraints at once.
+	FkIk(IkSettings),
+}
+
+/// Settings for the inverse-kinematics stage of the solver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IkSettings {
+	/// The maximum number of FABRIK iterations.
+	pub max_iterations: usize,
+	/// Iteration stops once all targets are closer than this distance.
+	pub tolerance: f32,
+	/// The maximum angle, in radians, that IK may bend each bone away from the
+	/// rotation that forward-kinematics gave it.
+	pub joint_limits: BoneMap<f32>,
+}
+impl Default for IkSettings {
+	fn default() -> Self {
+		use BoneKind::*;
+		let joint_limits =
+			BoneMap::new([0.; BoneKind::NUM_TYPES]).map(|kind, _| match kind {
+				Head | Neck | UpperChest | Chest | Waist | Hip => FRAC_PI_6,
+				ThighL | ThighR | AnkleL | AnkleR => FRAC_PI_2,
+				FootL | FootR | ToesL | ToesR | HandL | HandR => FRAC_PI_4,
+				ShoulderL | ShoulderR => FRAC_PI_6,
+				UpperArmL | UpperArmR | ForearmL | ForearmR => FRAC_PI_2,
+				// Fingers
+				_ => FRAC_PI_2,
+			});
+		Self {
+			max_iterations: 16,
+			tolerance: 0.001,
+			joint_limits,
+		}
+	}
+}
+
+/// Describes how well the inverse-kinematics stage satisfied the positional
+/// constraints.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IkReport {
+	/// The number of FABRIK iterations that ran.
+	pub iterations: usize,
+	/// The distance between each positional input tracker and where the skeleton
+	/// actually put it. The tracker used as the anchor is not included, as it is always
+	/// satisfied exactly.
+	pub residuals: Vec<(TrackerId, f32)>,
+}
+impl IkReport {
+	/// The largest of the [`Self::residuals`].
+	pub fn max_residual(&self) -> f32 {
+		self.residuals.iter().map(|(_id, r)| *r).fold(0., f32::max)
+	}
+}
+
+impl Skeleton {
+	/// Sets which stages the solver runs in [`Skeleton::solve()`].
+	pub fn set_solver_mode(&mut self, mode: SolverMode) {
+		self.solver_mode = mode;
+	}
+
+	pub fn solver_mode(&self) -> SolverMode {
+		self.solver_mode
+	}
+
+	/// Runs inverse-kinematics on top of an already solved skeleton.
+	pub(crate) fn solve_ik(&mut self, settings: &IkSettings) -> IkReport {
+		let Some(anchor) = self.find_root_nodes().next() else {
+			return IkReport::default();
+		};
+		let tree = Tree::new(self, anchor);
+
+		// Targets are always tracker nodes, so their parent edge is the tracker.
+		let targets: Vec<(NodeIndex, Point)> = self
+			.find_root_nodes()
+			.filter(|n| *n != anchor)
+			.map(|n| (n, self.graph[n].input_pos_g.unwrap().0))
+			.collect();
+		let active = tree.active_nodes(targets.iter().map(|(n, _)| *n));
+
+		// The direction and length of every edge from parent to child, according to
+		// forward-kinematics.
+		let fk_dirs: HashMap<NodeIndex, (Vector3<f32>, f32)> = tree.order[1..]
+			.iter()
+			.map(|n| {
+				let dir = self.parent_to_child_g(&tree, *n);
+				let len = dir.norm();
+				let dir = if len > 0. { dir / len } else { dir };
+				(*n, (dir, len))
+			})
+			.collect();
+		let limit = |edge: EdgeIndex| match self.graph[edge].kind {
+			EdgeKind::Bone(k) => settings.joint_limits[k],
+			_ => PI,
+		};
+
+		let mut pos: HashMap<NodeIndex, Point> = HashMap::new();
+		pos.insert(anchor, self.graph[anchor].input_pos_g.unwrap().0);
+		for n in &tree.order[1..] {
+			let (dir, len) = fk_dirs[n];
+			pos.insert(*n, pos[&tree.parent[n].0] + dir * len);
+		}
+
+		let mut iterations = 0;
+		while iterations < settings.max_iterations {
+			let residual = targets
+				.iter()
+				.map(|(n, target)| (pos[n] - target).norm())
+				.fold(0., f32::max);
+			if residual <= settings.tolerance {
+				break;
+			}
+			iterations += 1;
+
+			// Backward pass, from the targets to the anchor.
+			let mut reached: HashMap<NodeIndex, Point> = HashMap::new();
+			let mut wanted: HashMap<NodeIndex, (Vector3<f32>, f32)> = HashMap::new();
+			for (n, target) in targets.iter() {
+				reached.insert(*n, *target);
+			}
+			for n in tree.order[1..].iter().rev().filter(|n| active.contains(n)) {
+				let p = *reached.entry(*n).or_insert_with(|| {
+					let (sum, count) = wanted[n];
+					Point::from(sum / count)
+				});
+				let (parent, _edge) = tree.parent[n];
+				let (_dir, len) = fk_dirs[n];
+				let to_parent = pos[&parent] - p;
+				let to_parent =
+					to_parent.try_normalize(0.).unwrap_or_else(Vector3::zeros);
+				let w = wanted.entry(parent).or_insert((Vector3::zeros(), 0.));
+				w.0 += (p + to_parent * len).coords;
+				w.1 += 1.;
+			}
+
+			// Forward pass, from the anchor to the targets.
+			for n in tree.order[1..].iter().filter(|n| active.contains(n)) {
+				let (parent, edge) = tree.parent[n];
+				let (fk_dir, len) = fk_dirs[n];
+				let dir = (reached[n] - pos[&parent])
+					.try_normalize(0.)
+					.unwrap_or(fk_dir);
+				let dir = clamp_angle(fk_dir, dir, limit(edge));
+				pos.insert(*n, pos[&parent] + dir * len);
+			}
+		}
+
+		// Rotate the bones so that they point along the new positions.
+		for n in tree.order[1..].iter().filter(|n| active.contains(n)) {
+			let (parent, edge) = tree.parent[n];
+			let EdgeKind::Bone(_) = self.graph[edge].kind else {
+				continue;
+			};
+			let (fk_dir, _len) = fk_dirs[n];
+			let new_dir = pos[n] - pos[&parent];
+			if let Some(delta) = UnitQuat::rotation_between(&fk_dir, &new_dir) {
+				let e = &mut self.graph[edge];
+				e.output_rot_g = Global(delta * e.output_rot_g.0);
+			}
+		}
+		self.refresh_from_anchor(&tree);
+
+		let residuals = targets
+			.iter()
+			.map(|(n, target)| {
+				let (_parent, edge) = tree.parent[n];
+				let error = (self.graph[*n].output_pos_g.0 - target).norm();
+				(self.tracker_id(edge).unwrap(), error)
+			})
+			.collect();
+		IkReport {
+			iterations,
+			residuals,
+		}
+	}
+
+	/// Recomputes the rotations of tracker edges that follow their bone, and then the
+	/// positions of all nodes, starting at the anchor of `tree`.
+	fn refresh_from_anchor(&mut self, tree: &Tree) {
+		let edges: Vec<_> = self.graph.edge_indices().collect();
+		for edge in edges {
+			let e = &self.graph[edge];
+			let bone = match e.kind {
+				EdgeKind::InputTracker(bone) | EdgeKind::OutputTracker(bone)
+					if e.input_rot_g.is_none() =>
+				{
+					bone
+				}
+				_ => continue,
+			};
+			let bone_rot_g = self.graph[self.bone_map[bone]].output_rot_g.0;
+			let e = &mut self.graph[edge];
+			e.output_rot_g = Global(bone_rot_g * e.calib_rot_l.0);
+		}
+
+		for n in &tree.order[1..] {
+			let (parent, _edge) = tree.parent[n];
+			let pos =
+				self.graph[parent].output_pos_g.0 + self.parent_to_child_g(tree, *n);
+			self.graph[*n].output_pos_g = Global(pos);
+		}
+	}
+
+	/// The vector from the parent of `n` in `tree` to `n`, based on the current
+	/// [`Edge::output_rot_g`](crate::skeleton::Edge::output_rot_g).
+	fn parent_to_child_g(&self, tree: &Tree, n: NodeIndex) -> Vector3<f32> {
+		let (parent, edge) = tree.parent[&n];
+		let head_to_tail = self.graph[edge].head_to_tail_g();
+		let (head, _tail) = self.graph.edge_endpoints(edge).unwrap();
+		if parent == head {
+			head_to_tail
+		} else {
+			-head_to_tail
+		}
+	}
+}
+
+/// The skeleton graph, viewed as a tree with `anchor` as its root.
+struct Tree {
+	/// All nodes in breadth-first order, starting at the anchor.
+	order: Vec<NodeIndex>,
+	/// The parent node of each node, and the edge that connects them.
+	parent: HashMap<NodeIndex, (NodeIndex, EdgeIndex)>,
+}
+impl Tree {
+	fn new(skeleton: &Skeleton, anchor: NodeIndex) -> Self {
+		let g = &skeleton.graph;
+		let mut order = vec![anchor];
+		let mut parent = HashMap::with_capacity(g.node_count());
+		let mut i = 0;
+		while let Some(&n) = order.get(i) {
+			i += 1;
+			let mut neighbors = g.neighbors(n).detach();
+			while let Some((edge, child)) = neighbors.next(g) {
+				if child != anchor && !parent.contains_key(&child) {
+					parent.insert(child, (n, edge));
+					order.push(child);
+				}
+			}
+		}
+		Self { order, parent }
+	}
+
+	/// The nodes that lie on the path between the anchor and any of the `targets`.
+	/// Only these nodes get moved by IK.
+	fn active_nodes(
+		&self,
+		targets: impl Iterator<Item = NodeIndex>,
+	) -> HashSet<NodeIndex> {
+		let mut active = HashSet::new();
+		for mut n in targets {
+			while let Some((parent, _edge)) = self.parent.get(&n) {
+				if !active.insert(n) {
+					break;
+				}
+				n = *parent;
+			}
+		}
+		active
+	}
+}
+
+/// Rotates `dir` towards `reference` until the angle between them is at most `limit`.
+fn clamp_angle(reference: Vector3<f32>, dir: Vector3<f32>, limit: f32) -> Vector3<f32> {
+	let angle = reference.angle(&dir);
+	if angle <= limit {
+		return dir;
+	}
+	match UnitQuat::rotation_between(&reference, &dir) {
+		Some(rot) => rot.powf(limit / angle) * reference,
+		None => dir,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::skeleton::SkeletonConfig;
+
+	use approx::assert_relative_eq;
+
+	/// Same proportions as the solver tests: the ankle is 1.5 below the head.
+	fn make_skeleton() -> (Skeleton, TrackerId, TrackerId) {
+		use BoneKind::*;
+		let bone_lengths =
+			BoneMap::new([0.; BoneKind::NUM_TYPES]).map(|kind, _| match kind {
+				Neck => 0.1,
+				Chest => 0.2,
+				Waist => 0.2,
+				Hip => 0.1,
+				ThighL | ThighR => 0.5,
+				AnkleL | AnkleR => 0.4,
+				FootL | FootR => 0.2,
+				UpperArmL | UpperArmR => 0.3,
+				ForearmL | ForearmR => 0.25,
+				HandL | HandR => 0.1,
+				// The remaining bones are collapsed, so they don't affect the pose.
+				_ => 0.,
+			});
+		let mut skeleton = Skeleton::new(&SkeletonConfig::new(bone_lengths));
+		skeleton.attach_input_tracker(
+			Neck,
+			Some(Global(Point::new(0., 1.5, 0.))),
+			None,
+		);
+		let left = skeleton.attach_input_tracker(FootL, None, None);
+		let right = skeleton.attach_input_tracker(FootR, None, None);
+		(skeleton, left, right)
+	}
+
+	fn set_pos(skeleton: &mut Skeleton, id: TrackerId, pos: Point) {
+		skeleton
+			.update_input_tracker(id, Some(Global(pos)), None)
+			.unwrap();
+	}
+
+	fn node_pos(skeleton: &Skeleton, bone: BoneKind) -> (Point, Point) {
+		let (head, tail) = skeleton
+			.graph
+			.edge_endpoints(skeleton.bone_map[bone])
+			.unwrap();
+		(
+			skeleton.graph[head].output_pos_g.0,
+			skeleton.graph[tail].output_pos_g.0,
+		)
+	}
+
+	#[test]
+	fn test_fk_mode_unaffected() {
+		let (mut skeleton, left, _right) = make_skeleton();
+		set_pos(&mut skeleton, left, Point::new(0., 0.2, 0.));
+		assert_eq!(skeleton.solver_mode(), SolverMode::Fk);
+		let report = skeleton.solve().unwrap();
+		assert_eq!(report.ik, None);
+	}
+
+	/// The user crouches, so the headset is lower than the legs can reach when they
+	/// are straight. IK has to bend the knees to satisfy both.
+	#[test]
+	fn test_crouch() {
+		let (mut skeleton, left, right) = make_skeleton();
+		set_pos(&mut skeleton, left, Point::new(-0.1, 0.2, -0.1));
+		set_pos(&mut skeleton, right, Point::new(0.1, 0.2, -0.1));
+
+		// With only FK, a bone between the headset and the ankles gets stretched.
+		skeleton.solve().unwrap();
+		let length_error = |skeleton: &Skeleton, bone| {
+			let (head, tail) = node_pos(skeleton, bone);
+			((tail - head).norm() - skeleton[bone].length).abs()
+		};
+		assert!(BoneKind::iter().any(|b| length_error(&skeleton, b) > 0.05));
+
+		skeleton.set_solver_mode(SolverMode::FkIk(IkSettings::default()));
+		let report = skeleton.solve().unwrap().ik.unwrap();
+		assert!(report.iterations > 0);
+		assert_eq!(report.residuals.len(), 2);
+		assert!(report.max_residual() < 0.01, "{report:?}");
+
+		// Bone lengths are all preserved.
+		for bone in BoneKind::iter() {
+			assert!(length_error(&skeleton, bone) < 1e-5, "{bone:?}");
+		}
+		let (head, _) = node_pos(&skeleton, BoneKind::Neck);
+		assert_relative_eq!(head, Point::new(0., 1.5, 0.));
+	}
+
+	/// The ankles are further away than the legs can reach. IK gets as close as
+	/// possible, and reports how far off it is.
+	#[test]
+	fn test_unreachable() {
+		let (mut skeleton, left, right) = make_skeleton();
+		set_pos(&mut skeleton, left, Point::new(0., -0.5, 0.));
+		set_pos(&mut skeleton, right, Point::new(0., -0.5, 0.));
+		skeleton.set_solver_mode(SolverMode::FkIk(IkSettings::default()));
+		let report = skeleton.solve().unwrap().ik.unwrap();
+
+		assert_eq!(report.iterations, IkSettings::default().max_iterations);
+		assert_relative_eq!(report.max_residual(), 0.5, epsilon = 1e-3);
+		for (id, _residual) in report.residuals {
+			assert!(id == left || id == right);
+		}
+	}
+
+	/// Joint limits keep bones close to what their trackers say.
+	#[test]
+	fn test_joint_limits() {
+		let (mut skeleton, left, right) = make_skeleton();
+		set_pos(&mut skeleton, left, Point::new(-0.1, 0.2, -0.1));
+		set_pos(&mut skeleton, right, Point::new(0.1, 0.2, -0.1));
+		let settings = IkSettings {
+			joint_limits: BoneMap::new([0.1; BoneKind::NUM_TYPES]),
+			..Default::default()
+		};
+		skeleton.set_solver_mode(SolverMode::FkIk(settings));
+		let report = skeleton.solve().unwrap().ik.unwrap();
+
+		// The legs can't bend enough to reach.
+		assert!(report.max_residual() > 0.01);
+		for bone in BoneKind::iter() {
+			let rot = skeleton[bone].output_rot_g.0;
+			let angle = rot.angle_to(&bone.calibration_rotation().0);
+			assert!(angle <= 0.1 + 1e-4, "{bone:?} bent by {angle}");
+		}
+	}
+}
@@ -0,0 +1,402 @@
+//! Contains the optional leg heuristics, which run after the rest of the solver.
+//!
+//! IMU trackers drift, and the legs are at the far end of the skeleton from the
+//! headset, so small errors add up there. Without any correction, this shows as feet
+//! sinking through the floor and sliding around while they should be planted. There
+//! are three heuristics, which can each be turned on separately in [`LegSettings`]:
+//! * Knee estimation: When only one of the thigh and the shin has a tracker, the
+//!   other bone is in its calibration pose, so the leg bends unnaturally. Instead, its
+//!   rotation is interpolated from the bones around it.
+//! * Skating correction: A foot that is close to the floor and barely moving is
+//!   considered planted. While it is planted, it gets locked in place, by bending the
+//!   knee with two-bone inverse-kinematics.
+//! * Floor clip: The ankle is kept above the floor with the same two-bone IK, and the
+//!   foot and toes get pitched up so that their tails stay above the floor too.
+//!
+//! A leg with a positional tracker on it is left alone, because its position is
+//! already known. All heuristics keep the lengths of the bones.
+
+use crate::conventions::forward_vec;
+use crate::newtypes::Global;
+use crate::skeleton::EdgeKind;
+use crate::{BoneKind, Point, Skeleton, UnitQuat};
+
+use nalgebra::Vector3;
+
+/// The bones of each leg, from the hip down.
+const LEGS: [[BoneKind; 4]; 2] = {
+	use BoneKind::*;
+	[
+		[ThighL, AnkleL, FootL, ToesL],
+		[ThighR, AnkleR, FootR, ToesR],
+	]
+};
+
+/// Settings for the leg heuristics. See [`Skeleton::set_leg_settings()`].
+///
+/// By default, all heuristics are turned off.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LegSettings {
+	/// Turns on the floor clip.
+	pub floor_clip: bool,
+	/// Turns on the skating correction.
+	pub skating_correction: bool,
+	/// Turns on the knee estimation.
+	pub knee_estimation: bool,
+	/// The height of the floor.
+	pub floor_height: f32,
+	/// The time between calls to [`Skeleton::solve()`], in seconds. This is used to
+	/// compute the speed of the feet.
+	pub frame_time: f32,
+	/// A foot that is slower than this, in meters per second, may be planted.
+	pub contact_speed: f32,
+	/// A foot whose lowest point is closer to the floor than this, in meters, may be
+	/// planted.
+	pub contact_height: f32,
+	/// Where between the bone above and the bone below the rotation of an untracked
+	/// thigh or shin gets interpolated, from 0 to 1.
+	pub knee_bend: f32,
+}
+impl LegSettings {
+	/// Whether any of the heuristics are turned on.
+	pub fn is_enabled(&self) -> bool {
+		self.floor_clip || self.skating_correction || self.knee_estimation
+	}
+}
+impl Default for LegSettings {
+	fn default() -> Self {
+		Self {
+			floor_clip: false,
+			skating_correction: false,
+			knee_estimation: false,
+			floor_height: 0.,
+			frame_time: 1. / 60.,
+			contact_speed: 0.2,
+			contact_height: 0.05,
+			knee_bend: 0.5,
+		}
+	}
+}
+
+/// Describes what the leg heuristics did during a call to [`Skeleton::solve()`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LegReport {
+	/// Whether each foot, left then right, is considered planted.
+	pub planted: [bool; 2],
+}
+
+/// The state that the leg heuristics keep between calls to [`Skeleton::solve()`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LegState {
+	/// The position of each ankle during the last solve, before any correction.
+	prev_ankles: [Option<Point>; 2],
+	/// Where each planted foot has its ankle locked.
+	locked_ankles: [Option<Point>; 2],
+}
+
+impl Skeleton {
+	/// Sets up the leg heuristics. This resets which feet are planted.
+	pub fn set_leg_settings(&mut self, settings: LegSettings) {
+		self.leg_settings = settings;
+		self.leg_state = LegState::default();
+	}
+
+	/// Gets the settings of the leg heuristics.
+	pub fn leg_settings(&self) -> LegSettings {
+		self.leg_settings
+	}
+
+	/// Runs the leg heuristics on top of an already solved skeleton.
+	pub(crate) fn solve_legs(&mut self) -> LegReport {
+		let settings = self.leg_settings;
+		let mut report = LegReport::default();
+		for (side, leg) in LEGS.iter().enumerate() {
+			if self.has_positional_tracker(leg) {
+				self.leg_state.prev_ankles[side] = None;
+				self.leg_state.locked_ankles[side] = None;
+				continue;
+			}
+			let [thigh, ankle, foot, toes] = *leg;
+			if settings.knee_estimation {
+				self.estimate_knee(leg, settings.knee_bend);
+			}
+
+			let ankle_pos = self.tail(ankle);
+			let mut target = ankle_pos;
+			if settings.skating_correction {
+				let prev = self.leg_state.prev_ankles[side].replace(ankle_pos);
+				let speed = prev.map(|p| (ankle_pos - p).norm() / settings.frame_time);
+				let lowest = [ankle, foot, toes]
+					.map(|b| self.tail(b).y)
+					.into_iter()
+					.fold(f32::INFINITY, f32::min);
+				let planted = speed.map_or(false, |s| s < settings.contact_speed)
+					&& lowest - settings.floor_height < settings.contact_height;
+				let locked = &mut self.leg_state.locked_ankles[side];
+				*locked = if planted {
+					Some(locked.unwrap_or(ankle_pos))
+				} else {
+					None
+				};
+				report.planted[side] = planted;
+				target = locked.unwrap_or(target);
+			}
+			if settings.floor_clip {
+				target.y = target.y.max(settings.floor_height);
+			}
+			if target != ankle_pos {
+				self.reach_with_leg(thigh, ankle, target);
+			}
+			if settings.floor_clip {
+				for bone in [foot, toes] {
+					self.lift_tail(bone, settings.floor_height);
+				}
+			}
+		}
+		report
+	}
+
+	/// Whether any of the bones in `leg` has a tracker with an input position.
+	fn has_positional_tracker(&self, leg: &[BoneKind]) -> bool {
+		self.graph.edge_indices().any(|e| {
+			let EdgeKind::InputTracker(bone) = self.graph[e].kind else {
+				return false;
+			};
+			let (_bone_head, node) = self.graph.edge_endpoints(e).unwrap();
+			leg.contains(&bone) && self.graph[node].input_pos_g.is_some()
+		})
+	}
+
+	/// Interpolates the rotation of an untracked thigh or shin, when the other one is
+	/// tracked. When both or neither are tracked, nothing changes.
+	fn estimate_knee(&mut self, leg: &[BoneKind; 4], bend: f32) {
+		let [thigh, ankle, foot, _toes] = *leg;
+		let tracked = |b: BoneKind| self[b].input_rot_g.is_some();
+		// The rotations of the bones away from their calibration pose.
+		let from_rest =
+			|b: BoneKind| self[b].output_rot_g.0 * b.calibration_rotation().0.inverse();
+		let (bone, from_rest) = match (tracked(thigh), tracked(ankle)) {
+			(false, true) => {
+				let hip = thigh.parent().unwrap();
+				(thigh, from_rest(hip).slerp(&from_rest(ankle), bend))
+			}
+			(true, false) if tracked(foot) => {
+				(ankle, from_rest(thigh).slerp(&from_rest(foot), bend))
+			}
+			// Without a foot tracker, the leg is kept straight.
+			(true, false) => (ankle, from_rest(thigh)),
+			_ => return,
+		};
+		let edge = self.bone_map[bone];
+		self.graph[edge].output_rot_g =
+			Global(from_rest * bone.calibration_rotation().0);
+		self.refresh_below(thigh);
+	}
+
+	/// Bends the thigh and the shin so that the tail of the shin reaches `target`, or
+	/// gets as close as it can. The knee keeps bending the way it already did.
+	fn reach_with_leg(&mut self, thigh: BoneKind, ankle: BoneKind, target: Point) {
+		let (l1, l2) = (self[thigh].length, self[ankle].length);
+		if l1 <= 0. || l2 <= 0. {
+			return;
+		}
+		let hip = self.head(thigh);
+		let knee = self.tail(thigh);
+		let foot = self.tail(ankle);
+
+		let to_target = target - hip;
+		let Some(dir) = to_target.try_normalize(f32::EPSILON) else {
+			return;
+		};
+		let d = to_target.norm().clamp((l1 - l2).abs() + 1e-4, l1 + l2);
+		// The distance along `dir` to the knee, and away from `dir` to the knee.
+		let along = (l1 * l1 - l2 * l2 + d * d) / (2. * d);
+		let away = (l1 * l1 - along * along).max(0.).sqrt();
+		let bend = [knee - hip, forward_vec().into_inner()]
+			.into_iter()
+			.find_map(|v| (v - dir * v.dot(&dir)).try_normalize(1e-4))
+			.unwrap_or_else(|| dir.cross(&Vector3::x()).normalize());
+		let new_knee = hip + dir * along + bend * away;
+		let new_foot = hip + dir * d;
+
+		self.rotate_bone(thigh, &(knee - hip), &(new_knee - hip));
+		self.rotate_bone(ankle, &(foot - knee), &(new_foot - new_knee));
+		self.refresh_below(thigh);
+	}
+
+	/// Pitches `bone` up, so that its tail is not below `floor`.
+	fn lift_tail(&mut self, bone: BoneKind, floor: f32) {
+		let head = self.head(bone);
+		let dir = self.tail(bone) - head;
+		let len = dir.norm();
+		if head.y + dir.y >= floor || len <= 0. {
+			return;
+		}
+		let dy = (floor - head.y).min(len);
+		let horizontal = Vector3::new(dir.x, 0., dir.z);
+		let horizontal = horizontal
+			.try_normalize(f32::EPSILON)
+			.unwrap_or_else(|| forward_vec().into_inner());
+		let new_dir =
+			horizontal * (len * len - dy * dy).max(0.).sqrt() + Vector3::y() * dy;
+		self.rotate_bone(bone, &dir, &new_dir);
+		self.refresh_below(bone);
+	}
+
+	/// Rotates the output of `bone` in global space, by the rotation from `from` to
+	/// `to`.
+	fn rotate_bone(&mut self, bone: BoneKind, from: &Vector3<f32>, to: &Vector3<f32>) {
+		if let Some(delta) = UnitQuat::rotation_between(from, to) {
+			let edge = &mut self.graph[self.bone_map[bone]];
+			edge.output_rot_g = Global(delta * edge.output_rot_g.0);
+		}
+	}
+
+	fn head(&self, bone: BoneKind) -> Point {
+		let (head, _tail) = self.graph.edge_endpoints(self.bone_map[bone]).unwrap();
+		self.graph[head].output_pos_g.0
+	}
+
+	fn tail(&self, bone: BoneKind) -> Point {
+		let (_head, tail) = self.graph.edge_endpoints(self.bone_map[bone]).unwrap();
+		self.graph[tail].output_pos_g.0
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::skeleton::{SkeletonConfig, TrackerId};
+
+	use approx::assert_relative_eq;
+	use std::f32::consts::FRAC_PI_2;
+
+	/// A skeleton of a 1.8m tall user with the headset at `hmd_height`, and 3DoF
+	/// trackers on the thighs.
+	fn make_skeleton(hmd_height: f32) -> (Skeleton, TrackerId, [TrackerId; 2]) {
+		let config = SkeletonConfig::from_height(1.8);
+		let mut skeleton = Skeleton::new(&config);
+		let hmd = skeleton.attach_input_tracker(
+			BoneKind::Head,
+			Some(Global(Point::new(0., hmd_height, 0.))),
+			None,
+		);
+		let thighs = [BoneKind::ThighL, BoneKind::ThighR]
+			.map(|b| skeleton.attach_input_tracker(b, None, None));
+		(skeleton, hmd, thighs)
+	}
+
+	/// The height of the headset when the ankles are on the floor.
+	fn standing_height(skeleton: &Skeleton) -> f32 {
+		use BoneKind::*;
+		[Head, Neck, UpperChest, Chest, Waist, Hip, ThighL, AnkleL]
+			.iter()
+			.map(|b| skeleton[*b].length)
+			.sum()
+	}
+
+	fn thigh_rot(angle: f32) -> Option<Global<UnitQuat>> {
+		Some(Global(UnitQuat::from_axis_angle(&Vector3::x_axis(), angle)))
+	}
+
+	#[test]
+	fn test_disabled() {
+		let (mut skeleton, _hmd, _thighs) = make_skeleton(1.);
+		let report = skeleton.solve().unwrap();
+		assert_eq!(report.legs, None);
+		// The legs go through the floor.
+		assert!(skeleton.tail(BoneKind::AnkleL).y < 0.);
+	}
+
+	#[test]
+	fn test_floor_clip() {
+		let (mut skeleton, _hmd, _thighs) = make_skeleton(1.);
+		skeleton.set_leg_settings(LegSettings {
+			floor_clip: true,
+			..Default::default()
+		});
+		skeleton.solve().unwrap();
+		for leg in LEGS {
+			let [thigh, ankle, foot, toes] = leg;
+			assert_relative_eq!(skeleton.tail(ankle).y, 0., epsilon = 1e-4);
+			// The knee bends forward, and every bone keeps its length.
+			assert!(skeleton.tail(thigh).z < -0.1);
+			for bone in [thigh, ankle, foot, toes] {
+				let len = (skeleton.tail(bone) - skeleton.head(bone)).norm();
+				assert_relative_eq!(len, skeleton[bone].length, epsilon = 1e-4);
+				assert!(skeleton.tail(bone).y > -1e-4, "{bone:?}");
+			}
+			// The bone pose agrees with the node positions.
+			let dir = skeleton.tail(toes) - skeleton.head(toes);
+			let rot = skeleton.bone_pose(toes).0.rotation;
+			assert_relative_eq!(
+				-(rot * Vector3::y()) * skeleton[toes].length,
+				dir,
+				epsilon = 1e-4
+			);
+		}
+	}
+
+	#[test]
+	fn test_skating_correction() {
+		let (mut skeleton, hmd, _thighs) = make_skeleton(0.);
+		let height = standing_height(&skeleton);
+		skeleton.set_leg_settings(LegSettings {
+			skating_correction: true,
+			..Default::default()
+		});
+		let set_hmd = |skeleton: &mut Skeleton, x: f32| {
+			let pos = Some(Global(Point::new(x, height, 0.)));
+			skeleton.update_input_tracker(hmd, pos, None).unwrap();
+			skeleton.solve().unwrap().legs.unwrap()
+		};
+
+		// The first solve has no speed yet, the second one plants the feet.
+		assert_eq!(set_hmd(&mut skeleton, 0.).planted, [false, false]);
+		assert_eq!(set_hmd(&mut skeleton, 0.).planted, [true, true]);
+		let planted = skeleton.tail(BoneKind::AnkleL);
+
+		// Leaning slowly keeps the feet in place.
+		assert_eq!(set_hmd(&mut skeleton, 0.002).planted, [true, true]);
+		assert_relative_eq!(skeleton.tail(BoneKind::AnkleL), planted, epsilon = 1e-4);
+		assert_relative_eq!(skeleton.head(BoneKind::Head).x, 0.002);
+
+		// Moving quickly releases them.
+		assert_eq!(set_hmd(&mut skeleton, 0.2).planted, [false, false]);
+		assert_relative_eq!(skeleton.tail(BoneKind::AnkleL).x, planted.x + 0.2);
+	}
+
+	#[test]
+	fn test_knee_estimation() {
+		let (mut skeleton, _hmd, [left, _right]) = make_skeleton(1.);
+		skeleton.set_leg_settings(LegSettings {
+			knee_estimation: true,
+			..Default::default()
+		});
+		// Sitting: the left thigh points forward.
+		skeleton
+			.update_input_tracker(left, None, thigh_rot(FRAC_PI_2))
+			.unwrap();
+		skeleton.solve().unwrap();
+		// Without a foot tracker, the shin follows the thigh.
+		assert_relative_eq!(
+			skeleton[BoneKind::AnkleL].output_rot_g.0,
+			skeleton[BoneKind::ThighL].output_rot_g.0,
+			epsilon = 1e-5
+		);
+		let knee = skeleton.tail(BoneKind::ThighL);
+		let ankle = skeleton.tail(BoneKind::AnkleL);
+		assert_relative_eq!(knee.y, ankle.y, epsilon = 1e-4);
+
+		// With a shin tracker but no thigh tracker, the thigh is halfway between the
+		// hip and the shin.
+		skeleton.detach_input_tracker(left).unwrap();
+		let shin = skeleton.attach_input_tracker(BoneKind::AnkleL, None, None);
+		skeleton
+			.update_input_tracker(shin, None, thigh_rot(1.))
+			.unwrap();
+		skeleton.solve().unwrap();
+		let thigh = skeleton[BoneKind::ThighL].output_rot_g.0;
+		assert_relative_eq!(thigh.angle(), 0.5, epsilon = 1e-5);
+	}
+}
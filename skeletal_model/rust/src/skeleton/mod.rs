@@ -59,8 +59,9 @@
 //!   latest global rotation. If the latest global rotation is not directly provided via
 //!   an input tracker, this will be solved for.
 //! * Type of edge (either [`BoneKind`], input tracker, or output tracker)
-//! * The length of the edge. This is defined by the user for bones, and computed at
-//!   calibration time for tracker edges.
+//! * The length of the edge. This is defined by the user for bones.
+//! * The offset of a tracker edge from the head of its bone. This is computed at
+//!   calibration time for input trackers, and defined by the user for output trackers.
 //!
 //! #### Node
 //! * The latest global position. If not directly provided via an input tracker, this
@@ -81,7 +82,8 @@
 //! each step of the traversal:
 //! #### Edge
 //! * If there is an input rotation, copy it to the output rotation
-//! * If there is no input rotation, output the calibration rotation
+//! * If there is no input rotation, output the calibration rotation. For tracker
+//!   edges, this is relative to the rotation of the bone they are attached to.
 //!
 //! #### Node
 //! * If there is an input position, copy it to the output position
@@ -97,44 +99,69 @@
 //! used for the nodes. This order is unspecified, but guaranteed not to change until
 //! new input trackers are added/removed.
 
+mod autobone;
 mod calibrate;
+mod config;
+mod constraints;
 mod edge;
+mod ik;
+mod legs;
 mod node;
 mod solver;
+mod tracker;
 
-pub(crate) use edge::Edge;
+pub use autobone::{
+	AutoBoneError, AutoBoneReport, AutoBoneSettings, InputFrame, InputRecording,
+	TrackerInput,
+};
+pub use calibrate::{CalibrationData, ResetKind};
+#[cfg(feature = "serde")]
+pub use config::{ConfigError, ConfigFile};
+pub use config::{SkeletonConfig, TrackerConfig};
+pub use constraints::{ConstraintReport, JointConstraint};
+pub(crate) use edge::{Edge, EdgeKind};
+pub use ik::{IkReport, IkSettings, SolverMode};
+pub use legs::{LegReport, LegSettings};
 pub(crate) use node::Node;
+pub use solver::{SolveError, SolveReport};
+pub use tracker::{TrackerError, TrackerId};
 
 use core::ops::Index;
 use petgraph::graph::{EdgeIndex, NodeIndex};
 
-pub(crate) type Graph = petgraph::graph::UnGraph<Node, Edge>;
+/// A `StableGraph` is used so that detaching trackers doesn't invalidate the indices
+/// of other edges and nodes.
+pub(crate) type Graph = petgraph::stable_graph::StableUnGraph<Node, Edge>;
 
 use crate::bone::{BoneKind, BoneMap};
+use crate::newtypes::Global;
+use crate::recording::PoseFrame;
+use crate::Isometry;
 
-/// Used to initialize the [`Skeleton`] with its initial parameters
-pub struct SkeletonConfig {
-	bone_lengths: BoneMap<f32>,
-}
-impl SkeletonConfig {
-	pub fn new(bone_lengths: BoneMap<f32>) -> Self {
-		SkeletonConfig { bone_lengths }
-	}
-}
+use std::collections::BTreeMap;
 
 /// The `Skeleton` provides a way of reading, writing, and solving for the pose of
 /// a human wearing FBT.
 ///
 /// See the [`crate::skeleton`] module for more information.
+#[derive(Debug, Clone)]
 pub struct Skeleton {
 	bone_map: BoneMap<EdgeIndex>,
 	graph: Graph,
+	solver_mode: SolverMode,
+	joint_constraints: Option<BoneMap<JointConstraint>>,
+	leg_settings: LegSettings,
+	leg_state: legs::LegState,
+	/// The edges of the attached trackers. Ids only ever grow, so that stale ids can't
+	/// refer to newer trackers.
+	trackers: BTreeMap<TrackerId, EdgeIndex>,
+	next_tracker_id: u32,
 }
 impl Skeleton {
-	/// Creates a new `Skeleton` from [`SkeletonConfig`]. Initially, the skeleton will
-	/// not have any input trackers or output trackers.
+	/// Creates a new `Skeleton` from [`SkeletonConfig`]. The trackers in the config
+	/// are attached in order, so their [`TrackerId`]s are ascending in that order.
 	pub fn new(config: &SkeletonConfig) -> Self {
-		let mut g = Graph::new_undirected();
+		let mut g = Graph::default();
 
 		// Option is used for resilience against bugs while the map is being built
 		let mut bone_map: BoneMap<Option<EdgeIndex>> = BoneMap::default();
@@ -143,12 +170,13 @@ impl Skeleton {
 		{
 			let parent = g.add_node(Node::new());
 			let child = g.add_node(Node::new());
+			let root = BoneKind::root();
 			let edge = g.update_edge(
 				parent,
 				child,
-				Edge::new(BoneKind::Neck, config.bone_lengths[BoneKind::Neck]),
+				Edge::new(root, config.bone_lengths[root]),
 			);
-			bone_map[BoneKind::Neck] = Some(edge);
+			bone_map[root] = Some(edge);
 		}
 
 		// This closure adds all the immediate children of `parent_bone` to the graph
@@ -159,10 +187,12 @@ impl Skeleton {
 
 			// The old child (old target) becomes the new parent (new source).
 			let new_parent = child_node;
-			let new_child = g.add_node(Node::new());
 
 			for &child_kind in parent_bone.children() {
-				let edge = g.update_edge(
+				// Each child bone gets its own tail node, otherwise siblings would all
+				// share the same edge.
+				let new_child = g.add_node(Node::new());
+				let edge = g.add_edge(
 					new_parent,
 					new_child,
 					Edge::new(child_kind, config.bone_lengths[child_kind]),
@@ -173,9 +203,8 @@ impl Skeleton {
 		};
 
 		// Call `add_child_bones` in a depth-first traversal to build the actual graph.
-		let mut bone_stack = vec![BoneKind::Neck];
-		while !bone_stack.is_empty() {
-			let parent_bone = bone_stack.pop().unwrap();
+		let mut bone_stack = vec![BoneKind::root()];
+		while let Some(parent_bone) = bone_stack.pop() {
 			add_child_bones(parent_bone);
 			bone_stack.extend(parent_bone.children());
 		}
@@ -183,21 +212,96 @@ impl Skeleton {
 		// Map is populated, get rid of the `Optional`
 		let bone_map: BoneMap<EdgeIndex> = bone_map.map(|_kind, bone| bone.unwrap());
 
-		Self { graph: g, bone_map }
+		let mut skeleton = Self {
+			graph: g,
+			bone_map,
+			solver_mode: SolverMode::default(),
+			joint_constraints: None,
+			leg_settings: LegSettings::default(),
+			leg_state: Default::default(),
+			trackers: BTreeMap::new(),
+			next_tracker_id: 0,
+		};
+		skeleton.attach_configured_trackers(config);
+		skeleton
+	}
+
+	/// Gets the pose of a bone, as of the last [`Skeleton::solve()`]. The position is
+	/// that of the head of the bone.
+	pub fn bone_pose(&self, bone: BoneKind) -> Global<Isometry> {
+		let edge = self.bone_map[bone];
+		let (head, _tail) = self.graph.edge_endpoints(edge).unwrap();
+		let pos = self.graph[head].output_pos_g.0;
+		let rot = self.graph[edge].output_rot_g.0;
+		Global(Isometry::from_parts(pos.coords.into(), rot))
 	}
 
-	// pub fn attach_input_tracker(
-	// 	&mut self,
-	// 	attach_to: BoneKind,
-	// 	pos: Option<Global<Point>>,
-	// 	rotation: Option<
-	// ) {
-	//
-	// 	// asdf
-	// }
+	/// Poses the skeleton as in `frame`, without solving. The trackers follow the
+	/// bones they are attached to. This is useful to read the output trackers of a
+	/// recorded pose, possibly from a skeleton with different bone lengths.
+	pub fn set_pose(&mut self, frame: &PoseFrame) {
+		for bone in BoneKind::iter() {
+			self.graph[self.bone_map[bone]].output_rot_g = frame.bone_rots[bone];
+		}
+		let edges: Vec<_> = self.graph.edge_indices().collect();
+		for edge in edges {
+			if let EdgeKind::InputTracker(bone) | EdgeKind::OutputTracker(bone) =
+				self.graph[edge].kind
+			{
+				let bone_rot = self.graph[self.bone_map[bone]].output_rot_g.0;
+				let e = &mut self.graph[edge];
+				e.output_rot_g = Global(bone_rot * e.calib_rot_l.0);
+			}
+		}
+		let root = BoneKind::root();
+		let (head, _tail) = self.graph.edge_endpoints(self.bone_map[root]).unwrap();
+		self.graph[head].output_pos_g = frame.root_pos;
+		self.refresh_below(root);
+	}
+
+	/// Changes the length of every bone. The trackers stay attached to the head of their
+	/// bone, but the skeleton may need to be calibrated again, because the offsets of
+	/// 6DoF trackers were computed using the old lengths.
+	pub fn set_bone_lengths(&mut self, bone_lengths: &BoneMap<f32>) {
+		for (bone, length) in bone_lengths.iter() {
+			self.graph[self.bone_map[bone]].length = *length;
+		}
+	}
 
 	// ---- Private fns ----
 
+	/// Recomputes the positions of `bone` and all bones below it, along with the
+	/// trackers attached to them, based on their rotations and the head of `bone`.
+	fn refresh_below(&mut self, bone: BoneKind) {
+		let mut stack = vec![bone];
+		while let Some(bone) = stack.pop() {
+			let bone_edge = self.bone_map[bone];
+			let (head, tail) = self.graph.edge_endpoints(bone_edge).unwrap();
+			let head_pos = self.graph[head].output_pos_g.0;
+			let bone_rot = self.graph[bone_edge].output_rot_g.0;
+
+			let mut edges = self.graph.neighbors(head).detach();
+			while let Some((edge, node)) = edges.next(&self.graph) {
+				let e = &mut self.graph[edge];
+				match e.kind {
+					EdgeKind::InputTracker(b) | EdgeKind::OutputTracker(b)
+						if b == bone =>
+					{
+						if e.input_rot_g.is_none() {
+							e.output_rot_g = Global(bone_rot * e.calib_rot_l.0);
+						}
+						let pos = head_pos + e.head_to_tail_g();
+						self.graph[node].output_pos_g = Global(pos);
+					}
+					_ => (),
+				}
+			}
+			let pos = head_pos + self.graph[bone_edge].head_to_tail_g();
+			self.graph[tail].output_pos_g = Global(pos);
+			stack.extend(bone.children());
+		}
+	}
+
 	/// Get the nodes of the graph that have a `Some(_)` [`Node::input_pos_g`]
 	fn find_root_nodes(&self) -> impl Iterator<Item = NodeIndex> + '_ {
 		self.graph
@@ -233,4 +337,21 @@ mod test {
 			assert_eq!(&skeleton[bone].length, length);
 		}
 	}
+
+	/// Tests that bone poses are positioned at the head of the bone
+	#[test]
+	fn test_bone_pose() {
+		let bone_lengths = BoneMap::new([0.5; BoneKind::num_types()]);
+		let mut skeleton = Skeleton::new(&SkeletonConfig::new(bone_lengths));
+		let pos = crate::Point::new(0., 2., 0.);
+		skeleton.attach_input_tracker(BoneKind::Neck, Some(Global(pos)), None);
+		skeleton.solve().unwrap();
+
+		let neck = skeleton.bone_pose(BoneKind::Neck).0;
+		assert_eq!(neck.translation.vector, pos.coords);
+		// Neck and upper chest are between the head of the neck and the chest.
+		let chest = skeleton.bone_pose(BoneKind::Chest).0;
+		assert_eq!(chest.translation.vector, nalgebra::Vector3::new(0., 1., 0.));
+		assert_eq!(chest.rotation, skeleton[BoneKind::Chest].output_rot_g.0);
+	}
 }
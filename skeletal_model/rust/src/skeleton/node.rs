@@ -6,7 +6,7 @@ use crate::{newtypes::Global, Point};
 /// For more information, see the [`skeleton`](crate::skeleton) module.
 ///
 /// [`Edge`]: crate::skeleton::Edge
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 #[non_exhaustive]
 pub struct Node {
 	/// Input position in global space. If it is unconstrained, it is `None`.
@@ -11,20 +11,51 @@
 #[cfg(doc)]
 use crate::skeleton::Edge;
 
-use crate::skeleton::Graph;
-use crate::Skeleton;
+use crate::newtypes::Global;
+use crate::skeleton::{
+	ConstraintReport, EdgeKind, Graph, IkReport, LegReport, SolverMode,
+};
+use crate::{BoneKind, Skeleton, UnitQuat};
 
 use derive_more::From;
 use petgraph::graph::{EdgeIndex, NodeIndex};
-use petgraph::visit::{VisitMap, Visitable};
+use petgraph::visit::{EdgeRef, VisitMap, Visitable};
 use std::collections::{HashSet, VecDeque};
 
 impl Skeleton {
 	/// Solves for the outputs of the skeletal model.
 	///
-	/// For more info on the algorithm, see [`crate::skeleton`].
-	pub fn solve(&mut self) -> Result<(), SolveError> {
-		self.traverse(do_fk)
+	/// For more info on the algorithm, see [`crate::skeleton`]. The stages after that
+	/// run in this order, when they are turned on:
+	/// 1. The [`JointConstraint`](crate::skeleton::JointConstraint)s.
+	/// 2. Inverse-kinematics, depending on the [`SolverMode`].
+	/// 3. The leg heuristics in [`LegSettings`](crate::skeleton::LegSettings).
+	pub fn solve(&mut self) -> Result<SolveReport, SolveError> {
+		self.apply_input_trackers();
+
+		// Root nodes are already solved before the traversal even begins.
+		let root_nodes: Vec<_> = self.find_root_nodes().collect();
+		for n in root_nodes {
+			let node = &mut self.graph[n];
+			node.output_pos_g = node.input_pos_g.unwrap();
+		}
+		self.traverse(do_fk)?;
+
+		let mut report = SolveReport::default();
+		if let Some(constraints) = self.joint_constraints {
+			let constraints = self.apply_constraints(&constraints);
+			if !constraints.active.is_empty() {
+				self.traverse(do_positions)?;
+			}
+			report.constraints = Some(constraints);
+		}
+		if let SolverMode::FkIk(settings) = self.solver_mode {
+			report.ik = Some(self.solve_ik(&settings));
+		}
+		if self.leg_settings.is_enabled() {
+			report.legs = Some(self.solve_legs());
+		}
+		Ok(report)
 	}
 
 	/// Traverses all edges and nodes in the graph in a breadth-first search. Calls `f` at the
@@ -36,15 +67,21 @@ impl Skeleton {
 		// We want to "expand outward" from root nodes with constrained position.
 		// This means we perform a breadth-first traversal with these root nodes in the
 		// initial set.
+		//
+		// Root nodes are always in ascending index order, which is what gives us the
+		// canonical order for equidistant nodes.
 		let root_nodes: VecDeque<_> = self.find_root_nodes().collect();
 		if root_nodes.is_empty() {
 			return Err(SolveError::NoRootNode);
 		}
 
-		let mut bfs = petgraph::visit::Bfs {
-			stack: root_nodes,
-			discovered: self.graph.visit_map(),
-		};
+		// Tracks which nodes have been solved. Unlike `petgraph::visit::Bfs`, nodes are
+		// only marked once they are actually solved, rather than when they are queued.
+		let mut solved_nodes = self.graph.visit_map();
+		for n in root_nodes.iter() {
+			solved_nodes.visit(*n);
+		}
+		let mut queue = root_nodes;
 		// We solve edges too, so we need to track if we visited them to avoid
 		// double-solving.
 		let mut visited_edges = HashSet::with_capacity(self.graph.edge_count());
@@ -55,13 +92,13 @@ impl Skeleton {
 		// neighboring edges and nodes may not yet be. The job of this nested while loop
 		// is to munch on the neighbors of the popped node, solving them one by one, and
 		// then queue the solved neighbor nodes to repeat the process.
-		while let Some(popped) = bfs.next(&self.graph) {
+		while let Some(popped) = queue.pop_front() {
 			let mut neighbors = self.graph.neighbors(popped).detach();
 			while let Some((edge, node)) = neighbors.next(&self.graph) {
 				if visited_edges.contains(&edge) {
 					continue; // If the edge was visited, the node definitely was too.
 				}
-				let is_solved = bfs.discovered.is_visited(&node);
+				let is_solved = solved_nodes.is_visited(&node);
 				f(
 					&mut self.graph,
 					popped.into(),
@@ -72,6 +109,9 @@ impl Skeleton {
 				);
 
 				visited_edges.insert(edge);
+				if solved_nodes.visit(node) {
+					queue.push_back(node);
+				}
 			}
 		}
 		Ok(())
@@ -110,24 +150,244 @@ struct PoppedNode(pub NodeIndex);
 /// mutates the weights of the graph.
 ///
 /// For more info, see [`crate::skeleton`].
-fn do_fk(
-	_g: &mut Graph,
-	PoppedNode(_popped): PoppedNode,
+fn do_fk(g: &mut Graph, popped: PoppedNode, neighbors: Neighbors) {
+	// The edge always gets solved, regardless of whether `node` was already solved.
+	g[neighbors.edge].output_rot_g = solve_rot(g, neighbors.edge);
+	do_positions(g, popped, neighbors);
+}
+
+/// Like [`do_fk`], but keeps the current rotations of the edges, and only solves the
+/// positions of the nodes.
+fn do_positions(
+	g: &mut Graph,
+	PoppedNode(popped): PoppedNode,
 	Neighbors { edge, node }: Neighbors,
 ) {
-	let _edge = edge; // unused
 	match node {
 		MaybeSolvedNode::Solved(_node) => {
-			todo!("popped -> edge <- node")
+			// popped -> edge <- node
+			// Both nodes were already solved, so only the edge needed solving. Whichever
+			// node got to `node` first "wins", which is why there is a canonical order.
+		}
+		MaybeSolvedNode::Unsolved(node) => {
+			// popped -> edge + node
+			let pos = if let Some(pos) = g[node].input_pos_g {
+				pos.0
+			} else {
+				// `edge_endpoints()` gives the head first, which is the end nearer the
+				// root. We walk the edge backwards if we came from its tail.
+				let head_to_tail = g[edge].head_to_tail_g();
+				let (head, _tail) = g.edge_endpoints(edge).unwrap();
+				let popped_pos = g[popped].output_pos_g.0;
+				if popped == head {
+					popped_pos + head_to_tail
+				} else {
+					popped_pos - head_to_tail
+				}
+			};
+			g[node].output_pos_g = Global(pos);
 		}
-		MaybeSolvedNode::Unsolved(_node) => {
-			todo!("popped -> edge + node")
+	}
+}
+
+/// Computes the output rotation of `edge`.
+///
+/// Unlike positions, rotations don't depend on the order of the traversal, because they
+/// only come from the inputs or the calibration. Trackers follow the rotation of their
+/// bone, unless they have an input rotation.
+fn solve_rot(g: &Graph, edge: EdgeIndex) -> Global<UnitQuat> {
+	let e = &g[edge];
+	if let Some(rot) = e.input_rot_g {
+		return rot;
+	}
+	match e.kind {
+		EdgeKind::Bone(_) => e.calibration_rot_g(),
+		EdgeKind::InputTracker(bone) | EdgeKind::OutputTracker(bone) => {
+			let bone_rot_g = solve_rot(g, find_bone(g, edge, bone));
+			Global(bone_rot_g.0 * e.calib_rot_l.0)
 		}
 	}
 }
 
+/// Finds the edge of `bone`, given a tracker `edge` that is attached to it.
+fn find_bone(g: &Graph, edge: EdgeIndex, bone: BoneKind) -> EdgeIndex {
+	let (bone_head, _tracker) = g.edge_endpoints(edge).unwrap();
+	g.edges(bone_head)
+		.find(|e| e.weight().kind == EdgeKind::Bone(bone))
+		.map(|e| e.id())
+		.expect("Trackers are always attached to the head of their bone")
+}
+
+/// Information about a call to [`Skeleton::solve()`].
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct SolveReport {
+	/// Which joint constraints were active, if they are turned on.
+	pub constraints: Option<ConstraintReport>,
+	/// The results of inverse-kinematics, if it ran.
+	pub ik: Option<IkReport>,
+	/// The results of the leg heuristics, if any of them are turned on.
+	pub legs: Option<LegReport>,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum SolveError {
 	#[error("Need at least one \"root\" `Node` (root nodes have a `input_rot_g`)")]
 	NoRootNode,
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::bone::{BoneKind, BoneMap};
+	use crate::skeleton::SkeletonConfig;
+	use crate::{Point, UnitQuat};
+
+	use approx::{assert_relative_eq, relative_eq};
+	use nalgebra::Vector3;
+	use std::f32::consts::FRAC_PI_2;
+
+	const HEAD_POS: [f32; 3] = [0., 1.7, 0.];
+
+	/// Builds a skeleton with reasonable bone lengths, whose head is constrained to
+	/// [`HEAD_POS`].
+	fn make_skeleton() -> Skeleton {
+		use BoneKind::*;
+		let bone_lengths =
+			BoneMap::new([0.; BoneKind::NUM_TYPES]).map(|kind, _| match kind {
+				Neck => 0.1,
+				Chest => 0.2,
+				Waist => 0.2,
+				Hip => 0.1,
+				ThighL | ThighR => 0.5,
+				AnkleL | AnkleR => 0.4,
+				FootL | FootR => 0.2,
+				UpperArmL | UpperArmR => 0.3,
+				ForearmL | ForearmR => 0.25,
+				HandL | HandR => 0.1,
+				// The remaining bones are collapsed, so they don't affect the pose.
+				_ => 0.,
+			});
+		let mut skeleton = Skeleton::new(&SkeletonConfig::new(bone_lengths));
+		skeleton.attach_input_tracker(Neck, Some(Global(Point::from(HEAD_POS))), None);
+		skeleton
+	}
+
+	/// Attaches a 3DoF tracker with rotation `rot` to `bone`.
+	fn set_rot(skeleton: &mut Skeleton, bone: BoneKind, rot: UnitQuat) {
+		skeleton.attach_input_tracker(bone, None, Some(Global(rot)));
+	}
+
+	/// Gets the solved position of the tail of `bone`.
+	fn tail_pos(skeleton: &Skeleton, bone: BoneKind) -> Point {
+		let (_, tail) = skeleton
+			.graph
+			.edge_endpoints(skeleton.bone_map[bone])
+			.unwrap();
+		skeleton.graph[tail].output_pos_g.0
+	}
+
+	#[test]
+	fn test_no_root_node() {
+		let mut skeleton = make_skeleton();
+		let (hmd, _) = skeleton.input_trackers().next().unwrap();
+		skeleton.detach_input_tracker(hmd).unwrap();
+		assert!(matches!(skeleton.solve(), Err(SolveError::NoRootNode)));
+	}
+
+	#[test]
+	fn test_t_pose() {
+		use BoneKind::*;
+		let mut skeleton = make_skeleton();
+
+		// Arms stick straight out to the sides, so the tail of each arm bone is further
+		// from the body than its head.
+		let left = UnitQuat::from_axis_angle(&Vector3::z_axis(), -FRAC_PI_2);
+		let right = UnitQuat::from_axis_angle(&Vector3::z_axis(), FRAC_PI_2);
+		for b in [UpperArmL, ForearmL, HandL] {
+			set_rot(&mut skeleton, b, left);
+		}
+		for b in [UpperArmR, ForearmR, HandR] {
+			set_rot(&mut skeleton, b, right);
+		}
+		skeleton.solve().unwrap();
+
+		let expected = [
+			(Neck, [0., 1.6, 0.]),
+			(Chest, [0., 1.4, 0.]),
+			(Waist, [0., 1.2, 0.]),
+			(Hip, [0., 1.1, 0.]),
+			(ThighL, [0., 0.6, 0.]),
+			(ThighR, [0., 0.6, 0.]),
+			(AnkleL, [0., 0.2, 0.]),
+			(AnkleR, [0., 0.2, 0.]),
+			(FootL, [0., 0.2, -0.2]),
+			(FootR, [0., 0.2, -0.2]),
+			(UpperArmL, [-0.3, 1.6, 0.]),
+			(UpperArmR, [0.3, 1.6, 0.]),
+			(ForearmL, [-0.55, 1.6, 0.]),
+			(ForearmR, [0.55, 1.6, 0.]),
+			(HandL, [-0.65, 1.6, 0.]),
+			(HandR, [0.65, 1.6, 0.]),
+		];
+		for (bone, pos) in expected {
+			let actual = tail_pos(&skeleton, bone);
+			assert!(
+				relative_eq!(actual, Point::from(pos), epsilon = 1e-6),
+				"{bone:?} ends at {actual}, expected {pos:?}"
+			);
+		}
+
+		// Bones without inputs output their calibration rotation.
+		assert_relative_eq!(skeleton[Chest].output_rot_g.0, UnitQuat::identity());
+		assert_relative_eq!(skeleton[ForearmL].output_rot_g.0, left);
+	}
+
+	#[test]
+	fn test_seated_pose() {
+		use BoneKind::*;
+		let mut skeleton = make_skeleton();
+
+		// Thighs point forward, shins hang straight down, and the forearms rest
+		// forward on the lap.
+		let forward = UnitQuat::from_axis_angle(&Vector3::x_axis(), FRAC_PI_2);
+		for b in [ThighL, ThighR, ForearmL, ForearmR, HandL, HandR] {
+			set_rot(&mut skeleton, b, forward);
+		}
+		skeleton.solve().unwrap();
+
+		let expected = [
+			(Hip, [0., 1.1, 0.]),
+			(ThighL, [0., 1.1, -0.5]),
+			(ThighR, [0., 1.1, -0.5]),
+			(AnkleL, [0., 0.7, -0.5]),
+			(AnkleR, [0., 0.7, -0.5]),
+			(FootL, [0., 0.7, -0.7]),
+			(FootR, [0., 0.7, -0.7]),
+			(UpperArmL, [0., 1.3, 0.]),
+			(ForearmL, [0., 1.3, -0.25]),
+			(HandR, [0., 1.3, -0.35]),
+		];
+		for (bone, pos) in expected {
+			let actual = tail_pos(&skeleton, bone);
+			assert!(
+				relative_eq!(actual, Point::from(pos), epsilon = 1e-6),
+				"{bone:?} ends at {actual}, expected {pos:?}"
+			);
+		}
+	}
+
+	/// Nodes with an input position keep that position, even if it disagrees with the
+	/// bone lengths.
+	#[test]
+	fn test_multiple_roots() {
+		use BoneKind::*;
+		let mut skeleton = make_skeleton();
+		let foot_pos = Point::new(0.1, 0.0, -0.3);
+		skeleton.attach_input_tracker(FootL, Some(Global(foot_pos)), None);
+		skeleton.solve().unwrap();
+
+		assert_relative_eq!(tail_pos(&skeleton, AnkleL), foot_pos);
+		assert_relative_eq!(tail_pos(&skeleton, Neck), Point::new(0., 1.6, 0.));
+	}
+}
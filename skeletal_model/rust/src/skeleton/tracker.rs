@@ -0,0 +1,434 @@
+//! Contains the API for attaching trackers to the [`Skeleton`].
+//!
+//! Trackers are represented in the skeleton as an extra edge that connects a new node
+//! (the tracker) to the head of the bone that it is attached to. See the
+//! [`skeleton`](crate::skeleton) module for more info.
+
+use crate::newtypes::{Global, Local};
+use crate::skeleton::{Edge, EdgeKind, Node};
+use crate::{BoneKind, Isometry, Point, Skeleton, UnitQuat};
+
+use petgraph::graph::EdgeIndex;
+
+/// Identifies a tracker attached to the [`Skeleton`].
+///
+/// The id stays valid until its tracker is detached. A skeleton never reuses ids, so
+/// the id of a detached tracker stays invalid, and trackers that are attached later
+/// get larger ids.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct TrackerId(pub(crate) u32);
+// Integer conversions are provided for language bindings. Invalid ids are rejected by
+// the skeleton with [`TrackerError::InvalidId`].
+impl From<TrackerId> for u32 {
+	fn from(other: TrackerId) -> Self {
+		other.0
+	}
+}
+impl From<u32> for TrackerId {
+	fn from(other: u32) -> Self {
+		Self(other)
+	}
+}
+
+impl Skeleton {
+	/// Attaches a new input tracker to `attach_to`, and returns its [`TrackerId`].
+	///
+	/// Provide `pos` for trackers that have positional data, and `rot` for trackers
+	/// that have rotational data. "3DoF" trackers will only provide `rot`, and "6DoF"
+	/// trackers will provide both.
+	///
+	/// Until the skeleton is calibrated, the tracker is assumed to sit exactly at the
+	/// head of the bone, with the same rotation as the bone.
+	pub fn attach_input_tracker(
+		&mut self,
+		attach_to: BoneKind,
+		pos: Option<Global<Point>>,
+		rot: Option<Global<UnitQuat>>,
+	) -> TrackerId {
+		let mut node = Node::new();
+		node.input_pos_g = pos;
+		let mut edge = Edge::new(EdgeKind::InputTracker(attach_to), 0.);
+		edge.input_rot_g = rot;
+		self.add_tracker(attach_to, node, edge)
+	}
+
+	/// Updates the position and rotation of an input tracker. This is typically done
+	/// every frame, before calling [`Skeleton::solve()`].
+	///
+	/// Passing `None` removes the existing position or rotation constraint.
+	pub fn update_input_tracker(
+		&mut self,
+		id: TrackerId,
+		pos: Option<Global<Point>>,
+		rot: Option<Global<UnitQuat>>,
+	) -> Result<(), TrackerError> {
+		let (edge, _bone) = self.input_tracker(id)?;
+		let (_bone_head, tracker_node) = self.graph.edge_endpoints(edge).unwrap();
+		self.graph[tracker_node].input_pos_g = pos;
+		let edge = &mut self.graph[edge];
+		edge.input_rot_g = rot.map(|rot| Global(edge.yaw_fix_g.0 * rot.0));
+		Ok(())
+	}
+
+	/// Detaches an input tracker from the skeleton. After this, `id` is no longer
+	/// valid.
+	pub fn detach_input_tracker(&mut self, id: TrackerId) -> Result<(), TrackerError> {
+		self.input_tracker(id)?;
+		self.remove_tracker(id);
+		Ok(())
+	}
+
+	/// Gets the offset of an input tracker from the head of its bone, in the local frame
+	/// of that bone. This is computed by calibration.
+	pub fn input_tracker_offset(
+		&self,
+		id: TrackerId,
+	) -> Result<Local<Isometry>, TrackerError> {
+		let (edge, _bone) = self.input_tracker(id)?;
+		let edge = &self.graph[edge];
+		Ok(Local(Isometry::from_parts(
+			edge.offset_l.0,
+			edge.calib_rot_l.0,
+		)))
+	}
+
+	/// Overrides the offset of an input tracker, for example to restore the results of
+	/// a previous calibration. See [`Skeleton::input_tracker_offset()`].
+	pub fn set_input_tracker_offset(
+		&mut self,
+		id: TrackerId,
+		local_offset: Local<Isometry>,
+	) -> Result<(), TrackerError> {
+		let (edge, _bone) = self.input_tracker(id)?;
+		let edge = &mut self.graph[edge];
+		edge.calib_rot_l = Local(local_offset.0.rotation);
+		edge.offset_l = Local(local_offset.0.translation);
+		Ok(())
+	}
+
+	/// Iterates over all input trackers, and the bones they are attached to.
+	pub fn input_trackers(&self) -> impl Iterator<Item = (TrackerId, BoneKind)> + '_ {
+		self.trackers
+			.iter()
+			.filter_map(|(&id, &edge)| match self.graph[edge].kind {
+				EdgeKind::InputTracker(bone) => Some((id, bone)),
+				_ => None,
+			})
+	}
+
+	/// Adds a new output tracker to `attach_to`, and returns its [`TrackerId`].
+	///
+	/// Output trackers are synthetic 6DoF trackers, such as the hip and feet trackers
+	/// that get sent to SteamVR. They get solved along with the rest of the skeleton,
+	/// and their pose is available via [`Skeleton::output_tracker_pose()`].
+	///
+	/// `local_offset` is the pose of the tracker relative to the head of the bone, in
+	/// the local frame of that bone.
+	pub fn add_output_tracker(
+		&mut self,
+		attach_to: BoneKind,
+		local_offset: Local<Isometry>,
+	) -> TrackerId {
+		let mut edge = Edge::new(EdgeKind::OutputTracker(attach_to), 0.);
+		edge.calib_rot_l = Local(local_offset.0.rotation);
+		edge.offset_l = Local(local_offset.0.translation);
+		self.add_tracker(attach_to, Node::new(), edge)
+	}
+
+	/// Changes the offset of an output tracker. See [`Skeleton::add_output_tracker()`].
+	pub fn set_output_tracker_offset(
+		&mut self,
+		id: TrackerId,
+		local_offset: Local<Isometry>,
+	) -> Result<(), TrackerError> {
+		let (edge, _bone) = self.output_tracker(id)?;
+		let edge = &mut self.graph[edge];
+		edge.calib_rot_l = Local(local_offset.0.rotation);
+		edge.offset_l = Local(local_offset.0.translation);
+		Ok(())
+	}
+
+	/// Removes an output tracker from the skeleton. After this, `id` is no longer
+	/// valid.
+	pub fn remove_output_tracker(&mut self, id: TrackerId) -> Result<(), TrackerError> {
+		self.output_tracker(id)?;
+		self.remove_tracker(id);
+		Ok(())
+	}
+
+	/// Iterates over all output trackers, and the bones they are attached to.
+	pub fn output_trackers(&self) -> impl Iterator<Item = (TrackerId, BoneKind)> + '_ {
+		self.trackers
+			.iter()
+			.filter_map(|(&id, &edge)| match self.graph[edge].kind {
+				EdgeKind::OutputTracker(bone) => Some((id, bone)),
+				_ => None,
+			})
+	}
+
+	/// Gets the pose of an output tracker, as of the last [`Skeleton::solve()`].
+	pub fn output_tracker_pose(
+		&self,
+		id: TrackerId,
+	) -> Result<Global<Isometry>, TrackerError> {
+		let (edge, _bone) = self.output_tracker(id)?;
+		let (_bone_head, tracker_node) = self.graph.edge_endpoints(edge).unwrap();
+		let pos = self.graph[tracker_node].output_pos_g.0;
+		let rot = self.graph[edge].output_rot_g.0;
+		Ok(Global(Isometry::from_parts(pos.coords.into(), rot)))
+	}
+
+	/// Iterates over the poses of all output trackers, as of the last
+	/// [`Skeleton::solve()`].
+	pub fn output_tracker_poses(
+		&self,
+	) -> impl Iterator<Item = (TrackerId, Global<Isometry>)> + '_ {
+		self.output_trackers()
+			.map(|(id, _bone)| (id, self.output_tracker_pose(id).unwrap()))
+	}
+
+	// ---- Private fns ----
+
+	/// Adds a tracker with `node` and `edge` to the head of `attach_to`, and gives it
+	/// the next [`TrackerId`].
+	fn add_tracker(
+		&mut self,
+		attach_to: BoneKind,
+		node: Node,
+		edge: Edge,
+	) -> TrackerId {
+		let (bone_head, _bone_tail) =
+			self.graph.edge_endpoints(self.bone_map[attach_to]).unwrap();
+		let tracker_node = self.graph.add_node(node);
+		let edge = self.graph.add_edge(bone_head, tracker_node, edge);
+
+		let id = TrackerId(self.next_tracker_id);
+		self.next_tracker_id = self
+			.next_tracker_id
+			.checked_add(1)
+			.expect("Ran out of tracker ids");
+		self.trackers.insert(id, edge);
+		id
+	}
+
+	/// Removes the tracker `id`, which must be valid.
+	fn remove_tracker(&mut self, id: TrackerId) {
+		let edge = self.trackers.remove(&id).unwrap();
+		let (_bone_head, tracker_node) = self.graph.edge_endpoints(edge).unwrap();
+		// Removing the node also removes the edge.
+		self.graph.remove_node(tracker_node);
+	}
+
+	/// Gets the edge of the input tracker `id`, and the bone it is attached to.
+	pub(crate) fn input_tracker(
+		&self,
+		id: TrackerId,
+	) -> Result<(EdgeIndex, BoneKind), TrackerError> {
+		match self.tracker_edge(id) {
+			Some((edge, EdgeKind::InputTracker(bone))) => Ok((edge, bone)),
+			_ => Err(TrackerError::InvalidId(id)),
+		}
+	}
+
+	/// Gets the edge of the output tracker `id`, and the bone it is attached to.
+	pub(crate) fn output_tracker(
+		&self,
+		id: TrackerId,
+	) -> Result<(EdgeIndex, BoneKind), TrackerError> {
+		match self.tracker_edge(id) {
+			Some((edge, EdgeKind::OutputTracker(bone))) => Ok((edge, bone)),
+			_ => Err(TrackerError::InvalidId(id)),
+		}
+	}
+
+	/// Gets the id of the tracker that `edge` belongs to.
+	pub(crate) fn tracker_id(&self, edge: EdgeIndex) -> Option<TrackerId> {
+		self.trackers
+			.iter()
+			.find_map(|(&id, &e)| (e == edge).then_some(id))
+	}
+
+	fn tracker_edge(&self, id: TrackerId) -> Option<(EdgeIndex, EdgeKind)> {
+		let edge = *self.trackers.get(&id)?;
+		Some((edge, self.graph[edge].kind))
+	}
+
+	/// Sets the [`Edge::input_rot_g`] of every bone based on the input trackers
+	/// attached to it.
+	///
+	/// If a bone has multiple trackers with a rotation, the one with the smallest
+	/// [`TrackerId`] is used.
+	pub(crate) fn apply_input_trackers(&mut self) {
+		for bone in BoneKind::iter() {
+			self.graph[self.bone_map[bone]].input_rot_g = None;
+		}
+		// `input_trackers()` is in ascending order, so we skip bones that were already
+		// set by a smaller `TrackerId`.
+		let trackers: Vec<_> = self.input_trackers().collect();
+		for (id, bone) in trackers {
+			let tracker = &self.graph[self.trackers[&id]];
+			let Some(tracker_rot_g) = tracker.input_rot_g else {
+				continue;
+			};
+			// tracker_rot_g = bone_rot_g * calib_rot_l, so we invert `calib_rot_l`.
+			let bone_rot_g = tracker_rot_g.0 * tracker.calib_rot_l.0.inverse();
+			let bone_edge = &mut self.graph[self.bone_map[bone]];
+			if bone_edge.input_rot_g.is_none() {
+				bone_edge.input_rot_g = Some(Global(bone_rot_g));
+			}
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TrackerError {
+	#[error("`{0:?}` does not refer to an attached tracker")]
+	InvalidId(TrackerId),
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::bone::BoneMap;
+	use crate::skeleton::SkeletonConfig;
+	use crate::Translation;
+
+	use approx::assert_relative_eq;
+	use nalgebra::Vector3;
+	use std::f32::consts::FRAC_PI_2;
+
+	fn make_skeleton() -> Skeleton {
+		let bone_lengths = BoneMap::new([0.5; BoneKind::NUM_TYPES]);
+		Skeleton::new(&SkeletonConfig::new(bone_lengths))
+	}
+
+	#[test]
+	fn test_attach_update_detach() {
+		let mut skeleton = make_skeleton();
+		let hmd = skeleton.attach_input_tracker(
+			BoneKind::Neck,
+			Some(Global(Point::new(0., 1.7, 0.))),
+			Some(Global(UnitQuat::identity())),
+		);
+		let chest = skeleton.attach_input_tracker(BoneKind::Chest, None, None);
+		assert_ne!(hmd, chest);
+		assert_eq!(
+			skeleton.input_trackers().collect::<Vec<_>>(),
+			vec![(hmd, BoneKind::Neck), (chest, BoneKind::Chest)]
+		);
+
+		let rot = UnitQuat::from_axis_angle(&Vector3::x_axis(), 0.5);
+		skeleton
+			.update_input_tracker(chest, None, Some(Global(rot)))
+			.unwrap();
+		skeleton.solve().unwrap();
+		assert_relative_eq!(skeleton[BoneKind::Chest].output_rot_g.0, rot);
+
+		// Detaching one tracker leaves the other one alone.
+		skeleton.detach_input_tracker(chest).unwrap();
+		assert!(matches!(
+			skeleton.update_input_tracker(chest, None, None),
+			Err(TrackerError::InvalidId(_))
+		));
+		assert!(skeleton.detach_input_tracker(chest).is_err());
+		assert_eq!(skeleton.input_tracker(hmd).unwrap().1, BoneKind::Neck);
+
+		// Without the chest tracker, the chest goes back to its calibration rotation.
+		skeleton.solve().unwrap();
+		assert_relative_eq!(
+			skeleton[BoneKind::Chest].output_rot_g.0,
+			BoneKind::Chest.calibration_rotation().0
+		);
+	}
+
+	#[test]
+	fn test_tracker_position() {
+		let mut skeleton = make_skeleton();
+		let pos = Point::new(1., 2., 3.);
+		let id =
+			skeleton.attach_input_tracker(BoneKind::FootL, Some(Global(pos)), None);
+		skeleton.solve().unwrap();
+
+		// Uncalibrated trackers sit on the head of their bone.
+		let (head, _) = skeleton
+			.graph
+			.edge_endpoints(skeleton.bone_map[BoneKind::FootL])
+			.unwrap();
+		assert_relative_eq!(skeleton.graph[head].output_pos_g.0, pos);
+
+		// Ids that were never handed out are invalid.
+		assert!(skeleton.input_tracker(TrackerId(1000)).is_err());
+		skeleton.detach_input_tracker(id).unwrap();
+		assert!(skeleton.solve().is_err());
+	}
+
+	#[test]
+	fn test_stale_ids() {
+		let mut skeleton = make_skeleton();
+		let first = skeleton.attach_input_tracker(BoneKind::Chest, None, None);
+		let second = skeleton.attach_input_tracker(BoneKind::Hip, None, None);
+		skeleton.detach_input_tracker(first).unwrap();
+
+		// The new tracker gets a new id, even though its edge may reuse the old one.
+		let third = skeleton.attach_input_tracker(BoneKind::Neck, None, None);
+		assert!(first < second && second < third);
+		assert!(matches!(
+			skeleton.update_input_tracker(first, None, None),
+			Err(TrackerError::InvalidId(id)) if id == first
+		));
+		assert!(skeleton.remove_output_tracker(first).is_err());
+		assert_eq!(
+			skeleton.input_trackers().collect::<Vec<_>>(),
+			vec![(second, BoneKind::Hip), (third, BoneKind::Neck)]
+		);
+	}
+
+	#[test]
+	fn test_output_trackers() {
+		let mut skeleton = make_skeleton();
+		skeleton.attach_input_tracker(
+			BoneKind::Neck,
+			Some(Global(Point::new(0., 2.5, 0.))),
+			None,
+		);
+		// Thighs point forward, like when sitting.
+		let thigh_rot = UnitQuat::from_axis_angle(&Vector3::x_axis(), FRAC_PI_2);
+		skeleton.attach_input_tracker(BoneKind::ThighL, None, Some(Global(thigh_rot)));
+
+		// A hip tracker worn on the back, and a knee tracker worn on the front of the
+		// thigh, facing forward.
+		let hip = skeleton.add_output_tracker(
+			BoneKind::Hip,
+			Local(Isometry::translation(0., 0., 0.1)),
+		);
+		let knee_offset = Isometry::from_parts(
+			Translation::new(0., -0.4, -0.1),
+			UnitQuat::from_axis_angle(&Vector3::x_axis(), -FRAC_PI_2),
+		);
+		let knee = skeleton.add_output_tracker(BoneKind::ThighL, Local(knee_offset));
+		skeleton.solve().unwrap();
+
+		// All bones are 0.5 long, so the head of the hip is 2.0 below the neck.
+		let hip_pose = skeleton.output_tracker_pose(hip).unwrap().0;
+		assert_relative_eq!(hip_pose.translation.vector, Vector3::new(0., 0.5, 0.1));
+		assert_relative_eq!(hip_pose.rotation, UnitQuat::identity());
+
+		// The thigh's "down" is now forward, and its "forward" is now up.
+		let knee_pose = skeleton.output_tracker_pose(knee).unwrap().0;
+		assert_relative_eq!(
+			knee_pose.translation.vector,
+			Vector3::new(0., 0.1, -0.4),
+			epsilon = 1e-6
+		);
+		assert_relative_eq!(knee_pose.rotation, UnitQuat::identity(), epsilon = 1e-6);
+
+		let poses: Vec<_> = skeleton.output_tracker_poses().map(|(id, _)| id).collect();
+		assert_eq!(poses, vec![hip, knee]);
+
+		// Output trackers are not input trackers, and vice versa.
+		assert!(skeleton.detach_input_tracker(hip).is_err());
+		skeleton.remove_output_tracker(hip).unwrap();
+		assert!(skeleton.output_tracker_pose(hip).is_err());
+		assert_eq!(skeleton.output_trackers().count(), 1);
+	}
+}
@@ -0,0 +1,109 @@
+//! Measures the cost of one [`Vqf::update()`] on the host, for several configurations.
+//!
+//! Run it with `cargo bench -p vqf`, and again with `--no-default-features` to see the
+//! cost without motion bias estimation compiled in. On x86_64, the cost is also counted
+//! in cycles of the time stamp counter.
+//!
+//! The host has an FPU, so this can't tell the absolute cost on an MCU. But it shows
+//! how the configurations compare, and catches regressions.
+
+use nalgebra::Vector3;
+use std::time::Instant;
+use vqf::{Vqf, VqfParameters};
+
+const TS: f32 = 1.0 / 400.0;
+const SAMPLES: usize = 400 * 60;
+
+/// A sensor that turns around for half of the time, and lies still for the rest.
+fn samples() -> Vec<(Vector3<f32>, Vector3<f32>, Vector3<f32>)> {
+	(0..SAMPLES)
+		.map(|i| {
+			let t = i as f32 * TS;
+			let moving = (t % 10.0) < 5.0;
+			let gyr = if moving {
+				Vector3::new(
+					(0.7 * t).sin(),
+					0.6 * (1.1 * t).sin(),
+					1.2 * (0.5 * t).cos(),
+				)
+			} else {
+				Vector3::zeros()
+			};
+			let acc = Vector3::new(0.3 * t.sin(), 0.2 * t.cos(), 9.81);
+			let mag = Vector3::new(20.0 * t.sin(), 20.0 * t.cos(), -40.0);
+			(gyr + Vector3::new(0.01, -0.02, 0.015), acc, mag)
+		})
+		.collect()
+}
+
+#[cfg(target_arch = "x86_64")]
+fn cycles() -> Option<u64> {
+	// SAFETY: every x86_64 CPU has the time stamp counter.
+	#[allow(unused_unsafe)]
+	Some(unsafe { core::arch::x86_64::_rdtsc() })
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn cycles() -> Option<u64> {
+	None
+}
+
+fn bench(name: &str, params: VqfParameters, with_mag: bool) {
+	let samples = samples();
+	let mut vqf = Vqf::new(TS, TS, TS, params.clone());
+	// Warm up the caches and the branch predictor.
+	for (gyr, acc, mag) in &samples {
+		vqf.update(*gyr, *acc, with_mag.then_some(*mag));
+	}
+
+	let mut vqf = Vqf::new(TS, TS, TS, params);
+	let start_cycles = cycles();
+	let start = Instant::now();
+	for (gyr, acc, mag) in &samples {
+		vqf.update(*gyr, *acc, with_mag.then_some(*mag));
+	}
+	let elapsed = start.elapsed();
+	let end_cycles = cycles();
+
+	let ns = elapsed.as_nanos() as f64 / SAMPLES as f64;
+	let cycles = match (start_cycles, end_cycles) {
+		(Some(start), Some(end)) => {
+			format!("{:.0}", (end - start) as f64 / SAMPLES as f64)
+		}
+		_ => String::from("-"),
+	};
+	// Printing the result keeps the optimizer from removing the updates.
+	let w = vqf.quat_9d().w;
+	println!("{name:<28} {ns:>10.1} {cycles:>14} {w:>10.4}");
+}
+
+fn main() {
+	println!(
+		"motion-bias-est feature: {}",
+		if cfg!(feature = "motion-bias-est") {
+			"on"
+		} else {
+			"off"
+		}
+	);
+	println!(
+		"{:<28} {:>10} {:>14} {:>10}",
+		"configuration", "ns/update", "cycles/update", "quat9d.w"
+	);
+
+	bench("6d", VqfParameters::default(), false);
+	bench("9d", VqfParameters::default(), true);
+	let no_motion_bias = VqfParameters {
+		motion_bias_est_enabled: false,
+		..Default::default()
+	};
+	bench("6d, no motion bias", no_motion_bias.clone(), false);
+	bench("9d, no motion bias", no_motion_bias, true);
+	let no_bias = VqfParameters {
+		motion_bias_est_enabled: false,
+		rest_bias_est_enabled: false,
+		mag_dist_rejection_enabled: false,
+		..Default::default()
+	};
+	bench("6d, no bias or rejection", no_bias, false);
+}
@@ -0,0 +1,90 @@
+//! Processing of whole sequences of samples.
+
+use crate::{Quat, Vec3, Vqf};
+
+/// The outputs of [`Vqf`] after a single sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VqfOutput {
+	/// The 6D orientation, as in [`Vqf::quat_6d()`].
+	pub quat6d: Quat,
+	/// The 9D orientation, as in [`Vqf::quat_9d()`].
+	pub quat9d: Quat,
+	/// The heading offset, as in [`Vqf::delta()`].
+	pub delta: f32,
+	/// The gyroscope bias estimate in rad/s, as in [`Vqf::bias_estimate()`].
+	pub bias: Vec3,
+	/// The standard deviation of the bias estimate in rad/s.
+	pub bias_sigma: f32,
+	/// Whether the sensor was at rest.
+	pub rest_detected: bool,
+	/// Whether the magnetic field was disturbed.
+	pub mag_dist_detected: bool,
+}
+
+impl Vqf {
+	/// Updates the filter with every sample in turn, like calling [`Vqf::update()`]
+	/// for each of them. The samples must all have the same length. Without `mag`, the
+	/// 9D orientation only follows the 6D one.
+	///
+	/// Returns the outputs after each sample. The filter is updated as the iterator is
+	/// advanced, so nothing happens until it is consumed.
+	pub fn update_batch<'a>(
+		&'a mut self,
+		gyr: &'a [Vec3],
+		acc: &'a [Vec3],
+		mag: Option<&'a [Vec3]>,
+	) -> impl Iterator<Item = VqfOutput> + 'a {
+		assert_eq!(gyr.len(), acc.len());
+		if let Some(mag) = mag {
+			assert_eq!(gyr.len(), mag.len());
+		}
+		gyr.iter().zip(acc).enumerate().map(move |(i, (gyr, acc))| {
+			self.update(*gyr, *acc, mag.map(|mag| mag[i]));
+			self.output()
+		})
+	}
+
+	/// The outputs of the filter in its current state.
+	pub(crate) fn output(&self) -> VqfOutput {
+		let (bias, bias_sigma) = self.bias_estimate();
+		VqfOutput {
+			quat6d: self.quat_6d(),
+			quat9d: self.quat_9d(),
+			delta: self.delta(),
+			bias,
+			bias_sigma,
+			rest_detected: self.rest_detected(),
+			mag_dist_detected: self.mag_dist_detected(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::VqfParameters;
+
+	use core::f32::consts::PI;
+
+	#[test]
+	fn test_update_batch() {
+		let gyr: [Vec3; 300] =
+			core::array::from_fn(|i| Vec3::new(0.5, -0.2, (i as f32 / 50.0).sin()));
+		let acc: [Vec3; 300] = core::array::from_fn(|i| {
+			let tilt = Quat::from_axis_angle(&Vec3::x_axis(), i as f32 * PI / 600.0);
+			tilt.inverse() * Vec3::new(0.0, 0.0, 9.81)
+		});
+		let mag = [Vec3::new(0.0, 20.0, -40.0); 300];
+
+		let mut batch = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		let mut single = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		let mut count = 0;
+		for (i, output) in batch.update_batch(&gyr, &acc, Some(&mag)).enumerate() {
+			single.update(gyr[i], acc[i], Some(mag[i]));
+			assert_eq!(output, single.output());
+			count += 1;
+		}
+		assert_eq!(count, 300);
+		assert_eq!(batch.quat_9d(), single.quat_9d());
+	}
+}
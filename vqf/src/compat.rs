@@ -0,0 +1,211 @@
+//! The names of the reference implementation, which this crate used before it got a
+//! Rust-style API. The old methods forward to the new ones. Rust can't alias fields, so
+//! the parameters and state with the old field names are separate structs, which
+//! convert to and from the new ones.
+
+#![allow(non_snake_case)]
+#![allow(deprecated)]
+
+use crate::{
+	Mat2x2, Mat2x3, Mat2x9, Mat3x3, Quat, Vec2, Vec3, Vqf, VqfParameters, VqfState,
+};
+
+macro_rules! renamed {
+	($(fn $old:ident(&self $(, $arg:ident: $ty:ty)*) -> $ret:ty => $new:ident;)*) => {
+		$(
+			#[doc = concat!("Renamed to [`Vqf::", stringify!($new), "()`].")]
+			#[deprecated = concat!("Renamed to `", stringify!($new), "`")]
+			pub fn $old(&self $(, $arg: $ty)*) -> $ret {
+				self.$new($($arg),*)
+			}
+		)*
+	};
+	($(fn $old:ident(&mut self $(, $arg:ident: $ty:ty)*) => $new:ident;)*) => {
+		$(
+			#[doc = concat!("Renamed to [`Vqf::", stringify!($new), "()`].")]
+			#[deprecated = concat!("Renamed to `", stringify!($new), "`")]
+			pub fn $old(&mut self $(, $arg: $ty)*) {
+				self.$new($($arg),*)
+			}
+		)*
+	};
+}
+
+impl Vqf {
+	renamed! {
+		fn getQuat3D(&self) -> Quat => quat_3d;
+		fn getQuat6D(&self) -> Quat => quat_6d;
+		fn getQuat9D(&self) -> Quat => quat_9d;
+		fn getDelta(&self) -> f32 => delta;
+		fn getBiasEstimate(&self) -> (Vec3, f32) => bias_estimate;
+		fn getRestDetected(&self) -> bool => rest_detected;
+		fn getMagDistDetected(&self) -> bool => mag_dist_detected;
+		fn getRelativeRestDeviations(&self) -> Vec2 => relative_rest_deviations;
+		fn getMagRefNorm(&self) -> f32 => mag_ref_norm;
+		fn getMagRefDip(&self) -> f32 => mag_ref_dip;
+		fn getState(&self) -> &VqfState => state;
+	}
+
+	renamed! {
+		fn updateGyr(&mut self, gyr: Vec3) => update_gyr;
+		fn updateAcc(&mut self, acc: Vec3) => update_acc;
+		fn updateMag(&mut self, mag: Vec3) => update_mag;
+		fn setBiasEstimate(&mut self, bias: Vec3, sigma: f32) => set_bias_estimate;
+		fn setMagRef(&mut self, norm: f32, dip: f32) => set_mag_ref;
+		fn resetState(&mut self) => reset_state;
+		fn setState(&mut self, state: VqfState) => set_state;
+	}
+}
+
+/// Declares a struct with the old field names, and its conversions to and from the
+/// struct with the new ones.
+macro_rules! renamed_struct {
+	(
+		$(#[$meta:meta])*
+		struct $old:ident => $new:ident {
+			$($old_field:ident: $ty:ty => $new_field:ident,)*
+		}
+	) => {
+		#[doc = concat!(
+			"[`", stringify!($new), "`], with the field names of the reference ",
+			"implementation.",
+		)]
+		#[deprecated = concat!("Renamed to `", stringify!($new), "`")]
+		$(#[$meta])*
+		pub struct $old {
+			$(pub $old_field: $ty,)*
+		}
+		impl From<$old> for $new {
+			fn from(other: $old) -> Self {
+				Self {
+					$($new_field: other.$old_field,)*
+				}
+			}
+		}
+		impl From<&$new> for $old {
+			fn from(other: &$new) -> Self {
+				Self {
+					$($old_field: other.$new_field,)*
+				}
+			}
+		}
+		impl From<$new> for $old {
+			fn from(other: $new) -> Self {
+				Self::from(&other)
+			}
+		}
+	};
+}
+
+renamed_struct! {
+	#[derive(Debug, Clone)]
+	struct VQFParams => VqfParameters {
+		tauAcc: f32 => tau_acc,
+		tauMag: f32 => tau_mag,
+		motionBiasEstEnabled: bool => motion_bias_est_enabled,
+		restBiasEstEnabled: bool => rest_bias_est_enabled,
+		magDistRejectionEnabled: bool => mag_dist_rejection_enabled,
+		biasSigmaInit: f32 => bias_sigma_init,
+		biasForgettingTime: f32 => bias_forgetting_time,
+		biasClip: f32 => bias_clip,
+		biasSigmaMotion: f32 => bias_sigma_motion,
+		biasVerticalForgettingFactor: f32 => bias_vertical_forgetting_factor,
+		biasSigmaRest: f32 => bias_sigma_rest,
+		restMinT: f32 => rest_min_t,
+		restFilterTau: f32 => rest_filter_tau,
+		restThGyr: f32 => rest_th_gyr,
+		restThAcc: f32 => rest_th_acc,
+		magCurrentTau: f32 => mag_current_tau,
+		magRefTau: f32 => mag_ref_tau,
+		magNormTh: f32 => mag_norm_th,
+		magDipTh: f32 => mag_dip_th,
+		magNewTime: f32 => mag_new_time,
+		magNewFirstTime: f32 => mag_new_first_time,
+		magNewMinGyr: f32 => mag_new_min_gyr,
+		magMinUndisturbedTime: f32 => mag_min_undisturbed_time,
+		magMaxRejectionTime: f32 => mag_max_rejection_time,
+		magRejectionFactor: f32 => mag_rejection_factor,
+	}
+}
+impl Default for VQFParams {
+	fn default() -> Self {
+		VqfParameters::default().into()
+	}
+}
+
+renamed_struct! {
+	struct VQFState => VqfState {
+		gyrQuat: Quat => gyr_quat,
+		accQuat: Quat => acc_quat,
+		delta: f32 => delta,
+		restDetected: bool => rest_detected,
+		magDistDetected: bool => mag_dist_detected,
+		lastAccLp: Vec3 => last_acc_lp,
+		accLpState: Mat2x3 => acc_lp_state,
+		kMagInit: f32 => k_mag_init,
+		lastMagDisAngle: f32 => last_mag_dis_angle,
+		lastMagCorrAngularRate: f32 => last_mag_corr_angular_rate,
+		bias: Vec3 => bias,
+		biasP: Mat3x3 => bias_p,
+		motionBiasEstRLpState: Mat2x9 => motion_bias_est_rlp_state,
+		motionBiasEstBiasLpState: Mat2x2 => motion_bias_est_bias_lp_state,
+		restLastSquaredDeviations: Vec2 => rest_last_squared_deviations,
+		restT: f32 => rest_t,
+		restLastGyrLp: Vec3 => rest_last_gyr_lp,
+		restGyrLpState: Mat2x3 => rest_gyr_lp_state,
+		restLastAccLp: Vec3 => rest_last_acc_lp,
+		restAccLpState: Mat2x3 => rest_acc_lp_state,
+		magRefNorm: f32 => mag_ref_norm,
+		magRefDip: f32 => mag_ref_dip,
+		magUndisturbedT: f32 => mag_undisturbed_t,
+		magRejectT: f32 => mag_reject_t,
+		magCandidateNorm: f32 => mag_candidate_norm,
+		magCandidateDip: f32 => mag_candidate_dip,
+		magCandidateT: f32 => mag_candidate_t,
+		magNormDip: Vec2 => mag_norm_dip,
+		magNormDipLpState: Mat2x2 => mag_norm_dip_lp_state,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::VqfParameters;
+
+	#[test]
+	#[allow(deprecated)]
+	fn test_old_names() {
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		vqf.updateGyr(Vec3::new(0.0, 0.0, 1.0));
+		vqf.updateAcc(Vec3::new(0.0, 0.0, 9.81));
+		vqf.setMagRef(45.0, 1.0);
+		assert_eq!(vqf.getQuat6D(), vqf.quat_6d());
+		assert_eq!(vqf.getMagRefNorm(), 45.0);
+		vqf.resetState();
+		assert_eq!(vqf.getQuat3D(), Quat::identity());
+	}
+
+	#[test]
+	#[allow(deprecated)]
+	fn test_old_structs() {
+		let params = VQFParams {
+			tauAcc: 2.0,
+			magDistRejectionEnabled: false,
+			..Default::default()
+		};
+		let params = VqfParameters::from(params);
+		assert_eq!(params.tau_acc, 2.0);
+		assert!(!params.mag_dist_rejection_enabled);
+		assert_eq!(params.tau_mag, VqfParameters::default().tau_mag);
+		assert_eq!(VQFParams::from(params).tauAcc, 2.0);
+
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		vqf.update(Vec3::new(0.1, 0.0, 0.0), Vec3::new(0.0, 0.0, 9.81), None);
+		let mut state = VQFState::from(vqf.state());
+		assert_eq!(state.gyrQuat, vqf.state().gyr_quat);
+		state.restT = 1.0;
+		vqf.set_state(state.into());
+		assert_eq!(vqf.state().rest_t, 1.0);
+		assert_eq!(vqf.quat_3d(), vqf.getQuat3D());
+	}
+}
@@ -1,17 +1,58 @@
 //! This crate reimplements most of the relevant parts of the VQF algorithm from
 //! <https://github.com/dlaidig/vqf/blob/f2a63375604e0b025048d181ba6a204e96ce2559/vqf/pyvqf.py>
-//! Currently this is just copy-pasted from the python code, but it should be made more
-//! idiomatic before actually using it. I have marked areas most likely to contain bugs
-//! with ඞ
+//!
+//! Create a [`Vqf`] with the sampling times of the sensors and the
+//! [`VqfParameters`], feed it samples with [`Vqf::update()`], and read the orientation
+//! with [`Vqf::quat_6d()`] or [`Vqf::quat_9d()`]. For IMUs whose samples don't arrive
+//! at a fixed rate, use [`Vqf::update_dt()`]. Calibrate the magnetometer with a
+//! [`MagCalibrator`] before using it.
+//!
+//! # Features
+//! - `motion-bias-est` (default): Gyroscope bias estimation while moving, see
+//!   [`VqfParameters::motion_bias_est_enabled`]. It low-pass filters a rotation matrix
+//!   in every accelerometer update, which is most of the cost of the filter. Turn it off
+//!   on MCUs without an FPU, where that cost limits the update rate. The bias is then
+//!   only estimated at rest.
+//! - `alloc`: Offline processing with [`offline_vqf()`].
+//!
+//! The methods used to be named like the ones of the reference implementation, such as
+//! `getQuat6D`. Those names still work, but are deprecated. The fields of
+//! [`VqfParameters`] and [`VqfState`] were renamed to `snake_case` as well, like
+//! `tauAcc` to `tau_acc` and `gyrQuat` to `gyr_quat`. Rust can't alias fields, so the
+//! deprecated [`VQFParams`] and [`VQFState`] keep the old field names, and convert to
+//! and from the new structs with [`From`].
 //!
 //! The original code is licensed under the MIT license, so this crate is also licensed under the MIT license.
 
 #![no_std]
-#![allow(non_snake_case)]
 
-use core::f32::consts::PI;
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+mod batch;
+mod compat;
+mod mag_calib;
+#[cfg(feature = "alloc")]
+mod offline;
+mod params;
+mod state;
+
+/// The version of `nalgebra` used in the API.
+pub use nalgebra;
 
-use nalgebra::{ArrayStorage, U2, U9};
+pub use batch::VqfOutput;
+#[allow(deprecated)]
+pub use compat::{VQFParams, VQFState};
+pub use mag_calib::{FitError, FitQuality, MagCalibration, MagCalibrator, MagFit};
+#[cfg(feature = "alloc")]
+pub use offline::offline_vqf;
+pub use params::{ParamError, VqfParameters, VqfParametersBuilder};
+pub use state::{SavedState, StateError};
+
+use core::f32::consts::{PI, SQRT_2};
+
+use nalgebra::{ArrayStorage, SMatrix, SVector, U2, U9};
+use num_traits::Float;
 
 type Quat = nalgebra::UnitQuaternion<f32>;
 type Vec2 = nalgebra::Vector2<f32>;
@@ -22,583 +63,821 @@ type Mat2x2 = nalgebra::Matrix2<f32>;
 type Mat3x3 = nalgebra::Matrix3<f32>;
 
 const EPS: f32 = 1e-6;
+/// Below this half angle, in radians, [`gyr_step_quat()`] approximates the sine and
+/// cosine with polynomials. Their error is below the precision of `f32`.
+const SMALL_HALF_ANGLE: f32 = 0.15;
 
-pub struct VqfParameters {
-	pub tauAcc: f32,
-	pub tauMag: f32,
-	pub motionBiasEstEnabled: bool,
-	pub restBiasEstEnabled: bool,
-	pub magDistRejectionEnabled: bool,
-	pub biasSigmaInit: f32,
-	pub biasForgettingTime: f32,
-	pub biasClip: f32,
-	pub biasSigmaMotion: f32,
-	pub biasVerticalForgettingFactor: f32,
-	pub biasSigmaRest: f32,
-	pub restMinT: f32,
-	pub restFilterTau: f32,
-	pub restThGyr: f32,
-	pub restThAcc: f32,
-	pub magCurrentTau: f32,
-	pub magRefTau: f32,
-	pub magNormTh: f32,
-	pub magDipTh: f32,
-	pub magNewTime: f32,
-	pub magNewFirstTime: f32,
-	pub magNewMinGyr: f32,
-	pub magMinUndisturbedTime: f32,
-	pub magMaxRejectionTime: f32,
-	pub magRejectionFactor: f32,
-}
-
-impl Default for VqfParameters {
-	fn default() -> Self {
-		VqfParameters {
-			tauAcc: 3.0,
-			tauMag: 9.0,
-			motionBiasEstEnabled: true,
-			restBiasEstEnabled: true,
-			magDistRejectionEnabled: true,
-			biasSigmaInit: 0.5,
-			biasForgettingTime: 100.0,
-			biasClip: 2.0,
-			biasSigmaMotion: 0.1,
-			biasVerticalForgettingFactor: 0.0001,
-			biasSigmaRest: 0.03,
-			restMinT: 1.5,
-			restFilterTau: 0.5,
-			restThGyr: 2.0,
-			restThAcc: 0.5,
-			magCurrentTau: 0.05,
-			magRefTau: 20.0,
-			magNormTh: 0.1,
-			magDipTh: 10.0,
-			magNewTime: 20.0,
-			magNewFirstTime: 5.0,
-			magNewMinGyr: 20.0,
-			magMinUndisturbedTime: 0.5,
-			magMaxRejectionTime: 60.0,
-			magRejectionFactor: 2.0,
-		}
-	}
-}
-
+/// The complete internal state of the filter. See [`Vqf::state()`].
 pub struct VqfState {
-	pub gyrQuat: Quat,
-	pub accQuat: Quat,
+	pub gyr_quat: Quat,
+	pub acc_quat: Quat,
 	pub delta: f32,
-	pub restDetected: bool,
-	pub magDistDetected: bool,
-	pub lastAccLp: Vec3,
-	pub accLpState: Mat2x3,
-	pub kMagInit: f32,
-	pub lastMagDisAngle: f32,
-	pub lastMagCorrAngularRate: f32,
+	pub rest_detected: bool,
+	pub mag_dist_detected: bool,
+	pub last_acc_lp: Vec3,
+	pub acc_lp_state: Mat2x3,
+	pub k_mag_init: f32,
+	pub last_mag_dis_angle: f32,
+	pub last_mag_corr_angular_rate: f32,
 	pub bias: Vec3,
-	pub biasP: Mat3x3,
-	pub motionBiasEstRLpState: Mat2x9,
-	pub motionBiasEstBiasLpState: Mat2x2,
-	pub restLastSquaredDeviations: Vec2,
-	pub restT: f32,
-	pub restLastGyrLp: Vec3,
-	pub restGyrLpState: Mat2x3,
-	pub restLastAccLp: Vec3,
-	pub restAccLpState: Mat2x3,
-	pub magRefNorm: f32,
-	pub magRefDip: f32,
-	pub magUndisturbedT: f32,
-	pub magRejectT: f32,
-	pub magCandidateNorm: f32,
-	pub magCandidateDip: f32,
-	pub magCandidateT: f32,
-	pub magNormDip: Vec2,
-	pub magNormDipLpState: Mat2x2,
+	pub bias_p: Mat3x3,
+	pub motion_bias_est_rlp_state: Mat2x9,
+	pub motion_bias_est_bias_lp_state: Mat2x2,
+	pub rest_last_squared_deviations: Vec2,
+	pub rest_t: f32,
+	pub rest_last_gyr_lp: Vec3,
+	pub rest_gyr_lp_state: Mat2x3,
+	pub rest_last_acc_lp: Vec3,
+	pub rest_acc_lp_state: Mat2x3,
+	pub mag_ref_norm: f32,
+	pub mag_ref_dip: f32,
+	pub mag_undisturbed_t: f32,
+	pub mag_reject_t: f32,
+	pub mag_candidate_norm: f32,
+	pub mag_candidate_dip: f32,
+	pub mag_candidate_t: f32,
+	pub mag_norm_dip: Vec2,
+	pub mag_norm_dip_lp_state: Mat2x2,
 }
 
 impl Default for VqfState {
 	fn default() -> VqfState {
 		VqfState {
-			gyrQuat: Quat::identity(),
-			accQuat: Quat::identity(),
+			gyr_quat: Quat::identity(),
+			acc_quat: Quat::identity(),
 			delta: 0.0,
-			restDetected: false,
-			magDistDetected: true,
-			lastAccLp: Vec3::zeros(),
-			accLpState: Mat2x3::repeat(f32::NAN),
-			kMagInit: 1.0,
-			lastMagDisAngle: 0.0,
-			lastMagCorrAngularRate: 0.0,
+			rest_detected: false,
+			mag_dist_detected: true,
+			last_acc_lp: Vec3::zeros(),
+			acc_lp_state: Mat2x3::repeat(f32::NAN),
+			k_mag_init: 1.0,
+			last_mag_dis_angle: 0.0,
+			last_mag_corr_angular_rate: 0.0,
 			bias: Vec3::zeros(),
-			biasP: Mat3x3::repeat(f32::NAN),
-			motionBiasEstRLpState: Mat2x9::repeat(f32::NAN),
-			motionBiasEstBiasLpState: Mat2x2::repeat(f32::NAN),
-			restLastSquaredDeviations: Vec2::zeros(),
-			restT: 0.0,
-			restLastGyrLp: Vec3::zeros(),
-			restGyrLpState: Mat2x3::repeat(f32::NAN),
-			restLastAccLp: Vec3::zeros(),
-			restAccLpState: Mat2x3::repeat(f32::NAN),
-			magRefNorm: 0.0,
-			magRefDip: 0.0,
-			magUndisturbedT: 0.0,
-			magRejectT: -1.0,
-			magCandidateNorm: -1.0,
-			magCandidateDip: 0.0,
-			magCandidateT: 0.0,
-			magNormDip: Vec2::zeros(),
-			magNormDipLpState: Mat2x2::repeat(f32::NAN),
+			bias_p: Mat3x3::repeat(f32::NAN),
+			motion_bias_est_rlp_state: Mat2x9::repeat(f32::NAN),
+			motion_bias_est_bias_lp_state: Mat2x2::repeat(f32::NAN),
+			rest_last_squared_deviations: Vec2::zeros(),
+			rest_t: 0.0,
+			rest_last_gyr_lp: Vec3::zeros(),
+			rest_gyr_lp_state: Mat2x3::repeat(f32::NAN),
+			rest_last_acc_lp: Vec3::zeros(),
+			rest_acc_lp_state: Mat2x3::repeat(f32::NAN),
+			mag_ref_norm: 0.0,
+			mag_ref_dip: 0.0,
+			mag_undisturbed_t: 0.0,
+			mag_reject_t: -1.0,
+			mag_candidate_norm: -1.0,
+			mag_candidate_dip: 0.0,
+			mag_candidate_t: 0.0,
+			mag_norm_dip: Vec2::zeros(),
+			mag_norm_dip_lp_state: Mat2x2::repeat(f32::NAN),
 		}
 	}
 }
 
-pub struct VQFCoefficients {
-	pub gyrTs: f32,
-	pub accTs: f32,
-	pub magTs: f32,
-	pub accLpB: Vec3,
-	pub accLpA: Vec2,
-	pub kMag: f32,
-	pub biasP0: f32,
-	pub biasV: f32,
-	pub biasMotionW: f32,
-	pub biasVerticalW: f32,
-	pub biasRestW: f32,
-	pub restGyrLpB: Vec3,
-	pub restGyrLpA: Vec2,
-	pub restAccLpB: Vec3,
-	pub restAccLpA: Vec2,
-	pub kMagRef: f32,
-	pub magNormDipLpB: Vec3,
-	pub magNormDipLpA: Vec2,
+struct VqfCoefficients {
+	pub gyr_ts: f32,
+	pub acc_ts: f32,
+	pub mag_ts: f32,
+	pub acc_lp_b: Vec3,
+	pub acc_lp_a: Vec2,
+	pub k_mag: f32,
+	pub bias_p0: f32,
+	pub bias_v: f32,
+	pub bias_motion_w: f32,
+	pub bias_vertical_w: f32,
+	pub bias_rest_w: f32,
+	pub rest_gyr_lp_b: Vec3,
+	pub rest_gyr_lp_a: Vec2,
+	pub rest_acc_lp_b: Vec3,
+	pub rest_acc_lp_a: Vec2,
+	pub k_mag_ref: f32,
+	pub mag_norm_dip_lp_b: Vec3,
+	pub mag_norm_dip_lp_a: Vec2,
+	// The thresholds of the parameters, converted to radians and squared where the
+	// update steps need them that way.
+	pub bias_clip: f32,
+	pub rest_th_gyr_squared: f32,
+	pub rest_th_acc_squared: f32,
+	pub mag_dip_th: f32,
+	pub mag_new_min_gyr_squared: f32,
 }
 
-impl Default for VQFCoefficients {
+impl Default for VqfCoefficients {
 	fn default() -> Self {
 		Self {
-			gyrTs: 0.0,
-			accTs: 0.0,
-			magTs: 0.0,
-			accLpB: Vec3::repeat(f32::NAN),
-			accLpA: Vec2::repeat(f32::NAN),
-			kMag: -1.0,
-			biasP0: -1.0,
-			biasV: -1.0,
-			biasMotionW: -1.0,
-			biasVerticalW: -1.0,
-			biasRestW: -1.0,
-			restGyrLpB: Vec3::repeat(f32::NAN),
-			restGyrLpA: Vec2::repeat(f32::NAN),
-			restAccLpB: Vec3::repeat(f32::NAN),
-			restAccLpA: Vec2::repeat(f32::NAN),
-			kMagRef: -1.0,
-			magNormDipLpB: Vec3::repeat(f32::NAN),
-			magNormDipLpA: Vec2::repeat(f32::NAN),
+			gyr_ts: 0.0,
+			acc_ts: 0.0,
+			mag_ts: 0.0,
+			acc_lp_b: Vec3::repeat(f32::NAN),
+			acc_lp_a: Vec2::repeat(f32::NAN),
+			k_mag: -1.0,
+			bias_p0: -1.0,
+			bias_v: -1.0,
+			bias_motion_w: -1.0,
+			bias_vertical_w: -1.0,
+			bias_rest_w: -1.0,
+			rest_gyr_lp_b: Vec3::repeat(f32::NAN),
+			rest_gyr_lp_a: Vec2::repeat(f32::NAN),
+			rest_acc_lp_b: Vec3::repeat(f32::NAN),
+			rest_acc_lp_a: Vec2::repeat(f32::NAN),
+			k_mag_ref: -1.0,
+			mag_norm_dip_lp_b: Vec3::repeat(f32::NAN),
+			mag_norm_dip_lp_a: Vec2::repeat(f32::NAN),
+			bias_clip: f32::NAN,
+			rest_th_gyr_squared: f32::NAN,
+			rest_th_acc_squared: f32::NAN,
+			mag_dip_th: f32::NAN,
+			mag_new_min_gyr_squared: f32::NAN,
 		}
 	}
 }
 
+/// The VQF orientation filter.
 pub struct Vqf {
-	_params: VqfParameters,
-	_state: VqfState,
-	_coeffs: VQFCoefficients,
+	params: VqfParameters,
+	state: VqfState,
+	coeffs: VqfCoefficients,
 }
 
 impl Vqf {
-	pub fn new(gyrTs: f32, accTs: f32, magTs: f32, params: VqfParameters) -> Vqf {
-		Vqf {
-			_params: params,
-			_state: Default::default(),
-			_coeffs: VQFCoefficients {
-				gyrTs,
-				accTs,
-				magTs,
+	/// Creates a filter for sensors sampled every `gyr_ts`, `acc_ts` and `mag_ts`
+	/// seconds.
+	///
+	/// # Panics
+	/// Panics if the parameters or sampling times are invalid. See [`Vqf::try_new()`].
+	pub fn new(gyr_ts: f32, acc_ts: f32, mag_ts: f32, params: VqfParameters) -> Vqf {
+		match Self::try_new(gyr_ts, acc_ts, mag_ts, params) {
+			Ok(vqf) => vqf,
+			Err(err) => panic!("Invalid VQF parameters: {err}"),
+		}
+	}
+
+	/// Like [`Vqf::new()`], but returns an error if the parameters or sampling times
+	/// are invalid.
+	pub fn try_new(
+		gyr_ts: f32,
+		acc_ts: f32,
+		mag_ts: f32,
+		params: VqfParameters,
+	) -> Result<Vqf, ParamError> {
+		params::check_positive("gyr_ts", gyr_ts)?;
+		params::check_positive("acc_ts", acc_ts)?;
+		params::check_positive("mag_ts", mag_ts)?;
+		params.validate()?;
+		let mut vqf = Vqf {
+			params,
+			state: Default::default(),
+			coeffs: VqfCoefficients {
+				gyr_ts,
+				acc_ts,
+				mag_ts,
 				..Default::default()
 			},
+		};
+		vqf.setup();
+		Ok(vqf)
+	}
+
+	pub fn params(&self) -> &VqfParameters {
+		&self.params
+	}
+
+	/// Computes the filter coefficients from the parameters and sampling times, and
+	/// resets the state.
+	fn setup(&mut self) {
+		let params = &self.params;
+		let coeffs = &mut self.coeffs;
+
+		(coeffs.acc_lp_b, coeffs.acc_lp_a) =
+			filter_coeffs(params.tau_acc, coeffs.acc_ts);
+
+		coeffs.k_mag = gain_from_tau(params.tau_mag, coeffs.mag_ts);
+
+		coeffs.bias_p0 = (params.bias_sigma_init * 100.0).powi(2);
+		// the system noise increases the variance from 0 to (0.1 °/s)^2 in bias_forgetting_time seconds
+		coeffs.bias_v =
+			(0.1 * 100.0).powi(2) * coeffs.acc_ts / params.bias_forgetting_time;
+
+		let p_motion = (params.bias_sigma_motion * 100.0).powi(2);
+		coeffs.bias_motion_w = p_motion.powi(2) / coeffs.bias_v + p_motion;
+		coeffs.bias_vertical_w =
+			coeffs.bias_motion_w / params.bias_vertical_forgetting_factor.max(1e-10);
+
+		let p_rest = (params.bias_sigma_rest * 100.0).powi(2);
+		coeffs.bias_rest_w = p_rest.powi(2) / coeffs.bias_v + p_rest;
+
+		(coeffs.rest_gyr_lp_b, coeffs.rest_gyr_lp_a) =
+			filter_coeffs(params.rest_filter_tau, coeffs.gyr_ts);
+		(coeffs.rest_acc_lp_b, coeffs.rest_acc_lp_a) =
+			filter_coeffs(params.rest_filter_tau, coeffs.acc_ts);
+
+		coeffs.k_mag_ref = gain_from_tau(params.mag_ref_tau, coeffs.mag_ts);
+		if params.mag_current_tau > 0.0 {
+			(coeffs.mag_norm_dip_lp_b, coeffs.mag_norm_dip_lp_a) =
+				filter_coeffs(params.mag_current_tau, coeffs.mag_ts);
+		} else {
+			coeffs.mag_norm_dip_lp_b = Vec3::repeat(f32::NAN);
+			coeffs.mag_norm_dip_lp_a = Vec2::repeat(f32::NAN);
 		}
+
+		coeffs.bias_clip = params.bias_clip.to_radians();
+		coeffs.rest_th_gyr_squared = params.rest_th_gyr.to_radians().powi(2);
+		coeffs.rest_th_acc_squared = params.rest_th_acc.powi(2);
+		coeffs.mag_dip_th = params.mag_dip_th.to_radians();
+		coeffs.mag_new_min_gyr_squared = params.mag_new_min_gyr.to_radians().powi(2);
+
+		self.reset_state();
+	}
+
+	/// Resets the state to the initial values, keeping the parameters and
+	/// coefficients.
+	pub fn reset_state(&mut self) {
+		self.state = VqfState {
+			bias_p: Mat3x3::identity() * self.coeffs.bias_p0,
+			// Until there is a magnetic field reference, the heading is corrected
+			// slowly instead of not at all, like in the reference implementation.
+			mag_reject_t: self.params.mag_max_rejection_time,
+			..Default::default()
+		};
 	}
 
-	pub fn updateGyr(&mut self, gyr: Vec3) {
-		if self._params.restBiasEstEnabled || self._params.magDistRejectionEnabled {
-			let gyrLp = filterVec(
+	/// Updates the orientation with a gyroscope sample, in rad/s.
+	pub fn update_gyr(&mut self, gyr: Vec3) {
+		self.gyr_step(gyr, self.coeffs.gyr_ts);
+	}
+
+	/// Like [`Vqf::update_gyr()`], but for a sample that came `dt` seconds after the
+	/// previous one. If `dt` is not positive and finite, the sample is ignored and an
+	/// error is returned.
+	pub fn update_gyr_dt(&mut self, gyr: Vec3, dt: f32) -> Result<(), ParamError> {
+		params::check_positive("dt", dt)?;
+		self.gyr_step(gyr, dt);
+		Ok(())
+	}
+
+	fn gyr_step(&mut self, gyr: Vec3, dt: f32) {
+		if self.params.rest_bias_est_enabled || self.params.mag_dist_rejection_enabled {
+			let gyr_lp = filter_vec(
 				gyr,
-				self._params.restFilterTau,
-				self._coeffs.gyrTs,
-				self._coeffs.restGyrLpB,
-				self._coeffs.restGyrLpA,
-				&mut self._state.restGyrLpState,
+				self.params.rest_filter_tau,
+				dt,
+				self.coeffs.rest_gyr_lp_b,
+				self.coeffs.rest_gyr_lp_a,
+				&mut self.state.rest_gyr_lp_state,
 			);
 
-			let deviation = gyr - gyrLp;
-			let squaredDeviation = deviation.dot(&deviation);
+			let deviation = gyr - gyr_lp;
+			let squared_deviation = deviation.dot(&deviation);
 
-			let biasClip = self._params.biasClip * PI / 180.0;
-			if squaredDeviation >= (self._params.restThGyr * PI / 180.0).powf(2.0)
-				|| gyrLp.abs().max() > biasClip
+			if squared_deviation >= self.coeffs.rest_th_gyr_squared
+				|| gyr_lp.abs().max() > self.coeffs.bias_clip
 			{
-				self._state.restT = 0.0;
-				self._state.restDetected = false;
+				self.state.rest_t = 0.0;
+				self.state.rest_detected = false;
 			}
-			self._state.restLastGyrLp = gyrLp;
-			self._state.restLastSquaredDeviations[0] = squaredDeviation;
+			self.state.rest_last_gyr_lp = gyr_lp;
+			self.state.rest_last_squared_deviations[0] = squared_deviation;
 		}
 
 		// remove estimated gyro bias
-		let gyrNoBias = gyr - self._state.bias;
+		let gyr_no_bias = gyr - self.state.bias;
 
 		// gyroscope prediction step
-		let gyrNorm = gyrNoBias.dot(&gyrNoBias).sqrt();
-		let angle = gyrNorm * self._coeffs.gyrTs;
-		if gyrNorm > EPS {
-			let c = (angle / 2.0).cos();
-			let s = (angle / 2.0).sin() / gyrNorm;
-			let gyrStepQuat = Quat::from_quaternion(
-				[c, s * gyrNoBias[0], s * gyrNoBias[1], s * gyrNoBias[2]].into(),
-			);
-			self._state.gyrQuat = self._state.gyrQuat * gyrStepQuat;
+		if gyr_no_bias.norm_squared() > EPS * EPS {
+			self.state.gyr_quat *= gyr_step_quat(gyr_no_bias, dt);
 		}
 	}
 
-	pub fn updateAcc(&mut self, acc: Vec3) {
+	/// Corrects the inclination with an accelerometer sample, in m/s². Samples that are
+	/// exactly zero are ignored.
+	pub fn update_acc(&mut self, acc: Vec3) {
+		self.acc_step(acc, self.coeffs.acc_ts);
+	}
+
+	/// Like [`Vqf::update_acc()`], but for a sample that came `dt` seconds after the
+	/// previous one. If `dt` is not positive and finite, the sample is ignored and an
+	/// error is returned.
+	pub fn update_acc_dt(&mut self, acc: Vec3, dt: f32) -> Result<(), ParamError> {
+		params::check_positive("dt", dt)?;
+		self.acc_step(acc, dt);
+		Ok(())
+	}
+
+	fn acc_step(&mut self, acc: Vec3, dt: f32) {
 		if acc == Vec3::zeros() {
 			return;
 		}
 
-		let accTs = self._coeffs.accTs;
+		let acc_ts = dt;
 
 		// Rest detection
-		if self._params.restBiasEstEnabled {
-			let accLp = filterVec(
+		if self.params.rest_bias_est_enabled {
+			let acc_lp = filter_vec(
 				acc,
-				self._params.restFilterTau,
-				accTs,
-				self._coeffs.restAccLpB,
-				self._coeffs.restAccLpA,
-				&mut self._state.restAccLpState,
+				self.params.rest_filter_tau,
+				acc_ts,
+				self.coeffs.rest_acc_lp_b,
+				self.coeffs.rest_acc_lp_a,
+				&mut self.state.rest_acc_lp_state,
 			);
 
-			let deviation = acc - accLp;
-			let squaredDeviation = deviation.dot(&deviation);
+			let deviation = acc - acc_lp;
+			let squared_deviation = deviation.dot(&deviation);
 
-			if squaredDeviation >= self._params.restThAcc.powf(2.0) {
-				self._state.restT = 0.0;
-				self._state.restDetected = false;
+			if squared_deviation >= self.coeffs.rest_th_acc_squared {
+				self.state.rest_t = 0.0;
+				self.state.rest_detected = false;
 			} else {
-				self._state.restT += accTs;
-				if self._state.restT >= self._params.restMinT {
-					self._state.restDetected = true
+				// Summing up the sampling time loses precision every sample in `f32`,
+				// which would detect rest a sample later than the reference. So at the
+				// nominal sampling time, count the samples instead.
+				self.state.rest_t = if acc_ts == self.coeffs.acc_ts {
+					((self.state.rest_t / acc_ts).round() + 1.0) * acc_ts
+				} else {
+					self.state.rest_t + acc_ts
+				};
+				if self.state.rest_t >= self.params.rest_min_t {
+					self.state.rest_detected = true
 				}
 			}
 
-			self._state.restLastAccLp = accLp;
-			self._state.restLastSquaredDeviations[1] = squaredDeviation
+			self.state.rest_last_acc_lp = acc_lp;
+			self.state.rest_last_squared_deviations[1] = squared_deviation
 		}
 
 		// filter acc in inertial frame
-		let accEarth = self._state.gyrQuat * acc;
-		self._state.lastAccLp = filterVec(
-			accEarth,
-			self._params.tauAcc,
-			accTs,
-			self._coeffs.accLpB,
-			self._coeffs.accLpA,
-			&mut self._state.accLpState,
+		let acc_earth = self.state.gyr_quat * acc;
+		self.state.last_acc_lp = filter_vec(
+			acc_earth,
+			self.params.tau_acc,
+			acc_ts,
+			self.coeffs.acc_lp_b,
+			self.coeffs.acc_lp_a,
+			&mut self.state.acc_lp_state,
 		);
 
 		// transform to 6D earth frame and normalize
-		let accEarth = self._state.accQuat * self._state.lastAccLp;
+		let acc_earth = (self.state.acc_quat * self.state.last_acc_lp).normalize();
 
 		// inclination correction
-		let q_w = ((accEarth[2] + 1.0) / 2.0).sqrt();
-		let accCorrQuat;
-		if q_w > EPS {
-			accCorrQuat = Quat::from_quaternion(
-				[q_w, 0.5 * accEarth[1] / q_w, -0.5 * accEarth[0] / q_w, 0.0].into(),
-			);
+		let q_w = ((acc_earth[2] + 1.0) / 2.0).sqrt();
+		let acc_corr_quat = if q_w > EPS {
+			Quat::from_quaternion(nalgebra::Quaternion::new(
+				q_w,
+				0.5 * acc_earth[1] / q_w,
+				-0.5 * acc_earth[0] / q_w,
+				0.0,
+			))
 		} else {
-			accCorrQuat = Quat::from_quaternion([0.0, 1.0, 0.0, 0.0].into());
-		}
-		self._state.accQuat = accCorrQuat * self._state.accQuat;
+			// to avoid numeric issues when acc is close to [0 0 -1], i.e. the correction
+			// step is close (<= 0.00011°) to 180°
+			Quat::from_quaternion(nalgebra::Quaternion::new(0.0, 1.0, 0.0, 0.0))
+		};
+		self.state.acc_quat = acc_corr_quat * self.state.acc_quat;
 
 		// calculate correction angular rate to facilitate debugging
-		// self._state.lastAccCorrAngularRate = (accEarth[2]).acos() / self._coeffs.accTs;
+		// self.state.last_acc_corr_angular_rate = (acc_earth[2]).acos() / self.coeffs.acc_ts;
 
 		// bias estimation
-		if self._params.motionBiasEstEnabled || self._params.restBiasEstEnabled {
-			let biasClip = self._params.biasClip * PI / 180.0;
-			let mut bias = self._state.bias;
-
-			// get rotation matrix corresponding to accGyrQuat
-			let accGyrQuat = self.getQuat6D();
-			// ඞ
-			let R = accGyrQuat.to_rotation_matrix().into_inner();
-
-			// calculate R*b_hat (only the x and y component, as z is not needed)
-			let biasLp = (R * bias).xy();
-
-			// low-pass filter R and R*b_hat
-			let mut R = filterVec(
-				R.reshape_generic(nalgebra::Const::<9>, nalgebra::Const::<1>),
-				self._params.tauAcc,
-				accTs,
-				self._coeffs.accLpB,
-				self._coeffs.accLpA,
-				&mut self._state.motionBiasEstRLpState,
-			)
-			.reshape_generic(nalgebra::Const::<3>, nalgebra::Const::<3>);
-			let biasLp = filterVec(
-				biasLp,
-				self._params.tauAcc,
-				accTs,
-				self._coeffs.accLpB,
-				self._coeffs.accLpA,
-				&mut self._state.motionBiasEstBiasLpState,
-			);
-
-			// set measurement error and covariance for the respective Kalman filter update
-			let e;
-			let w;
-			if self._state.restDetected && self._params.restBiasEstEnabled {
-				e = Some(self._state.restLastGyrLp - bias);
-				R = Mat3x3::identity();
-				w = Some(Vec3::repeat(self._coeffs.biasRestW));
-			} else if self._params.motionBiasEstEnabled {
-				// ඞ
-				e = Some(Vec3::new(
-					-accEarth[1] / accTs + biasLp[0]
-						- R[0] * bias[0] - R[1] * bias[1]
-						- R[2] * bias[2],
-					accEarth[0] / accTs + biasLp[1]
-						- R[3] * bias[0] - R[4] * bias[1]
-						- R[5] * bias[2],
-					-R[6] * bias[0] - R[7] * bias[1] - R[8] * bias[2],
-				));
-				R = Mat3x3::repeat(f32::NAN);
-				w = Some(Vec3::new(
-					self._coeffs.biasMotionW,
-					self._coeffs.biasMotionW,
-					self._coeffs.biasVerticalW,
-				));
-			} else {
-				w = None;
-				e = None;
-			}
+		let motion_bias_est =
+			cfg!(feature = "motion-bias-est") && self.params.motion_bias_est_enabled;
+		if motion_bias_est || self.params.rest_bias_est_enabled {
+			let bias_clip = self.coeffs.bias_clip;
+			let mut bias = self.state.bias;
+
+			// low-pass filtered R and R*b_hat, only needed while moving
+			let motion_lp = motion_bias_est.then(|| {
+				// get rotation matrix corresponding to acc_gyr_quat
+				let r = self.quat_6d().to_rotation_matrix().into_inner();
+
+				// calculate R*b_hat (only the x and y component, as z is not needed)
+				let bias_lp = (r * bias).xy();
+
+				// low-pass filter R and R*b_hat. R is flattened in row-major order, like
+				// the reference implementation.
+				let r = filter_vec(
+					SVector::<f32, 9>::from_row_slice(r.transpose().as_slice()),
+					self.params.tau_acc,
+					acc_ts,
+					self.coeffs.acc_lp_b,
+					self.coeffs.acc_lp_a,
+					&mut self.state.motion_bias_est_rlp_state,
+				);
+				let bias_lp = filter_vec(
+					bias_lp,
+					self.params.tau_acc,
+					acc_ts,
+					self.coeffs.acc_lp_b,
+					self.coeffs.acc_lp_a,
+					&mut self.state.motion_bias_est_bias_lp_state,
+				);
+				(r, bias_lp)
+			});
+
+			// set measurement matrix, error and covariance for the respective Kalman
+			// filter update
+			let measurement =
+				if self.state.rest_detected && self.params.rest_bias_est_enabled {
+					Some((
+						Mat3x3::identity(),
+						self.state.rest_last_gyr_lp - bias,
+						Vec3::repeat(self.coeffs.bias_rest_w),
+					))
+				} else if let Some((r, bias_lp)) = motion_lp {
+					let e = Vec3::new(
+						-acc_earth[1] / acc_ts + bias_lp[0]
+							- r[0] * bias[0] - r[1] * bias[1]
+							- r[2] * bias[2],
+						acc_earth[0] / acc_ts + bias_lp[1]
+							- r[3] * bias[0] - r[4] * bias[1]
+							- r[5] * bias[2],
+						-r[6] * bias[0] - r[7] * bias[1] - r[8] * bias[2],
+					);
+					let w = Vec3::new(
+						self.coeffs.bias_motion_w,
+						self.coeffs.bias_motion_w,
+						self.coeffs.bias_vertical_w,
+					);
+					Some((Mat3x3::from_row_slice(r.as_slice()), e, w))
+				} else {
+					None
+				};
 
 			// Kalman filter update
 			// step 1: P = P + V (also increase covariance if there is no measurement update!)
-			if self._state.biasP[(0, 0)] < self._coeffs.biasP0 {
-				self._state.biasP[(0, 0)] += self._coeffs.biasV;
-			}
-			if self._state.biasP[(1, 1)] < self._coeffs.biasP0 {
-				self._state.biasP[(1, 1)] += self._coeffs.biasV;
-			}
-			if self._state.biasP[(2, 2)] < self._coeffs.biasP0 {
-				self._state.biasP[(2, 2)] += self._coeffs.biasV;
+			let bias_v = self.coeffs.bias_v * dt / self.coeffs.acc_ts;
+			for i in 0..3 {
+				if self.state.bias_p[(i, i)] < self.coeffs.bias_p0 {
+					self.state.bias_p[(i, i)] += bias_v;
+				}
 			}
 
-			if let Some(w) = w {
+			if let Some((r, e, w)) = measurement {
 				// clip disagreement to -2..2 °/s
 				// (this also effectively limits the harm done by the first inclination correction step)
-				let e = e.unwrap();
-				let e = Vec3::from_fn(|x, y| e[(x, y)].clamp(-biasClip, biasClip));
+				let e = e.map(|x| x.clamp(-bias_clip, bias_clip));
 
 				// step 2: K = P R^T inv(W + R P R^T)
-				let K = self._state.biasP
-					* R.transpose() * (Mat3x3::from_diagonal(&w)
-					+ R * self._state.biasP * R.transpose())
-				.pseudo_inverse(EPS)
-				.unwrap();
+				// W + R P R^T is symmetric positive definite, so the closed form inverse
+				// exists, and is much cheaper than the pseudo inverse of the reference.
+				let s =
+					Mat3x3::from_diagonal(&w) + r * self.state.bias_p * r.transpose();
+				let s_inv = s
+					.try_inverse()
+					.unwrap_or_else(|| s.pseudo_inverse(EPS).unwrap());
+				let k = self.state.bias_p * r.transpose() * s_inv;
 
 				// step 3: bias = bias + K (y - R bias) = bias + K e
-				bias += K * e;
+				bias += k * e;
 
 				// step 4: P = P - K R P
-				self._state.biasP -= K * R * self._state.biasP;
+				self.state.bias_p -= k * r * self.state.bias_p;
 
 				// clip bias estimate to -2..2 °/s
-				bias = Vec3::from_fn(|x, y| bias[(x, y)].clamp(-biasClip, biasClip));
+				bias = bias.map(|x| x.clamp(-bias_clip, bias_clip));
 			}
 
-			// ඞ
-			self._state.bias = bias;
+			self.state.bias = bias;
 		}
 	}
 
-	pub fn updateMag(&mut self, mag: Vec3) {
+	/// Corrects the heading with a magnetometer sample, in any unit. Samples that are
+	/// exactly zero are ignored.
+	pub fn update_mag(&mut self, mag: Vec3) {
+		self.mag_step(mag, self.coeffs.mag_ts);
+	}
+
+	/// Like [`Vqf::update_mag()`], but for a sample that came `dt` seconds after the
+	/// previous one. If `dt` is not positive and finite, the sample is ignored and an
+	/// error is returned.
+	pub fn update_mag_dt(&mut self, mag: Vec3, dt: f32) -> Result<(), ParamError> {
+		params::check_positive("dt", dt)?;
+		self.mag_step(mag, dt);
+		Ok(())
+	}
+
+	fn mag_step(&mut self, mag: Vec3, dt: f32) {
 		if mag == Vec3::zeros() {
 			return;
 		}
 
-		let magTs = self._coeffs.magTs;
+		let mag_ts = dt;
 
 		// bring magnetometer measurement into 6D earth frame
-		let magEarth = self.getQuat6D() * mag;
-
-		if self._params.magDistRejectionEnabled {
-			let mut magNormDip = self._state.magNormDip;
-			magNormDip[0] = (magEarth.dot(&magEarth)).sqrt();
-			magNormDip[1] = -((magEarth[2] / magNormDip[0]).asin());
-
-			if self._params.magCurrentTau > 0.0 {
-				magNormDip = filterVec(
-					magNormDip,
-					self._params.magCurrentTau,
-					magTs,
-					self._coeffs.magNormDipLpB,
-					self._coeffs.magNormDipLpA,
-					&mut self._state.magNormDipLpState,
+		let mag_earth = self.quat_6d() * mag;
+
+		if self.params.mag_dist_rejection_enabled {
+			let mut mag_norm_dip = self.state.mag_norm_dip;
+			mag_norm_dip[0] = (mag_earth.dot(&mag_earth)).sqrt();
+			mag_norm_dip[1] = -((mag_earth[2] / mag_norm_dip[0]).asin());
+
+			if self.params.mag_current_tau > 0.0 {
+				mag_norm_dip = filter_vec(
+					mag_norm_dip,
+					self.params.mag_current_tau,
+					mag_ts,
+					self.coeffs.mag_norm_dip_lp_b,
+					self.coeffs.mag_norm_dip_lp_a,
+					&mut self.state.mag_norm_dip_lp_state,
 				);
 			}
+			self.state.mag_norm_dip = mag_norm_dip;
 
 			// magnetic disturbance detection
-			if (magNormDip[0] - self._state.magRefNorm).abs()
-				< self._params.magNormTh * self._state.magRefNorm
-				&& (magNormDip[1] - self._state.magRefDip).abs()
-					< self._params.magDipTh * PI / 180.0
+			if (mag_norm_dip[0] - self.state.mag_ref_norm).abs()
+				< self.params.mag_norm_th * self.state.mag_ref_norm
+				&& (mag_norm_dip[1] - self.state.mag_ref_dip).abs()
+					< self.coeffs.mag_dip_th
 			{
-				self._state.magUndisturbedT += magTs;
-
-				if self._state.magUndisturbedT >= self._params.magMinUndisturbedTime {
-					self._state.magDistDetected = false;
-					self._state.magRefNorm +=
-						self._coeffs.kMagRef * (magNormDip[0] - self._state.magRefNorm);
-					self._state.magRefDip +=
-						self._coeffs.kMagRef * (magNormDip[1] - self._state.magRefDip);
+				self.state.mag_undisturbed_t += mag_ts;
+
+				if self.state.mag_undisturbed_t >= self.params.mag_min_undisturbed_time
+				{
+					self.state.mag_dist_detected = false;
+					self.state.mag_ref_norm += self.coeffs.k_mag_ref
+						* (mag_norm_dip[0] - self.state.mag_ref_norm);
+					self.state.mag_ref_dip += self.coeffs.k_mag_ref
+						* (mag_norm_dip[1] - self.state.mag_ref_dip);
 				}
 			} else {
-				self._state.magUndisturbedT = 0.0;
-				self._state.magDistDetected = true;
+				self.state.mag_undisturbed_t = 0.0;
+				self.state.mag_dist_detected = true;
 			}
 
 			// new magnetic field acceptance
-			if (magNormDip[0] - self._state.magCandidateNorm).abs()
-				< self._params.magNormTh * self._state.magCandidateNorm
-				&& (magNormDip[1] - self._state.magCandidateDip).abs()
-					< self._params.magDipTh * PI / 180.0
+			if (mag_norm_dip[0] - self.state.mag_candidate_norm).abs()
+				< self.params.mag_norm_th * self.state.mag_candidate_norm
+				&& (mag_norm_dip[1] - self.state.mag_candidate_dip).abs()
+					< self.coeffs.mag_dip_th
 			{
-				let gyrNorm =
-					(self._state.restLastGyrLp.dot(&self._state.restLastGyrLp)).sqrt();
-				if gyrNorm >= self._params.magNewMinGyr * PI / 180.0 {
-					self._state.magCandidateT += magTs;
+				if self.state.rest_last_gyr_lp.norm_squared()
+					>= self.coeffs.mag_new_min_gyr_squared
+				{
+					self.state.mag_candidate_t += mag_ts;
 				}
 
-				self._state.magCandidateNorm += self._coeffs.kMagRef
-					* (magNormDip[0] - self._state.magCandidateNorm);
-				self._state.magCandidateDip += self._coeffs.kMagRef
-					* (magNormDip[1] - self._state.magCandidateDip);
+				self.state.mag_candidate_norm += self.coeffs.k_mag_ref
+					* (mag_norm_dip[0] - self.state.mag_candidate_norm);
+				self.state.mag_candidate_dip += self.coeffs.k_mag_ref
+					* (mag_norm_dip[1] - self.state.mag_candidate_dip);
 
-				if self._state.magDistDetected
-					&& (self._state.magCandidateT >= self._params.magNewTime
-						|| (self._state.magRefNorm == 0.0
-							&& self._state.magCandidateT
-								>= self._params.magNewFirstTime))
+				if self.state.mag_dist_detected
+					&& (self.state.mag_candidate_t >= self.params.mag_new_time
+						|| (self.state.mag_ref_norm == 0.0
+							&& self.state.mag_candidate_t
+								>= self.params.mag_new_first_time))
 				{
-					self._state.magRefNorm = self._state.magCandidateNorm;
-					self._state.magRefDip = self._state.magCandidateDip;
-					self._state.magDistDetected = false;
-					self._state.magUndisturbedT = self._params.magMinUndisturbedTime;
+					self.state.mag_ref_norm = self.state.mag_candidate_norm;
+					self.state.mag_ref_dip = self.state.mag_candidate_dip;
+					self.state.mag_dist_detected = false;
+					self.state.mag_undisturbed_t = self.params.mag_min_undisturbed_time;
 				}
 			} else {
-				self._state.magCandidateT = 0.0;
-				self._state.magCandidateNorm = magNormDip[0];
-				self._state.magCandidateDip = magNormDip[1];
+				self.state.mag_candidate_t = 0.0;
+				self.state.mag_candidate_norm = mag_norm_dip[0];
+				self.state.mag_candidate_dip = mag_norm_dip[1];
 			}
 		}
 
 		// calculate disagreement angle based on current magnetometer measurement
-		self._state.lastMagDisAngle =
-			magEarth[0].atan2(magEarth[1]) - self._state.delta;
+		self.state.last_mag_dis_angle =
+			mag_earth[0].atan2(mag_earth[1]) - self.state.delta;
 
 		// make sure the disagreement angle is in the range [-pi, pi]
-		if self._state.lastMagDisAngle > PI {
-			self._state.lastMagDisAngle -= 2.0 * PI;
-		} else if self._state.lastMagDisAngle < -PI {
-			self._state.lastMagDisAngle += 2.0 * PI;
+		if self.state.last_mag_dis_angle > PI {
+			self.state.last_mag_dis_angle -= 2.0 * PI;
+		} else if self.state.last_mag_dis_angle < -PI {
+			self.state.last_mag_dis_angle += 2.0 * PI;
 		}
 
-		let mut k = self._coeffs.kMag;
+		let mut k = self.coeffs.k_mag;
 
-		if self._params.magDistRejectionEnabled {
+		if self.params.mag_dist_rejection_enabled {
 			// magnetic disturbance rejection
-			if self._state.magDistDetected {
-				if self._state.magRejectT <= self._params.magMaxRejectionTime {
-					self._state.magRejectT += magTs;
+			if self.state.mag_dist_detected {
+				if self.state.mag_reject_t <= self.params.mag_max_rejection_time {
+					self.state.mag_reject_t += mag_ts;
 					k = 0.0;
 				} else {
-					k /= self._params.magRejectionFactor;
+					k /= self.params.mag_rejection_factor;
 				}
 			} else {
-				self._state.magRejectT = (self._state.magRejectT
-					- self._params.magRejectionFactor * magTs)
+				self.state.mag_reject_t = (self.state.mag_reject_t
+					- self.params.mag_rejection_factor * mag_ts)
 					.max(0.0);
 			}
 		}
 
 		// ensure fast initial convergence
-		if self._state.kMagInit != 0.0 {
+		if self.state.k_mag_init != 0.0 {
 			// make sure that the gain k is at least 1/N, N=1,2,3,... in the first few samples
-			if k < self._state.kMagInit {
-				k = self._state.kMagInit;
+			if k < self.state.k_mag_init {
+				k = self.state.k_mag_init;
 			}
 
 			// iterative expression to calculate 1/N
-			self._state.kMagInit = self._state.kMagInit / (self._state.kMagInit + 1.0);
+			self.state.k_mag_init =
+				self.state.k_mag_init / (self.state.k_mag_init + 1.0);
 
-			// disable if t > tauMag
-			if self._state.kMagInit * self._params.tauMag < self._coeffs.magTs {
-				self._state.kMagInit = 0.0;
+			// disable if t > tau_mag
+			if self.state.k_mag_init * self.params.tau_mag < mag_ts {
+				self.state.k_mag_init = 0.0;
 			}
 		}
 
 		// first-order filter step
-		self._state.delta += k * self._state.lastMagDisAngle;
+		self.state.delta += k * self.state.last_mag_dis_angle;
 		// calculate correction angular rate to facilitate debugging
-		self._state.lastMagCorrAngularRate =
-			k * self._state.lastMagDisAngle / self._coeffs.magTs;
+		self.state.last_mag_corr_angular_rate =
+			k * self.state.last_mag_dis_angle / mag_ts;
 
 		// make sure delta is in the range [-pi, pi]
-		if self._state.delta > PI {
-			self._state.delta -= 2.0 * PI;
-		} else if self._state.delta < -PI {
-			self._state.delta += 2.0 * PI;
+		if self.state.delta > PI {
+			self.state.delta -= 2.0 * PI;
+		} else if self.state.delta < -PI {
+			self.state.delta += 2.0 * PI;
 		}
 	}
 
+	/// Updates the filter with one sample of every sensor, sampled at the rates given
+	/// to [`Vqf::new()`].
 	pub fn update(&mut self, gyr: Vec3, acc: Vec3, mag: Option<Vec3>) {
-		self.updateGyr(gyr);
-		self.updateAcc(acc);
+		self.update_gyr(gyr);
+		self.update_acc(acc);
+		if let Some(mag) = mag {
+			self.update_mag(mag);
+		}
+	}
+
+	/// Like [`Vqf::update()`], but for samples that came `dt` seconds after the
+	/// previous ones.
+	///
+	/// The low-pass filters keep the coefficients of the sampling times given to
+	/// [`Vqf::new()`], so `dt` should stay close to those. This suits IMUs whose samples
+	/// jitter around a nominal rate. If `dt` is not positive and finite, the samples
+	/// are ignored and an error is returned.
+	pub fn update_dt(
+		&mut self,
+		gyr: Vec3,
+		acc: Vec3,
+		mag: Option<Vec3>,
+		dt: f32,
+	) -> Result<(), ParamError> {
+		params::check_positive("dt", dt)?;
+		self.gyr_step(gyr, dt);
+		self.acc_step(acc, dt);
 		if let Some(mag) = mag {
-			self.updateMag(mag);
+			self.mag_step(mag, dt);
 		}
+		Ok(())
 	}
 
-	pub fn getQuat3D(&self) -> Quat {
-		self._state.gyrQuat
+	/// The orientation from only integrating the gyroscope.
+	pub fn quat_3d(&self) -> Quat {
+		self.state.gyr_quat
 	}
 
-	pub fn getQuat6D(&self) -> Quat {
-		self._state.accQuat * self.getQuat3D()
+	/// The 6D orientation, with the inclination corrected by the accelerometer. Its
+	/// heading drifts.
+	pub fn quat_6d(&self) -> Quat {
+		self.state.acc_quat * self.quat_3d()
 	}
+
+	/// The 9D orientation, with the heading corrected by the magnetometer.
+	pub fn quat_9d(&self) -> Quat {
+		Quat::from_axis_angle(&Vec3::z_axis(), self.state.delta) * self.quat_6d()
+	}
+
+	/// The heading offset between the 6D and the 9D earth frame, in radians.
+	pub fn delta(&self) -> f32 {
+		self.state.delta
+	}
+
+	/// The estimated gyroscope bias in rad/s, and an upper bound of its standard
+	/// deviation in rad/s.
+	pub fn bias_estimate(&self) -> (Vec3, f32) {
+		let sigma = bias_sigma(&self.state.bias_p, self.coeffs.bias_p0);
+		(self.state.bias, sigma)
+	}
+
+	/// Sets the gyroscope bias estimate in rad/s. If `sigma` is positive, it also sets
+	/// the standard deviation of the estimate in rad/s.
+	pub fn set_bias_estimate(&mut self, bias: Vec3, sigma: f32) {
+		self.state.bias = bias;
+		if sigma > 0.0 {
+			let p = (sigma * (180.0 * 100.0 / PI)).powi(2);
+			self.state.bias_p = Mat3x3::identity() * p;
+		}
+	}
+
+	/// Whether the sensor is currently at rest.
+	pub fn rest_detected(&self) -> bool {
+		self.state.rest_detected
+	}
+
+	/// Whether the magnetic field is currently considered disturbed.
+	pub fn mag_dist_detected(&self) -> bool {
+		self.state.mag_dist_detected
+	}
+
+	/// The gyroscope and accelerometer deviations used by rest detection, relative to
+	/// their thresholds. Rest is detected when both are below 1.
+	pub fn relative_rest_deviations(&self) -> Vec2 {
+		let deviations = self.state.rest_last_squared_deviations;
+		Vec2::new(
+			deviations[0].sqrt() / (self.params.rest_th_gyr * PI / 180.0),
+			deviations[1].sqrt() / self.params.rest_th_acc,
+		)
+	}
+
+	/// The norm of the reference magnetic field.
+	pub fn mag_ref_norm(&self) -> f32 {
+		self.state.mag_ref_norm
+	}
+
+	/// The dip angle of the reference magnetic field, in radians.
+	pub fn mag_ref_dip(&self) -> f32 {
+		self.state.mag_ref_dip
+	}
+
+	/// Sets the reference magnetic field, used by magnetic disturbance detection.
+	pub fn set_mag_ref(&mut self, norm: f32, dip: f32) {
+		self.state.mag_ref_norm = norm;
+		self.state.mag_ref_dip = dip;
+	}
+}
+
+/// Converts the bias covariance `p` to an upper bound of the standard deviation in rad/s.
+fn bias_sigma(p: &Mat3x3, bias_p0: f32) -> f32 {
+	// use largest absolute row sum as upper bound estimate for largest eigenvalue
+	// (Gershgorin circle theorem) and clip output to bias_sigma_init
+	let p = p.abs().column_sum().max().min(bias_p0);
+	p.sqrt() * PI / 100.0 / 180.0
+}
+
+/// The rotation by the angular velocity `gyr` over `dt` seconds.
+fn gyr_step_quat(gyr: Vec3, dt: f32) -> Quat {
+	let half_dt = dt / 2.0;
+	let x2 = gyr.norm_squared() * half_dt * half_dt;
+	// the cosine and sinc of the half angle. The hot path avoids trigonometry and
+	// square roots, which are slow without an FPU.
+	let (c, sinc) = if x2 < SMALL_HALF_ANGLE * SMALL_HALF_ANGLE {
+		(
+			1.0 - x2 / 2.0 + x2 * x2 / 24.0,
+			1.0 - x2 / 6.0 + x2 * x2 / 120.0,
+		)
+	} else {
+		let x = x2.sqrt();
+		(x.cos(), x.sin() / x)
+	};
+	let v = gyr * (sinc * half_dt);
+	Quat::from_quaternion(nalgebra::Quaternion::new(c, v[0], v[1], v[2]))
 }
 
-fn filterVec<const N: usize, const M: usize>(
-	x: nalgebra::SVector<f32, N>,
+/// Calculates the gain of a first-order low-pass filter with time constant `tau` and
+/// sampling time `ts`.
+fn gain_from_tau(tau: f32, ts: f32) -> f32 {
+	assert!(ts > 0.0);
+	if tau < 0.0 {
+		0.0 // k=0 for negative tau (disable update)
+	} else if tau == 0.0 {
+		1.0 // k=1 for tau=0
+	} else {
+		1.0 - (-ts / tau).exp() // fc = 1/(2*pi*tau)
+	}
+}
+
+/// Calculates the coefficients of a second-order Butterworth low-pass filter with time
+/// constant `tau` and sampling time `ts`. Returns `(b, a)`, where `a0` is 1 and omitted.
+fn filter_coeffs(tau: f32, ts: f32) -> (Vec3, Vec2) {
+	assert!(tau > 0.0);
+	assert!(ts > 0.0);
+	// second order Butterworth filter based on https://stackoverflow.com/a/52764064
+	// time constant of dampened, non-oscillating part of step response
+	let fc = (SQRT_2 / (2.0 * PI)) / tau;
+	let c = (PI * fc * ts).tan();
+	let d = c.powi(2) + SQRT_2 * c + 1.0;
+	let b0 = c.powi(2) / d;
+	let b1 = 2.0 * b0;
+	let b2 = b0;
+	// a0 = 1.0
+	let a1 = 2.0 * (c.powi(2) - 1.0) / d;
+	let a2 = (1.0 - SQRT_2 * c + c.powi(2)) / d;
+	(Vec3::new(b0, b1, b2), Vec2::new(a1, a2))
+}
+
+/// Applies the filter with coefficients `b` and `a` to each element of `x`. Each column
+/// of `state` holds the state of the filter for the corresponding element.
+fn filter_vec<const N: usize>(
+	x: SVector<f32, N>,
 	tau: f32,
-	Ts: f32,
+	ts: f32,
 	b: Vec3,
 	a: Vec2,
-	state: &mut nalgebra::Matrix<
-		f32,
-		nalgebra::Const<M>,
-		nalgebra::Const<N>,
-		ArrayStorage<f32, M, N>,
-	>,
-) -> nalgebra::Matrix<
-	f32,
-	nalgebra::Const<N>,
-	nalgebra::Const<1>,
-	ArrayStorage<f32, N, 1>,
-> {
-	assert!(M >= N);
+	state: &mut SMatrix<f32, 2, N>,
+) -> SVector<f32, N> {
 	// to avoid depending on a single sample, average the first samples (for duration tau)
 	// and then use this average to calculate the filter initial state
 	if state[(0, 0)].is_nan() {
@@ -606,63 +885,243 @@ fn filterVec<const N: usize, const M: usize>(
 		if state[(0, 1)].is_nan() {
 			// first sample
 			state[(0, 1)] = 0.0; // state[0, 1] is used to store the sample count
-					 // ඞ
-			state.get_mut((1, ..)).unwrap().fill(0.0); // state[1, :] is used to store the sum
+			state.row_mut(1).fill(0.0); // state[1, :] is used to store the sum
 		}
 
 		state[(0, 1)] += 1.0;
-		let mut out = nalgebra::SMatrix::zeros();
-		// ඞ
+		let mut out = SVector::zeros();
 		for (i, x) in x.iter().enumerate() {
 			state[(1, i)] += *x;
 			out[i] = state[(1, i)] / state[(0, 1)];
 		}
 
-		if state[(0, 1)] * Ts >= tau {
+		if state[(0, 1)] * ts >= tau {
 			for i in 0..N {
-				let init = filterInitialState(out[i], b, a);
-				// ඞ
-				for j in 0..M {
-					state[(j, i)] = init[j];
-				}
+				let init = filter_initial_state(out[i], b, a);
+				state.set_column(i, &init);
 			}
 		}
 		return out;
 	}
 
-	filterStep(x, b, a, state)
+	filter_step(x, b, a, state)
 }
 
-fn filterInitialState(x0: f32, b: Vec3, a: Vec2) -> Vec2 {
+fn filter_initial_state(x0: f32, b: Vec3, a: Vec2) -> Vec2 {
 	Vec2::new(x0 * (1.0 - b[0]), x0 * (b[2] - a[1]))
 }
 
-fn filterStep<const N: usize, const M: usize>(
-	x: nalgebra::SVector<f32, N>,
+fn filter_step<const N: usize>(
+	x: SVector<f32, N>,
 	b: Vec3,
 	a: Vec2,
-	state: &mut nalgebra::Matrix<
-		f32,
-		nalgebra::Const<M>,
-		nalgebra::Const<N>,
-		ArrayStorage<f32, M, N>,
-	>,
-) -> nalgebra::Matrix<
-	f32,
-	nalgebra::Const<N>,
-	nalgebra::Const<1>,
-	ArrayStorage<f32, N, 1>,
-> {
+	state: &mut SMatrix<f32, 2, N>,
+) -> SVector<f32, N> {
 	// difference equations based on scipy.signal.lfilter documentation
 	// assumes that a0 == 1.0
-	let y = b[0] * x + nalgebra::SMatrix::repeat(state[0]);
-	// ඞ
+	let y = b[0] * x + state.row(0).transpose();
 	for i in 0..N {
 		state[(0, i)] = b[1] * x[i] - a[0] * y[i] + state[(1, i)];
-	}
-	// ඞ
-	for i in 0..N {
 		state[(1, i)] = b[2] * x[i] - a[1] * y[i];
 	}
-	return y;
+	y
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_coefficients() {
+		let vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		let coeffs = &vqf.coeffs;
+		// The low-pass filter has unity gain at DC.
+		let (b, a) = filter_coeffs(0.05, 0.01);
+		assert!((b.sum() / (1.0 + a.sum()) - 1.0).abs() < 1e-4);
+		assert!((coeffs.k_mag - (1.0 - (-0.01f32 / 9.0).exp())).abs() < 1e-7);
+		assert_eq!(coeffs.bias_p0, 2500.0);
+		assert_eq!(vqf.state.bias_p, Mat3x3::identity() * 2500.0);
+		assert_eq!(gain_from_tau(-1.0, 0.01), 0.0);
+		assert_eq!(gain_from_tau(0.0, 0.01), 1.0);
+	}
+
+	#[test]
+	fn test_inclination() {
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		// A sensor lying still, rolled 30° around its x axis.
+		let tilt = Quat::from_axis_angle(&Vec3::x_axis(), PI / 6.0);
+		let acc = tilt.inverse() * Vec3::new(0.0, 0.0, 9.81);
+		for _ in 0..2000 {
+			vqf.update(Vec3::zeros(), acc, None);
+		}
+		let quat = vqf.quat_6d();
+		assert!((quat * acc).normalize().dot(&Vec3::z()) > 1.0 - 1e-5);
+		assert!(quat.angle_to(&tilt) < 1e-2);
+	}
+
+	#[test]
+	fn test_heading() {
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		// A sensor lying flat, turned 60° away from north (+y), in a field that dips
+		// down.
+		let heading = Quat::from_axis_angle(&Vec3::z_axis(), PI / 3.0);
+		let acc = Vec3::new(0.0, 0.0, 9.81);
+		let mag = heading.inverse() * Vec3::new(0.0, 20.0, -40.0);
+		let (norm, dip) = (20f32.hypot(40.0), (2.0 / 5f32.sqrt()).asin());
+		// A new field is only accepted while moving, so start with it as the reference.
+		vqf.set_mag_ref(norm, dip);
+		for _ in 0..2000 {
+			vqf.update(Vec3::zeros(), acc, Some(mag));
+		}
+		assert!(vqf.quat_6d().angle_to(&Quat::identity()) < 1e-4);
+		assert!(vqf.quat_9d().angle_to(&heading) < 1e-3);
+		assert!((vqf.delta() - PI / 3.0).abs() < 1e-3);
+		assert!(!vqf.mag_dist_detected());
+		assert!((vqf.mag_ref_norm() - norm).abs() < 1e-2);
+		assert!((vqf.mag_ref_dip() - dip).abs() < 1e-3);
+	}
+
+	#[test]
+	fn test_rest_and_bias() {
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		let (bias, sigma) = vqf.bias_estimate();
+		assert_eq!(bias, Vec3::zeros());
+		assert!((sigma - 0.5 * PI / 180.0).abs() < 1e-6);
+
+		// Lying still, with a constant gyroscope bias.
+		let gyr = Vec3::new(0.01, -0.02, 0.005);
+		let acc = Vec3::new(0.0, 0.0, 9.81);
+		for _ in 0..100 {
+			vqf.update(gyr, acc, None);
+		}
+		assert!(!vqf.rest_detected());
+		for _ in 0..1900 {
+			vqf.update(gyr, acc, None);
+		}
+		assert!(vqf.rest_detected());
+		assert!(vqf.relative_rest_deviations().max() < 1.0);
+		let (bias, new_sigma) = vqf.bias_estimate();
+		assert!((bias - gyr).norm() < 1e-3);
+		assert!(new_sigma < sigma);
+
+		vqf.set_bias_estimate(Vec3::zeros(), 0.001);
+		let (bias, sigma) = vqf.bias_estimate();
+		assert_eq!(bias, Vec3::zeros());
+		assert!((sigma - 0.001).abs() < 1e-6);
+
+		vqf.reset_state();
+		assert!(!vqf.rest_detected());
+		assert_eq!(vqf.quat_6d(), Quat::identity());
+	}
+
+	#[test]
+	fn test_rest_min_t() {
+		// Lying perfectly still, rest is detected on the 150th sample, after exactly
+		// `rest_min_t`, as in the reference.
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		let samples = (0..).find(|_| {
+			vqf.update(Vec3::zeros(), Vec3::new(0.0, 0.0, 9.81), None);
+			vqf.rest_detected()
+		});
+		assert_eq!(samples, Some(149));
+	}
+
+	#[test]
+	fn test_gyr_step_quat() {
+		// Both below and above SMALL_HALF_ANGLE.
+		for rate in [0.01, 1.0, 10.0, 29.0, 31.0, 100.0] {
+			let gyr = Vec3::new(0.6, -0.48, 0.64) * rate;
+			let expected = Quat::from_scaled_axis(gyr * 0.01);
+			let q = gyr_step_quat(gyr, 0.01);
+			assert!(q.angle_to(&expected) < 1e-6, "{rate} rad/s");
+			assert!((q.quaternion().norm() - 1.0).abs() < 1e-6);
+		}
+	}
+
+	#[test]
+	fn test_without_motion_bias() {
+		let params = VqfParameters {
+			motion_bias_est_enabled: false,
+			..Default::default()
+		};
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, params);
+		let gyr = Vec3::new(0.01, -0.02, 0.005);
+		for _ in 0..2000 {
+			vqf.update(gyr, Vec3::new(0.0, 0.0, 9.81), None);
+		}
+		// The motion bias filters are skipped, but the bias is still estimated at rest.
+		assert!(vqf.state.motion_bias_est_rlp_state[(0, 0)].is_nan());
+		assert!(vqf.rest_detected());
+		assert!((vqf.bias_estimate().0 - gyr).norm() < 1e-3);
+	}
+
+	#[test]
+	fn test_try_new() {
+		assert!(Vqf::try_new(0.01, 0.01, 0.01, VqfParameters::default()).is_ok());
+		assert_eq!(
+			Vqf::try_new(0.01, 0.0, 0.01, VqfParameters::default()).err(),
+			Some(ParamError::NotPositive {
+				name: "acc_ts",
+				value: 0.0
+			})
+		);
+		let params = VqfParameters {
+			tau_acc: -1.0,
+			..Default::default()
+		};
+		assert!(Vqf::try_new(0.01, 0.01, 0.01, params).is_err());
+	}
+
+	#[test]
+	fn test_update_dt() {
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		// Turning at 90°/s around the vertical axis for one second, with samples that
+		// are 5 or 15 ms apart.
+		let acc = Vec3::new(0.0, 0.0, 9.81);
+		let gyr = Vec3::new(0.0, 0.0, PI / 2.0);
+		for i in 0..100 {
+			let dt = if i % 2 == 0 { 0.005 } else { 0.015 };
+			vqf.update_dt(gyr, acc, None, dt).unwrap();
+		}
+		let expected = Quat::from_axis_angle(&Vec3::z_axis(), PI / 2.0);
+		assert!(vqf.quat_6d().angle_to(&expected) < 1e-4);
+
+		// With the nominal sampling time, it is the same as a regular update.
+		let mut a = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		let mut b = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		for _ in 0..100 {
+			a.update(gyr, acc, Some(Vec3::new(0.0, 20.0, -40.0)));
+			b.update_dt(gyr, acc, Some(Vec3::new(0.0, 20.0, -40.0)), 0.01)
+				.unwrap();
+		}
+		assert_eq!(a.quat_9d(), b.quat_9d());
+		assert_eq!(a.bias_estimate(), b.bias_estimate());
+
+		// Samples without a valid time step are ignored.
+		let rest_t = a.state().rest_t;
+		for dt in [0.0, -0.01, f32::NAN, f32::INFINITY] {
+			assert!(a.update_dt(gyr, acc, None, dt).is_err());
+			assert!(a.update_gyr_dt(gyr, dt).is_err());
+			assert!(a.update_acc_dt(acc, dt).is_err());
+			assert!(a.update_mag_dt(Vec3::new(0.0, 20.0, -40.0), dt).is_err());
+		}
+		assert_eq!(a.quat_9d(), b.quat_9d());
+		assert_eq!(a.state().rest_t, rest_t);
+	}
+
+	#[test]
+	fn test_gyr_integration() {
+		let params = VqfParameters {
+			motion_bias_est_enabled: false,
+			rest_bias_est_enabled: false,
+			..Default::default()
+		};
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, params);
+		// Turning at 90°/s around the vertical axis for one second.
+		for _ in 0..100 {
+			vqf.update_gyr(Vec3::new(0.0, 0.0, PI / 2.0));
+		}
+		let expected = Quat::from_axis_angle(&Vec3::z_axis(), PI / 2.0);
+		assert!(vqf.quat_3d().angle_to(&expected) < 1e-4);
+	}
 }
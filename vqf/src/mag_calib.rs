@@ -0,0 +1,373 @@
+//! Hard and soft iron calibration of the magnetometer.
+//!
+//! [`Vqf::update_mag()`](crate::Vqf::update_mag) expects a calibrated magnetometer,
+//! where the field has the same strength in every direction. Iron near the sensor adds
+//! a constant offset to the field (hard iron), and distorts the sphere of measurements
+//! into an ellipsoid (soft iron). To calibrate, turn the sensor around in every
+//! direction, while feeding the samples to a [`MagCalibrator`]. It fits an ellipsoid to
+//! them, and returns a [`MagCalibration`] that maps the ellipsoid back onto a sphere:
+//!
+//! ```
+//! # use vqf::{MagCalibrator, Vqf, VqfParameters};
+//! # use vqf::nalgebra::Vector3;
+//! # let samples: [(Vector3<f32>, Vector3<f32>, Vector3<f32>); 0] = [];
+//! let mut calibrator = MagCalibrator::<128>::new();
+//! for (gyr, _acc, mag) in samples {
+//!     calibrator.add_sample(mag, gyr);
+//! }
+//! if let Ok(fit) = calibrator.fit() {
+//!     if fit.quality.rms_error < 0.02 && fit.quality.coverage == 1.0 {
+//!         let mut vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+//!         for (gyr, acc, mag) in samples {
+//!             vqf.update(gyr, acc, Some(fit.calibration.apply(mag)));
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Nothing here allocates: the samples are kept in a fixed-size buffer.
+
+use crate::{Mat3x3, Vec3, EPS};
+
+use core::f32::consts::PI;
+use core::fmt;
+use nalgebra::{SMatrix, SVector};
+use num_traits::Float;
+
+/// Samples are only collected while the sensor turns at least this fast, in rad/s.
+const MIN_GYR: f32 = 20.0 * PI / 180.0;
+/// Samples are only collected if they differ from the previous one by at least this
+/// fraction of its norm, so that lingering in one direction doesn't dominate the fit.
+const MIN_SPACING: f32 = 0.1;
+
+/// Maps raw magnetometer samples onto a sphere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MagCalibration {
+	/// The hard iron offset, in the units of the magnetometer.
+	pub offset: Vec3,
+	/// The soft iron correction, applied after removing the offset. It keeps the
+	/// average field strength.
+	pub soft_iron: Mat3x3,
+}
+impl MagCalibration {
+	/// The calibration that leaves samples unchanged.
+	pub fn identity() -> Self {
+		Self {
+			offset: Vec3::zeros(),
+			soft_iron: Mat3x3::identity(),
+		}
+	}
+
+	/// Corrects a raw sample, before passing it to
+	/// [`Vqf::update_mag()`](crate::Vqf::update_mag).
+	pub fn apply(&self, mag: Vec3) -> Vec3 {
+		self.soft_iron * (mag - self.offset)
+	}
+}
+impl Default for MagCalibration {
+	fn default() -> Self {
+		Self::identity()
+	}
+}
+
+/// How well a [`MagCalibration`] fits the samples it was made from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FitQuality {
+	/// The root mean square deviation of the corrected field strength from
+	/// [`FitQuality::field_strength`], as a fraction of it. Below 0.02 is good.
+	pub rms_error: f32,
+	/// The fraction of the 8 octants around the sensor that the corrected samples point
+	/// into. Below 1, the fit is extrapolated and may be off.
+	pub coverage: f32,
+	/// The strength of the corrected field, in the units of the magnetometer.
+	pub field_strength: f32,
+	/// The number of samples in the fit.
+	pub samples: usize,
+}
+
+/// The result of [`MagCalibrator::fit()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MagFit {
+	pub calibration: MagCalibration,
+	pub quality: FitQuality,
+}
+
+/// An error when fitting a calibration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitError {
+	/// There are too few samples.
+	TooFewSamples { needed: usize, actual: usize },
+	/// The samples don't lie on an ellipsoid, for example because the sensor was only
+	/// turned around one axis.
+	NotAnEllipsoid,
+}
+impl fmt::Display for FitError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FitError::TooFewSamples { needed, actual } => write!(
+				f,
+				"Only {actual} magnetometer samples, but {needed} are needed"
+			),
+			FitError::NotAnEllipsoid => write!(
+				f,
+				"The magnetometer samples don't lie on an ellipsoid, turn the sensor \
+				around every axis"
+			),
+		}
+	}
+}
+
+/// Collects up to `N` magnetometer samples while the sensor moves, and fits a
+/// [`MagCalibration`] to them. Once full, new samples replace the oldest ones.
+#[derive(Debug, Clone)]
+pub struct MagCalibrator<const N: usize> {
+	samples: [Vec3; N],
+	len: usize,
+	/// Where the next sample goes.
+	next: usize,
+}
+impl<const N: usize> MagCalibrator<N> {
+	/// The fewest samples that [`MagCalibrator::fit()`] needs. An ellipsoid has 9
+	/// degrees of freedom, the rest average out the noise.
+	pub const MIN_SAMPLES: usize = 20;
+
+	pub fn new() -> Self {
+		Self {
+			samples: [Vec3::zeros(); N],
+			len: 0,
+			next: 0,
+		}
+	}
+
+	/// The collected samples, in no particular order.
+	pub fn samples(&self) -> &[Vec3] {
+		&self.samples[..self.len]
+	}
+
+	/// Forgets all samples.
+	pub fn clear(&mut self) {
+		self.len = 0;
+		self.next = 0;
+	}
+
+	/// Offers a raw magnetometer sample, along with the gyroscope sample in rad/s from
+	/// the same time. Returns whether the sample was collected: it isn't while the
+	/// sensor turns too slowly, or if it is too close to the previous sample.
+	pub fn add_sample(&mut self, mag: Vec3, gyr: Vec3) -> bool {
+		if N == 0 || gyr.norm_squared() < MIN_GYR * MIN_GYR || mag == Vec3::zeros() {
+			return false;
+		}
+		if self.len > 0 {
+			let last = self.samples[(self.next + N - 1) % N];
+			if (mag - last).norm_squared()
+				< MIN_SPACING * MIN_SPACING * last.norm_squared()
+			{
+				return false;
+			}
+		}
+		self.samples[self.next] = mag;
+		self.next = (self.next + 1) % N;
+		self.len = (self.len + 1).min(N);
+		true
+	}
+
+	/// Fits an ellipsoid to the samples, and returns the calibration that turns it into
+	/// a sphere.
+	pub fn fit(&self) -> Result<MagFit, FitError> {
+		let samples = self.samples();
+		if samples.len() < Self::MIN_SAMPLES {
+			return Err(FitError::TooFewSamples {
+				needed: Self::MIN_SAMPLES,
+				actual: samples.len(),
+			});
+		}
+
+		// center and scale the samples, for numerical stability in f32
+		let n = samples.len() as f32;
+		let mean = samples.iter().sum::<Vec3>() / n;
+		let scale = samples.iter().map(|m| (m - mean).norm()).sum::<f32>() / n;
+		if scale < EPS {
+			return Err(FitError::NotAnEllipsoid);
+		}
+
+		// least squares fit of the ellipsoid
+		// a x² + b y² + c z² + 2d xy + 2e xz + 2f yz + 2g x + 2h y + 2i z = 1
+		let mut dtd = SMatrix::<f32, 9, 9>::zeros();
+		let mut dt1 = SVector::<f32, 9>::zeros();
+		for m in samples {
+			let [x, y, z] = ((m - mean) / scale).into();
+			let d = SVector::<f32, 9>::from([
+				x * x,
+				y * y,
+				z * z,
+				2.0 * x * y,
+				2.0 * x * z,
+				2.0 * y * z,
+				2.0 * x,
+				2.0 * y,
+				2.0 * z,
+			]);
+			dtd += d * d.transpose();
+			dt1 += d;
+		}
+		let v = dtd.cholesky().ok_or(FitError::NotAnEllipsoid)?.solve(&dt1);
+		let a = Mat3x3::new(v[0], v[3], v[4], v[3], v[1], v[5], v[4], v[5], v[2]);
+		let center =
+			-a.try_inverse().ok_or(FitError::NotAnEllipsoid)? * v.fixed_rows::<3>(6);
+
+		// (p - center)^T m (p - center) = 1 for the scaled samples p
+		let m = a / (1.0 + center.dot(&(a * center)));
+		let eigen = m.symmetric_eigen();
+		if eigen.eigenvalues.min() <= 0.0 {
+			return Err(FitError::NotAnEllipsoid);
+		}
+		// scaling the sphere to the radius of the ellipsoid's volume keeps the average
+		// field strength
+		let radius = Float::powf(eigen.eigenvalues.product(), -1.0 / 6.0);
+		let sqrt_m = eigen.eigenvectors
+			* Mat3x3::from_diagonal(&eigen.eigenvalues.map(Float::sqrt))
+			* eigen.eigenvectors.transpose();
+		let calibration = MagCalibration {
+			offset: mean + center * scale,
+			soft_iron: sqrt_m * radius,
+		};
+		let field_strength = radius * scale;
+
+		let mut squared_error = 0.0;
+		let mut octants = 0u8;
+		for m in samples {
+			let corrected = calibration.apply(*m);
+			let error = corrected.norm() / field_strength - 1.0;
+			squared_error += error * error;
+			let octant = (corrected.x > 0.0) as u8
+				| ((corrected.y > 0.0) as u8) << 1
+				| ((corrected.z > 0.0) as u8) << 2;
+			octants |= 1 << octant;
+		}
+		Ok(MagFit {
+			calibration,
+			quality: FitQuality {
+				rms_error: Float::sqrt(squared_error / n),
+				coverage: octants.count_ones() as f32 / 8.0,
+				field_strength,
+				samples: samples.len(),
+			},
+		})
+	}
+}
+impl<const N: usize> Default for MagCalibrator<N> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Quat;
+
+	/// Field directions spread over the whole sphere.
+	fn directions(count: usize) -> impl Iterator<Item = Vec3> {
+		// a spiral from pole to pole
+		let golden_angle = PI * (3.0 - 5f32.sqrt());
+		(0..count).map(move |i| {
+			let z = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+			let r = (1.0 - z * z).sqrt();
+			let phi = golden_angle * i as f32;
+			Vec3::new(r * phi.cos(), r * phi.sin(), z)
+		})
+	}
+
+	fn distort(field: Vec3) -> Vec3 {
+		let soft_iron = Mat3x3::new(1.2, 0.1, 0.0, 0.1, 0.9, -0.05, 0.0, -0.05, 1.0);
+		soft_iron * field + Vec3::new(15.0, -8.0, 30.0)
+	}
+
+	#[test]
+	fn test_fit() {
+		let mut calibrator = MagCalibrator::<200>::new();
+		let gyr = Vec3::new(0.0, 0.0, 1.0);
+		for (i, direction) in directions(200).enumerate() {
+			// a little noise, deterministically
+			let noise = 0.2 * ((i as f32 * 12.9898).sin() * 43758.547).fract();
+			assert!(calibrator
+				.add_sample(distort(direction * 50.0) + Vec3::repeat(noise), gyr));
+		}
+		let fit = calibrator.fit().unwrap();
+		assert!(fit.quality.rms_error < 0.005, "{:?}", fit.quality);
+		assert_eq!(fit.quality.coverage, 1.0);
+		assert_eq!(fit.quality.samples, 200);
+		assert!((fit.calibration.offset - Vec3::new(15.0, -8.0, 30.0)).norm() < 0.5);
+
+		// the corrected field has the same strength in every direction, and the angles
+		// between directions are kept
+		let a = fit.calibration.apply(distort(Vec3::x() * 50.0));
+		let b = fit.calibration.apply(distort(Vec3::y() * 50.0));
+		assert!((a.norm() / b.norm() - 1.0).abs() < 0.01);
+		assert!((a.angle(&b) - PI / 2.0).abs() < 0.01);
+	}
+
+	#[test]
+	fn test_calibrated_heading() {
+		let mut calibrator = MagCalibrator::<100>::new();
+		for direction in directions(100) {
+			calibrator.add_sample(distort(direction * 50.0), Vec3::x());
+		}
+		let calibration = calibrator.fit().unwrap().calibration;
+
+		// the heading measured by a level sensor follows its rotation about the vertical
+		// axis. The soft iron distortion bends it without the calibration.
+		let earth = Vec3::new(0.0, 20.0, -40.0);
+		let heading = |q: Quat, cal: &MagCalibration| {
+			let mag = cal.apply(distort(q.inverse() * earth));
+			mag.x.atan2(mag.y)
+		};
+		let start = heading(Quat::identity(), &calibration);
+		let turned = Quat::from_axis_angle(&Vec3::z_axis(), 1.0);
+		let delta = heading(turned, &calibration) - start;
+		assert!((delta - 1.0).abs() < 0.05, "{delta}");
+		let uncalibrated = heading(turned, &MagCalibration::identity())
+			- heading(Quat::identity(), &MagCalibration::identity());
+		assert!((uncalibrated - 1.0).abs() > 0.05);
+	}
+
+	#[test]
+	fn test_sample_selection() {
+		let mut calibrator = MagCalibrator::<4>::new();
+		let mag = Vec3::new(20.0, 0.0, -40.0);
+		let gyr = Vec3::new(1.0, 0.0, 0.0);
+		// at rest
+		assert!(!calibrator.add_sample(mag, Vec3::zeros()));
+		assert!(calibrator.add_sample(mag, gyr));
+		// too close to the previous sample
+		assert!(!calibrator.add_sample(mag + Vec3::repeat(0.1), gyr));
+		for i in 1..=5 {
+			assert!(calibrator.add_sample(mag + Vec3::x() * 10.0 * i as f32, gyr));
+		}
+		assert_eq!(calibrator.samples().len(), 4);
+		// the oldest ones were replaced
+		assert!(!calibrator.samples().contains(&mag));
+
+		assert_eq!(
+			calibrator.fit(),
+			Err(FitError::TooFewSamples {
+				needed: MagCalibrator::<4>::MIN_SAMPLES,
+				actual: 4
+			})
+		);
+		calibrator.clear();
+		assert!(calibrator.samples().is_empty());
+	}
+
+	#[test]
+	fn test_not_an_ellipsoid() {
+		// only turned about the z axis, so all samples lie on a circle
+		let mut calibrator = MagCalibrator::<24>::new();
+		for i in 0..24 {
+			let angle = i as f32 * 2.0 * PI / 24.0;
+			let mag = Vec3::new(30.0 * angle.cos(), 30.0 * angle.sin(), -40.0);
+			assert!(calibrator.add_sample(mag, Vec3::z()));
+		}
+		assert_eq!(calibrator.fit(), Err(FitError::NotAnEllipsoid));
+	}
+}
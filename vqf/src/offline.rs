@@ -0,0 +1,144 @@
+//! Offline processing of recorded data, which also uses future samples to estimate the
+//! gyroscope bias.
+
+use crate::{bias_sigma, Mat3x3, Vec3, Vqf, VqfOutput, VqfParameters};
+
+use alloc::vec::Vec;
+
+/// Estimates the orientation for a whole recording, sampled every `ts` seconds.
+///
+/// Unlike [`Vqf::update_batch()`], this is not causal. The bias is estimated by running
+/// the filter both forward and backward in time, and the two estimates are combined
+/// according to their covariances. Both passes start from the same prior, so it is
+/// only counted once. So the bias is known from the very first sample,
+/// even if it is only learned later in the recording. The orientation then comes from
+/// a forward pass that uses the combined bias.
+pub fn offline_vqf(
+	gyr: &[Vec3],
+	acc: &[Vec3],
+	mag: Option<&[Vec3]>,
+	ts: f32,
+	params: VqfParameters,
+) -> Vec<VqfOutput> {
+	assert_eq!(gyr.len(), acc.len());
+	if let Some(mag) = mag {
+		assert_eq!(gyr.len(), mag.len());
+	}
+	let mag_at = |i: usize| mag.map(|mag| mag[i]);
+
+	let mut forward = Vec::with_capacity(gyr.len());
+	let mut vqf = Vqf::new(ts, ts, ts, params.clone());
+	for i in 0..gyr.len() {
+		vqf.update(gyr[i], acc[i], mag_at(i));
+		forward.push((vqf.state.bias, vqf.state.bias_p));
+	}
+
+	// Running backward in time negates the angular velocity, and so the bias.
+	let mut backward = Vec::with_capacity(gyr.len());
+	let mut vqf = Vqf::new(ts, ts, ts, params.clone());
+	for i in (0..gyr.len()).rev() {
+		vqf.update(-gyr[i], acc[i], mag_at(i));
+		backward.push((-vqf.state.bias, vqf.state.bias_p));
+	}
+	backward.reverse();
+
+	let mut vqf = Vqf::new(ts, ts, ts, params);
+	let p0 = vqf.coeffs.bias_p0;
+	let mut outputs = Vec::with_capacity(gyr.len());
+	for (i, (forward, backward)) in forward.iter().zip(&backward).enumerate() {
+		let (bias, p) = fuse(forward, backward, p0);
+		vqf.state.bias = bias;
+		vqf.update(gyr[i], acc[i], mag_at(i));
+		outputs.push(VqfOutput {
+			bias,
+			bias_sigma: bias_sigma(&p, vqf.coeffs.bias_p0),
+			..vqf.output()
+		});
+	}
+	outputs
+}
+
+/// Combines the estimates of the forward and backward passes, each a mean and a
+/// covariance. Both of them include the prior of a zero bias with a variance of `p0`,
+/// so its information is subtracted once, as in a two-filter smoother.
+fn fuse(a: &(Vec3, Mat3x3), b: &(Vec3, Mat3x3), p0: f32) -> (Vec3, Mat3x3) {
+	let ((a, pa), (b, pb)) = (a, b);
+	let (Some(info_a), Some(info_b)) = (pa.try_inverse(), pb.try_inverse()) else {
+		return ((a + b) / 2.0, (pa + pb) / 4.0);
+	};
+	let info = info_a + info_b - Mat3x3::identity() / p0;
+	// The covariance can grow a little past `p0` while there is no measurement, which
+	// leaves less information than the prior. Then the prior is counted twice instead.
+	let info = if info.cholesky().is_some() {
+		info
+	} else {
+		info_a + info_b
+	};
+	match info.try_inverse() {
+		Some(p) => (p * (info_a * a + info_b * b), p),
+		None => ((a + b) / 2.0, (pa + pb) / 4.0),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Quat;
+
+	#[test]
+	fn test_fuse() {
+		let p0 = 2500.0;
+		let prior = (Vec3::zeros(), Mat3x3::identity() * p0);
+		let a = (
+			Vec3::new(0.01, -0.02, 0.015),
+			Mat3x3::from_diagonal(&Vec3::new(10.0, 20.0, 2000.0)),
+		);
+		// A pass that learned nothing leaves the other one as it is.
+		let (bias, p) = fuse(&a, &prior, p0);
+		assert!((bias - a.0).norm() < 1e-6);
+		assert!((p - a.1).norm() < 1e-2);
+		let (bias, p) = fuse(&prior, &prior, p0);
+		assert_eq!(bias, Vec3::zeros());
+		assert!((p - prior.1).norm() < 1e-2);
+
+		// Two passes that learned as much as each other end up with about half the
+		// variance.
+		let (_bias, p) = fuse(&a, &a, p0);
+		let expected = 1.0 / (2.0 / 10.0 - 1.0 / p0);
+		assert!((p[(0, 0)] - expected).abs() < 1e-3);
+	}
+
+	#[test]
+	fn test_offline() {
+		// Turning around the vertical axis for 5 s, then lying still for 20 s.
+		let bias = Vec3::new(0.01, -0.02, 0.015);
+		let gyr: Vec<_> = (0..2500)
+			.map(|i| {
+				if i < 500 {
+					Vec3::new(0.0, 0.0, 0.3) + bias
+				} else {
+					bias
+				}
+			})
+			.collect();
+		let acc = [Vec3::new(0.0, 0.0, 9.81); 2500];
+
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		let causal: Vec<_> = vqf.update_batch(&gyr, &acc, None).collect();
+		let offline = offline_vqf(&gyr, &acc, None, 0.01, VqfParameters::default());
+		assert_eq!(offline.len(), 2500);
+
+		// The causal filter only learns the bias once still, but the offline one
+		// already knows it at the start.
+		assert!((causal[100].bias - bias).norm() > 0.02);
+		assert!((offline[100].bias - bias).norm() < 1e-3);
+		assert!(offline[100].bias_sigma < causal[100].bias_sigma);
+		assert!((offline[2499].bias - bias).norm() < 1e-3);
+
+		// So the heading does not drift while turning.
+		let expected = Quat::from_axis_angle(&Vec3::z_axis(), 0.3 * 5.0);
+		let error = |output: &VqfOutput| output.quat6d.angle_to(&expected);
+		assert!(error(&offline[499]) < 5e-3);
+		assert!(error(&offline[499]) < error(&causal[499]));
+	}
+}
@@ -0,0 +1,294 @@
+//! The tuning parameters of the filter, and their validation.
+
+use core::fmt;
+
+/// The tuning parameters of [`crate::Vqf`]. The defaults work well for most IMUs.
+///
+/// Either set the fields directly, or use [`VqfParameters::builder()`], which also
+/// validates them.
+#[derive(Debug, Clone)]
+pub struct VqfParameters {
+	/// The time constant of the accelerometer low-pass filter, in seconds. Smaller
+	/// values correct the inclination more strongly, but let through more disturbances.
+	pub tau_acc: f32,
+	/// The time constant of the magnetometer heading correction, in seconds. A negative
+	/// value turns the heading correction off.
+	pub tau_mag: f32,
+	/// Enables gyroscope bias estimation while the sensor moves.
+	/// Has no effect without the `motion-bias-est` feature, which is on by default.
+	pub motion_bias_est_enabled: bool,
+	/// Enables rest detection, and gyroscope bias estimation while at rest.
+	pub rest_bias_est_enabled: bool,
+	/// Enables magnetic disturbance detection and rejection.
+	pub mag_dist_rejection_enabled: bool,
+	/// The standard deviation of the initial bias estimate, in °/s.
+	pub bias_sigma_init: f32,
+	/// The time in which the uncertainty of the bias estimate grows from 0 °/s to
+	/// 0.1 °/s, in seconds.
+	pub bias_forgetting_time: f32,
+	/// The largest expected gyroscope bias, in °/s.
+	pub bias_clip: f32,
+	/// The standard deviation of the converged bias estimate while moving, in °/s.
+	pub bias_sigma_motion: f32,
+	/// How quickly the bias around the vertical axis, which is unobservable while
+	/// moving, is forgotten. Relative to the other axes.
+	pub bias_vertical_forgetting_factor: f32,
+	/// The standard deviation of the converged bias estimate at rest, in °/s.
+	pub bias_sigma_rest: f32,
+	/// How long the sensor must be still before rest is detected, in seconds.
+	pub rest_min_t: f32,
+	/// The time constant of the low-pass filters used for rest detection, in seconds.
+	pub rest_filter_tau: f32,
+	/// The angular velocity threshold for rest detection, in °/s.
+	pub rest_th_gyr: f32,
+	/// The acceleration threshold for rest detection, in m/s².
+	pub rest_th_acc: f32,
+	/// The time constant of the filter on the current magnetic field norm and dip, in
+	/// seconds. Zero or a negative value turns the filter off.
+	pub mag_current_tau: f32,
+	/// The time constant with which the reference magnetic field adapts, in seconds.
+	pub mag_ref_tau: f32,
+	/// The threshold for the magnetic field norm, relative to the reference norm, above
+	/// which the field is disturbed.
+	pub mag_norm_th: f32,
+	/// The threshold for the magnetic field dip angle, in degrees, above which the
+	/// field is disturbed.
+	pub mag_dip_th: f32,
+	/// How long a new homogeneous magnetic field must be seen before it becomes the
+	/// reference, in seconds.
+	pub mag_new_time: f32,
+	/// Like [`Self::mag_new_time`], but for the first field, when there is no reference
+	/// yet.
+	pub mag_new_first_time: f32,
+	/// The angular velocity needed to count time towards accepting a new magnetic
+	/// field, in °/s.
+	pub mag_new_min_gyr: f32,
+	/// How long the field must be within the thresholds before it is undisturbed
+	/// again, in seconds.
+	pub mag_min_undisturbed_time: f32,
+	/// How long a magnetic disturbance is fully rejected, in seconds.
+	pub mag_max_rejection_time: f32,
+	/// How much the heading correction is slowed down after
+	/// [`Self::mag_max_rejection_time`].
+	pub mag_rejection_factor: f32,
+}
+
+impl Default for VqfParameters {
+	fn default() -> Self {
+		VqfParameters {
+			tau_acc: 3.0,
+			tau_mag: 9.0,
+			motion_bias_est_enabled: true,
+			rest_bias_est_enabled: true,
+			mag_dist_rejection_enabled: true,
+			bias_sigma_init: 0.5,
+			bias_forgetting_time: 100.0,
+			bias_clip: 2.0,
+			bias_sigma_motion: 0.1,
+			bias_vertical_forgetting_factor: 0.0001,
+			bias_sigma_rest: 0.03,
+			rest_min_t: 1.5,
+			rest_filter_tau: 0.5,
+			rest_th_gyr: 2.0,
+			rest_th_acc: 0.5,
+			mag_current_tau: 0.05,
+			mag_ref_tau: 20.0,
+			mag_norm_th: 0.1,
+			mag_dip_th: 10.0,
+			mag_new_time: 20.0,
+			mag_new_first_time: 5.0,
+			mag_new_min_gyr: 20.0,
+			mag_min_undisturbed_time: 0.5,
+			mag_max_rejection_time: 60.0,
+			mag_rejection_factor: 2.0,
+		}
+	}
+}
+
+impl VqfParameters {
+	/// A builder that starts out with the default parameters.
+	pub fn builder() -> VqfParametersBuilder {
+		VqfParametersBuilder::default()
+	}
+
+	/// Checks that the parameters are in range.
+	pub fn validate(&self) -> Result<(), ParamError> {
+		for (name, value) in [
+			("tau_acc", self.tau_acc),
+			("bias_sigma_init", self.bias_sigma_init),
+			("bias_forgetting_time", self.bias_forgetting_time),
+			("bias_clip", self.bias_clip),
+			("bias_sigma_motion", self.bias_sigma_motion),
+			("bias_sigma_rest", self.bias_sigma_rest),
+			("rest_filter_tau", self.rest_filter_tau),
+			("rest_th_gyr", self.rest_th_gyr),
+			("rest_th_acc", self.rest_th_acc),
+			("mag_norm_th", self.mag_norm_th),
+			("mag_dip_th", self.mag_dip_th),
+			("mag_rejection_factor", self.mag_rejection_factor),
+		] {
+			check_positive(name, value)?;
+		}
+		for (name, value) in [
+			(
+				"bias_vertical_forgetting_factor",
+				self.bias_vertical_forgetting_factor,
+			),
+			("rest_min_t", self.rest_min_t),
+			("mag_new_time", self.mag_new_time),
+			("mag_new_first_time", self.mag_new_first_time),
+			("mag_new_min_gyr", self.mag_new_min_gyr),
+			("mag_min_undisturbed_time", self.mag_min_undisturbed_time),
+			("mag_max_rejection_time", self.mag_max_rejection_time),
+		] {
+			check_finite(name, value)?;
+			if value < 0.0 {
+				return Err(ParamError::Negative { name, value });
+			}
+		}
+		for (name, value) in [
+			("tau_mag", self.tau_mag),
+			("mag_current_tau", self.mag_current_tau),
+			("mag_ref_tau", self.mag_ref_tau),
+		] {
+			check_finite(name, value)?;
+		}
+		Ok(())
+	}
+}
+
+pub(crate) fn check_positive(name: &'static str, value: f32) -> Result<(), ParamError> {
+	check_finite(name, value)?;
+	if value <= 0.0 {
+		return Err(ParamError::NotPositive { name, value });
+	}
+	Ok(())
+}
+
+fn check_finite(name: &'static str, value: f32) -> Result<(), ParamError> {
+	if !value.is_finite() {
+		return Err(ParamError::NotFinite { name, value });
+	}
+	Ok(())
+}
+
+/// Builder for [`VqfParameters`].
+#[derive(Debug, Clone, Default)]
+pub struct VqfParametersBuilder {
+	params: VqfParameters,
+}
+
+macro_rules! setters {
+	($($name:ident: $ty:ty),* $(,)?) => {
+		$(
+			#[doc = concat!("Sets [`VqfParameters::", stringify!($name), "`].")]
+			pub fn $name(mut self, $name: $ty) -> Self {
+				self.params.$name = $name;
+				self
+			}
+		)*
+	};
+}
+
+impl VqfParametersBuilder {
+	setters!(
+		tau_acc: f32,
+		tau_mag: f32,
+		motion_bias_est_enabled: bool,
+		rest_bias_est_enabled: bool,
+		mag_dist_rejection_enabled: bool,
+		bias_sigma_init: f32,
+		bias_forgetting_time: f32,
+		bias_clip: f32,
+		bias_sigma_motion: f32,
+		bias_vertical_forgetting_factor: f32,
+		bias_sigma_rest: f32,
+		rest_min_t: f32,
+		rest_filter_tau: f32,
+		rest_th_gyr: f32,
+		rest_th_acc: f32,
+		mag_current_tau: f32,
+		mag_ref_tau: f32,
+		mag_norm_th: f32,
+		mag_dip_th: f32,
+		mag_new_time: f32,
+		mag_new_first_time: f32,
+		mag_new_min_gyr: f32,
+		mag_min_undisturbed_time: f32,
+		mag_max_rejection_time: f32,
+		mag_rejection_factor: f32,
+	);
+
+	/// Validates the parameters, and returns them.
+	pub fn build(self) -> Result<VqfParameters, ParamError> {
+		self.params.validate()?;
+		Ok(self.params)
+	}
+}
+
+/// An invalid parameter or sampling time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamError {
+	/// The parameter must be greater than zero.
+	NotPositive { name: &'static str, value: f32 },
+	/// The parameter must not be negative.
+	Negative { name: &'static str, value: f32 },
+	/// The parameter must not be NaN or infinite.
+	NotFinite { name: &'static str, value: f32 },
+}
+impl fmt::Display for ParamError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ParamError::NotPositive { name, value } => {
+				write!(f, "{name} must be greater than zero, but is {value}")
+			}
+			ParamError::Negative { name, value } => {
+				write!(f, "{name} must not be negative, but is {value}")
+			}
+			ParamError::NotFinite { name, value } => {
+				write!(f, "{name} must be finite, but is {value}")
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_builder() {
+		let params = VqfParameters::builder()
+			.tau_acc(1.0)
+			.mag_dist_rejection_enabled(false)
+			.build()
+			.unwrap();
+		assert_eq!(params.tau_acc, 1.0);
+		assert!(!params.mag_dist_rejection_enabled);
+		assert_eq!(params.tau_mag, VqfParameters::default().tau_mag);
+
+		assert_eq!(
+			VqfParameters::builder()
+				.rest_filter_tau(0.0)
+				.build()
+				.unwrap_err(),
+			ParamError::NotPositive {
+				name: "rest_filter_tau",
+				value: 0.0
+			}
+		);
+		assert_eq!(
+			VqfParameters::builder()
+				.rest_min_t(-1.0)
+				.build()
+				.unwrap_err(),
+			ParamError::Negative {
+				name: "rest_min_t",
+				value: -1.0
+			}
+		);
+		// A negative time constant turns the heading correction off.
+		assert!(VqfParameters::builder().tau_mag(-1.0).build().is_ok());
+		assert!(VqfParameters::builder().tau_mag(f32::NAN).build().is_err());
+	}
+}
@@ -0,0 +1,341 @@
+//! Serialization of the filter state to a compact, fixed-size byte format, so that it
+//! can be stored across reboots.
+//!
+//! Every format starts with a version byte, followed by little-endian `f32`s, and
+//! booleans as single bytes. Matrices are stored in column-major order.
+
+use crate::{Mat3x3, Quat, Vec3, Vqf, VqfParameters, VqfState};
+
+use core::fmt;
+use nalgebra::{Quaternion, SMatrix, Vector4};
+
+/// What the filter has learned about the sensor and its surroundings, which stays
+/// valid after a reboot: the gyroscope bias and the magnetic field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SavedState {
+	/// The gyroscope bias estimate, in rad/s.
+	pub bias: Vec3,
+	/// The covariance of the bias estimate, in the units of [`VqfState::bias_p`].
+	pub bias_p: Mat3x3,
+	/// The norm of the reference magnetic field. Zero if there is no reference yet.
+	pub mag_ref_norm: f32,
+	/// The dip angle of the reference magnetic field, in radians.
+	pub mag_ref_dip: f32,
+}
+impl SavedState {
+	pub const VERSION: u8 = 1;
+	/// The length of the serialized state, in bytes.
+	pub const LEN: usize = 1 + 4 * (3 + 9 + 2);
+
+	pub fn to_bytes(&self) -> [u8; Self::LEN] {
+		let mut bytes = [0; Self::LEN];
+		let mut w = Writer::new(&mut bytes, Self::VERSION);
+		w.f32s(self.bias.as_slice());
+		w.f32s(self.bias_p.as_slice());
+		w.f32s(&[self.mag_ref_norm, self.mag_ref_dip]);
+		debug_assert_eq!(w.pos, Self::LEN);
+		bytes
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, StateError> {
+		let mut r = Reader::new(bytes, Self::VERSION, Self::LEN)?;
+		Ok(Self {
+			bias: r.f32s().into(),
+			bias_p: r.matrix(),
+			mag_ref_norm: r.f32(),
+			mag_ref_dip: r.f32(),
+		})
+	}
+}
+
+impl VqfState {
+	pub const VERSION: u8 = 1;
+	/// The length of the serialized state, in bytes.
+	pub const LEN: usize = 1 + 4 * 89 + 2;
+
+	pub fn to_bytes(&self) -> [u8; Self::LEN] {
+		let mut bytes = [0; Self::LEN];
+		let mut w = Writer::new(&mut bytes, Self::VERSION);
+		w.f32s(self.gyr_quat.coords.as_slice());
+		w.f32s(self.acc_quat.coords.as_slice());
+		w.f32s(&[self.delta]);
+		w.bool(self.rest_detected);
+		w.bool(self.mag_dist_detected);
+		w.f32s(self.last_acc_lp.as_slice());
+		w.f32s(self.acc_lp_state.as_slice());
+		w.f32s(&[
+			self.k_mag_init,
+			self.last_mag_dis_angle,
+			self.last_mag_corr_angular_rate,
+		]);
+		w.f32s(self.bias.as_slice());
+		w.f32s(self.bias_p.as_slice());
+		w.f32s(self.motion_bias_est_rlp_state.as_slice());
+		w.f32s(self.motion_bias_est_bias_lp_state.as_slice());
+		w.f32s(self.rest_last_squared_deviations.as_slice());
+		w.f32s(&[self.rest_t]);
+		w.f32s(self.rest_last_gyr_lp.as_slice());
+		w.f32s(self.rest_gyr_lp_state.as_slice());
+		w.f32s(self.rest_last_acc_lp.as_slice());
+		w.f32s(self.rest_acc_lp_state.as_slice());
+		w.f32s(&[
+			self.mag_ref_norm,
+			self.mag_ref_dip,
+			self.mag_undisturbed_t,
+			self.mag_reject_t,
+			self.mag_candidate_norm,
+			self.mag_candidate_dip,
+			self.mag_candidate_t,
+		]);
+		w.f32s(self.mag_norm_dip.as_slice());
+		w.f32s(self.mag_norm_dip_lp_state.as_slice());
+		debug_assert_eq!(w.pos, Self::LEN);
+		bytes
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, StateError> {
+		let mut r = Reader::new(bytes, Self::VERSION, Self::LEN)?;
+		let quat = |coords: [f32; 4]| {
+			Quat::new_unchecked(Quaternion::from(Vector4::from(coords)))
+		};
+		Ok(Self {
+			gyr_quat: quat(r.f32s()),
+			acc_quat: quat(r.f32s()),
+			delta: r.f32(),
+			rest_detected: r.bool(),
+			mag_dist_detected: r.bool(),
+			last_acc_lp: r.f32s().into(),
+			acc_lp_state: r.matrix(),
+			k_mag_init: r.f32(),
+			last_mag_dis_angle: r.f32(),
+			last_mag_corr_angular_rate: r.f32(),
+			bias: r.f32s().into(),
+			bias_p: r.matrix(),
+			motion_bias_est_rlp_state: r.matrix(),
+			motion_bias_est_bias_lp_state: r.matrix(),
+			rest_last_squared_deviations: r.f32s().into(),
+			rest_t: r.f32(),
+			rest_last_gyr_lp: r.f32s().into(),
+			rest_gyr_lp_state: r.matrix(),
+			rest_last_acc_lp: r.f32s().into(),
+			rest_acc_lp_state: r.matrix(),
+			mag_ref_norm: r.f32(),
+			mag_ref_dip: r.f32(),
+			mag_undisturbed_t: r.f32(),
+			mag_reject_t: r.f32(),
+			mag_candidate_norm: r.f32(),
+			mag_candidate_dip: r.f32(),
+			mag_candidate_t: r.f32(),
+			mag_norm_dip: r.f32s().into(),
+			mag_norm_dip_lp_state: r.matrix(),
+		})
+	}
+}
+
+impl Vqf {
+	/// Creates a filter that starts out with what was learned before, so that it is
+	/// accurate right away instead of after the bias has converged again.
+	pub fn from_saved_state(
+		gyr_ts: f32,
+		acc_ts: f32,
+		mag_ts: f32,
+		params: VqfParameters,
+		saved: &SavedState,
+	) -> Vqf {
+		let mut vqf = Vqf::new(gyr_ts, acc_ts, mag_ts, params);
+		vqf.state.bias = saved.bias;
+		vqf.state.bias_p = saved.bias_p;
+		vqf.set_mag_ref(saved.mag_ref_norm, saved.mag_ref_dip);
+		vqf
+	}
+
+	/// What the filter has learned so far, to be restored with
+	/// [`Vqf::from_saved_state()`].
+	pub fn saved_state(&self) -> SavedState {
+		SavedState {
+			bias: self.state.bias,
+			bias_p: self.state.bias_p,
+			mag_ref_norm: self.state.mag_ref_norm,
+			mag_ref_dip: self.state.mag_ref_dip,
+		}
+	}
+
+	/// The complete state of the filter.
+	pub fn state(&self) -> &VqfState {
+		&self.state
+	}
+
+	/// Overwrites the complete state of the filter, for example with one from
+	/// [`VqfState::from_bytes()`]. The parameters and sampling times must be the same
+	/// as when it was saved.
+	pub fn set_state(&mut self, state: VqfState) {
+		self.state = state;
+	}
+}
+
+/// An error when deserializing a state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+	/// The state was saved in a different format version.
+	Version { expected: u8, actual: u8 },
+	/// The state has the wrong length.
+	Length { expected: usize, actual: usize },
+}
+impl fmt::Display for StateError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			StateError::Version { expected, actual } => write!(
+				f,
+				"State has format version {actual}, but version {expected} was expected"
+			),
+			StateError::Length { expected, actual } => write!(
+				f,
+				"State is {actual} bytes long, but {expected} bytes were expected"
+			),
+		}
+	}
+}
+
+struct Writer<'a> {
+	bytes: &'a mut [u8],
+	pos: usize,
+}
+impl<'a> Writer<'a> {
+	fn new(bytes: &'a mut [u8], version: u8) -> Self {
+		bytes[0] = version;
+		Self { bytes, pos: 1 }
+	}
+
+	fn f32s(&mut self, values: &[f32]) {
+		for v in values {
+			self.bytes[self.pos..self.pos + 4].copy_from_slice(&v.to_le_bytes());
+			self.pos += 4;
+		}
+	}
+
+	fn bool(&mut self, value: bool) {
+		self.bytes[self.pos] = value as u8;
+		self.pos += 1;
+	}
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+}
+impl<'a> Reader<'a> {
+	fn new(bytes: &'a [u8], version: u8, len: usize) -> Result<Self, StateError> {
+		if bytes.len() != len {
+			return Err(StateError::Length {
+				expected: len,
+				actual: bytes.len(),
+			});
+		}
+		if bytes[0] != version {
+			return Err(StateError::Version {
+				expected: version,
+				actual: bytes[0],
+			});
+		}
+		Ok(Self { bytes, pos: 1 })
+	}
+
+	fn f32(&mut self) -> f32 {
+		let [v] = self.f32s();
+		v
+	}
+
+	fn f32s<const N: usize>(&mut self) -> [f32; N] {
+		core::array::from_fn(|_| {
+			let bytes = self.bytes[self.pos..self.pos + 4].try_into().unwrap();
+			self.pos += 4;
+			f32::from_le_bytes(bytes)
+		})
+	}
+
+	fn matrix<const R: usize, const C: usize>(&mut self) -> SMatrix<f32, R, C> {
+		let mut m = SMatrix::<f32, R, C>::zeros();
+		for v in m.as_mut_slice() {
+			*v = self.f32();
+		}
+		m
+	}
+
+	fn bool(&mut self) -> bool {
+		let v = self.bytes[self.pos] != 0;
+		self.pos += 1;
+		v
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn run(vqf: &mut Vqf, samples: usize) {
+		for i in 0..samples {
+			let t = i as f32 / 100.0;
+			let gyr = Vec3::new(0.01 + (t * 3.0).sin(), -0.02, 0.5 * t.cos());
+			let acc = vqf.quat_6d().inverse() * Vec3::new(0.0, 0.0, 9.81);
+			let mag = vqf.quat_6d().inverse() * Vec3::new(0.0, 20.0, -40.0);
+			vqf.update(gyr, acc, Some(mag));
+		}
+	}
+
+	#[test]
+	fn test_state_round_trip() {
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		run(&mut vqf, 500);
+		let bytes = vqf.state().to_bytes();
+		let mut restored = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		restored.set_state(VqfState::from_bytes(&bytes).unwrap());
+		assert_eq!(restored.state().to_bytes(), bytes);
+
+		// Both continue exactly the same way.
+		run(&mut vqf, 100);
+		run(&mut restored, 100);
+		assert_eq!(restored.quat_9d(), vqf.quat_9d());
+		assert_eq!(restored.bias_estimate(), vqf.bias_estimate());
+	}
+
+	#[test]
+	fn test_warm_start() {
+		let params = || VqfParameters::default();
+		let mut vqf = Vqf::new(0.01, 0.01, 0.01, params());
+		vqf.set_bias_estimate(Vec3::new(0.01, -0.02, 0.005), 0.001);
+		vqf.set_mag_ref(45.0, 1.1);
+		let bytes = vqf.saved_state().to_bytes();
+		assert_eq!(bytes.len(), 57);
+
+		let saved = SavedState::from_bytes(&bytes).unwrap();
+		assert_eq!(saved, vqf.saved_state());
+		let warm = Vqf::from_saved_state(0.01, 0.01, 0.01, params(), &saved);
+		assert_eq!(warm.bias_estimate(), vqf.bias_estimate());
+		assert_eq!(warm.mag_ref_norm(), 45.0);
+		assert_eq!(warm.mag_ref_dip(), 1.1);
+		// The orientation itself starts over.
+		assert_eq!(warm.quat_9d(), Quat::identity());
+	}
+
+	#[test]
+	fn test_errors() {
+		let vqf = Vqf::new(0.01, 0.01, 0.01, VqfParameters::default());
+		let mut bytes = vqf.saved_state().to_bytes();
+		assert_eq!(
+			SavedState::from_bytes(&bytes[1..]),
+			Err(StateError::Length {
+				expected: 57,
+				actual: 56
+			})
+		);
+		bytes[0] = 2;
+		assert_eq!(
+			SavedState::from_bytes(&bytes),
+			Err(StateError::Version {
+				expected: 1,
+				actual: 2
+			})
+		);
+		assert!(VqfState::from_bytes(&bytes).is_err());
+	}
+}
@@ -0,0 +1,43 @@
+//! Runs vqf-rs in `f64` with the default parameters. Reads the sampling time from the
+//! first line of stdin, then rows of `gyr,acc,mag`. Writes a row of outputs to stdout
+//! for each of them, in the order of the output columns of the fixtures.
+
+use std::io::{BufRead, Write};
+use vqf_rs::VQF;
+
+fn main() {
+	let stdin = std::io::stdin();
+	let mut lines = stdin.lock().lines().map(Result::unwrap);
+	let ts = lines.next().unwrap().trim().parse().unwrap();
+	let mut vqf = VQF::new(ts, None, None, None);
+	let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+	for line in lines {
+		let v: Vec<f64> = line.split(',').map(|x| x.parse().unwrap()).collect();
+		vqf.update(
+			[v[0], v[1], v[2]],
+			[v[3], v[4], v[5]],
+			Some([v[6], v[7], v[8]]),
+		);
+		let q6 = vqf.quat_6d();
+		let q9 = vqf.quat_9d();
+		let (bias, sigma) = vqf.bias_estimate();
+		let values = [
+			q6.0,
+			q6.1,
+			q6.2,
+			q6.3,
+			q9.0,
+			q9.1,
+			q9.2,
+			q9.3,
+			bias[0],
+			bias[1],
+			bias[2],
+			sigma,
+			vqf.rest_detected() as u8 as f64,
+			vqf.mag_dist_detected() as u8 as f64,
+		];
+		let values: Vec<_> = values.iter().map(|v| format!("{v:e}")).collect();
+		writeln!(out, "{}", values.join(",")).unwrap();
+	}
+}
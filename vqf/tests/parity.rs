@@ -0,0 +1,132 @@
+//! Compares [`Vqf`] against outputs of vqf-rs 0.3.0, a direct port of the reference
+//! implementation, for synthetic IMU data in `tests/fixtures`. See
+//! `tests/fixtures/generate.py` for how the fixtures are made.
+
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+use std::ffi::OsStr;
+use std::path::Path;
+use vqf::{Vqf, VqfParameters};
+
+/// The largest allowed angle between our orientation and the reference one, in
+/// radians. The reference runs in `f64`, so they can't match exactly.
+const QUAT_TOLERANCE: f32 = 1e-3;
+/// The largest allowed difference in the bias estimate and its standard deviation, in
+/// rad/s.
+const BIAS_TOLERANCE: f32 = 1e-4;
+/// The fraction of samples in which a flag may differ from the reference. Near the
+/// thresholds, rounding can flip a flag a sample earlier or later.
+const FLAG_TOLERANCE: f32 = 0.005;
+
+/// A fixture: the sampling time, the column names, and the rows of values.
+struct Fixture {
+	ts: f32,
+	columns: Vec<String>,
+	rows: Vec<Vec<f32>>,
+}
+impl Fixture {
+	fn load(path: &Path) -> Self {
+		let text = std::fs::read_to_string(path).unwrap();
+		let mut ts = None;
+		let mut lines = text.lines().filter(|l| {
+			if let Some(ts_str) = l.strip_prefix("# ts=") {
+				ts = Some(ts_str.trim().parse().unwrap());
+			}
+			!l.starts_with('#') && !l.is_empty()
+		});
+		let columns = lines.next().unwrap().split(',').map(String::from).collect();
+		let rows = lines
+			.map(|l| l.split(',').map(|v| v.parse().unwrap()).collect())
+			.collect();
+		Self {
+			ts: ts.expect("fixture has no sampling time"),
+			columns,
+			rows,
+		}
+	}
+
+	fn get(&self, row: &[f32], column: &str) -> f32 {
+		let idx = self.columns.iter().position(|c| c == column);
+		row[idx.unwrap_or_else(|| panic!("no column {column}"))]
+	}
+
+	fn vec3(&self, row: &[f32], prefix: &str) -> Vector3<f32> {
+		Vector3::from_fn(|i, _| {
+			self.get(row, &format!("{prefix}_{}", ["x", "y", "z"][i]))
+		})
+	}
+
+	fn quat(&self, row: &[f32], prefix: &str) -> UnitQuaternion<f32> {
+		let v = self.vec3(row, prefix);
+		UnitQuaternion::from_quaternion(Quaternion::from_parts(
+			self.get(row, &format!("{prefix}_w")),
+			v,
+		))
+	}
+}
+
+fn check_fixture(path: &Path) {
+	let fixture = Fixture::load(path);
+	let name = path.file_name().unwrap().to_string_lossy();
+	let ts = fixture.ts;
+	let mut vqf = Vqf::new(ts, ts, ts, VqfParameters::default());
+	let (mut rest_mismatches, mut mag_dist_mismatches) = (0, 0);
+	for (i, row) in fixture.rows.iter().enumerate() {
+		let mag = fixture.vec3(row, "mag");
+		let mag = (mag != Vector3::zeros()).then_some(mag);
+		vqf.update(fixture.vec3(row, "gyr"), fixture.vec3(row, "acc"), mag);
+
+		for (quat, prefix) in [(vqf.quat_6d(), "quat6d"), (vqf.quat_9d(), "quat9d")] {
+			let angle = quat.angle_to(&fixture.quat(row, prefix));
+			assert!(
+				angle < QUAT_TOLERANCE,
+				"{name}, sample {i}: {prefix} is off by {angle} rad"
+			);
+		}
+
+		let (bias, sigma) = vqf.bias_estimate();
+		let bias_error = (bias - fixture.vec3(row, "bias")).abs().max();
+		assert!(
+			bias_error < BIAS_TOLERANCE,
+			"{name}, sample {i}: bias is off by {bias_error} rad/s"
+		);
+		let sigma_error = (sigma - fixture.get(row, "bias_sigma")).abs();
+		assert!(
+			sigma_error < BIAS_TOLERANCE,
+			"{name}, sample {i}: bias sigma is off by {sigma_error} rad/s"
+		);
+
+		let flag = |column| fixture.get(row, column) != 0.;
+		rest_mismatches += (vqf.rest_detected() != flag("rest_detected")) as usize;
+		mag_dist_mismatches +=
+			(vqf.mag_dist_detected() != flag("mag_dist_detected")) as usize;
+	}
+
+	let allowed = (fixture.rows.len() as f32 * FLAG_TOLERANCE) as usize;
+	assert!(
+		rest_mismatches <= allowed,
+		"{name}: rest detection differs in {rest_mismatches} samples"
+	);
+	assert!(
+		mag_dist_mismatches <= allowed,
+		"{name}: magnetic disturbance detection differs in {mag_dist_mismatches} samples"
+	);
+}
+
+#[test]
+#[cfg_attr(
+	not(feature = "motion-bias-est"),
+	ignore = "the reference estimates the bias while moving"
+)]
+fn test_parity() {
+	let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+	let mut paths: Vec<_> = std::fs::read_dir(dir)
+		.unwrap()
+		.map(|e| e.unwrap().path())
+		.filter(|p| p.extension() == Some(OsStr::new("csv")))
+		.collect();
+	paths.sort();
+	assert!(!paths.is_empty(), "no fixtures, run generate.py first");
+	for path in paths {
+		check_fixture(&path);
+	}
+}